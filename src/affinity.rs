@@ -0,0 +1,128 @@
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(test)]
+mod tests;
+
+/// Set of CPUs, written as a list of CPU numbers and ranges such as `0-3,8`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct CpuSet(Vec<usize>);
+
+impl CpuSet {
+    pub(crate) fn cpus(&self) -> &[usize] {
+        &self.0
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn contains(&self, cpu: usize) -> bool {
+        self.0.contains(&cpu)
+    }
+
+    /// CPUs in this set that aren't in `other`
+    pub(crate) fn without(&self, other: &CpuSet) -> CpuSet {
+        CpuSet(
+            self.0
+                .iter()
+                .copied()
+                .filter(|&cpu| !other.contains(cpu))
+                .collect(),
+        )
+    }
+}
+
+impl FromStr for CpuSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |cpu: &str| {
+            cpu.trim()
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid CPU `{}`", cpu.trim()))
+        };
+
+        let mut cpus = Vec::new();
+        for part in s.split(',') {
+            match part.split_once('-') {
+                Some((first, last)) => {
+                    let (first, last) = (parse(first)?, parse(last)?);
+                    if first > last {
+                        return Err(format!("Invalid CPU range `{}`", part.trim()));
+                    }
+                    cpus.extend(first..=last);
+                }
+                None => cpus.push(parse(part)?),
+            }
+        }
+        cpus.sort_unstable();
+        cpus.dedup();
+
+        Ok(CpuSet(cpus))
+    }
+}
+
+impl fmt::Display for CpuSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cpus: Vec<_> = self.0.iter().map(ToString::to_string).collect();
+        write!(f, "{}", cpus.join(","))
+    }
+}
+
+/// CPUs the current thread is allowed to run on
+#[cfg(target_os = "linux")]
+pub(crate) fn current() -> Result<CpuSet, String> {
+    // SAFETY: `cpu_set_t` is plain data, and the size passed is its own
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return Err(format!(
+                "Couldn't get CPU affinity: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        let cpus = (0..libc::CPU_SETSIZE as usize)
+            .filter(|&cpu| libc::CPU_ISSET(cpu, &set))
+            .collect();
+        Ok(CpuSet(cpus))
+    }
+}
+
+/// Only let the current thread, and threads it starts from now on, run on
+/// `cpus`
+#[cfg(target_os = "linux")]
+pub(crate) fn pin_current_thread(cpus: &CpuSet) -> Result<(), String> {
+    // SAFETY: `cpu_set_t` is plain data, and the size passed is its own
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for &cpu in cpus.cpus() {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                return Err(format!("CPU {} is out of range", cpu));
+            }
+            libc::CPU_SET(cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(format!(
+                "Couldn't pin thread to CPUs {}: {}",
+                cpus,
+                std::io::Error::last_os_error()
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn current() -> Result<CpuSet, String> {
+    Err("CPU affinity is only supported on Linux".to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn pin_current_thread(_cpus: &CpuSet) -> Result<(), String> {
+    Err("CPU affinity is only supported on Linux".to_string())
+}
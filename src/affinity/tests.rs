@@ -0,0 +1,37 @@
+use super::*;
+
+#[test]
+fn cpu_set_should_parse_lists_and_ranges() {
+    let cpus: CpuSet = "4, 0-2,8,1".parse().unwrap();
+
+    assert_eq!(cpus.cpus(), &[0, 1, 2, 4, 8]);
+    assert_eq!(cpus.to_string(), "0,1,2,4,8");
+}
+
+#[test]
+fn invalid_cpu_set_should_be_rejected() {
+    assert!("".parse::<CpuSet>().is_err());
+    assert!("3-1".parse::<CpuSet>().is_err());
+    assert!("0,x".parse::<CpuSet>().is_err());
+}
+
+#[test]
+fn cpu_set_without_should_remove_cpus() {
+    let all: CpuSet = "0-3".parse().unwrap();
+    let compute: CpuSet = "1,3".parse().unwrap();
+
+    assert_eq!(all.without(&compute).cpus(), &[0, 2]);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn pinned_thread_should_only_run_on_its_cpus() {
+    std::thread::spawn(|| {
+        let first: CpuSet = current().unwrap().cpus()[0].to_string().parse().unwrap();
+        pin_current_thread(&first).unwrap();
+
+        assert_eq!(current().unwrap(), first);
+    })
+    .join()
+    .unwrap();
+}
@@ -0,0 +1,227 @@
+use futures::StreamExt;
+use lapin::message::Delivery;
+use lapin::options::{
+    BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicPublishOptions, BasicQosOptions,
+    QueueDeclareOptions,
+};
+use lapin::types::{AMQPValue, FieldTable, LongString, ShortString};
+use lapin::uri::AMQPUri;
+use lapin::{BasicProperties, Channel, Connection, ConnectionProperties};
+use std::convert::TryFrom;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::queue;
+use crate::reconnect::{self, Backoff};
+use crate::server::OptimizerConfig;
+use crate::Opt;
+
+/// Header with the HTTP status code equivalent of the result
+const STATUS_HEADER: &str = "Cut-Optimizer-Status";
+
+/// Consume optimizer inputs from an AMQP queue, publishing each solution to the
+/// results exchange with the input's correlation ID. Solutions are routed with
+/// the input's `reply_to`, or the results routing key if it has none.
+///
+/// The broker is reconnected to with jittered backoff when the connection
+/// fails, and when its host resolves to other addresses. Unacknowledged
+/// deliveries are redelivered by the broker.
+pub(crate) async fn serve(url: &str, opt: &Opt) {
+    let hosts = match url.parse::<AMQPUri>() {
+        Ok(uri) => vec![(uri.authority.host, uri.authority.port)],
+        Err(e) => {
+            error!("Invalid AMQP URL {}: {}", url, e);
+            return;
+        }
+    };
+    let refresh =
+        (opt.dns_refresh_seconds > 0).then(|| Duration::from_secs(opt.dns_refresh_seconds));
+
+    let config = OptimizerConfig::from(opt);
+    let mut backoff = Backoff::new(Duration::from_secs(opt.reconnect_max_seconds));
+
+    loop {
+        let addresses = reconnect::resolve(&hosts).await.unwrap_or_default();
+        match Connection::connect(url, ConnectionProperties::default()).await {
+            Ok(connection) => {
+                tokio::select! {
+                    () = deliver(&connection, opt, &config, &mut backoff) => {}
+                    () = reconnect::changed(&hosts, &addresses, refresh) => {}
+                }
+                info!("Reconnecting to AMQP broker at {}", url);
+            }
+            Err(e) => error!("Couldn't connect to AMQP broker at {}: {}", url, e),
+        }
+        backoff.wait().await;
+    }
+}
+
+/// Process deliveries from the queue until the connection fails
+async fn deliver(
+    connection: &Connection,
+    opt: &Opt,
+    config: &OptimizerConfig,
+    backoff: &mut Backoff,
+) {
+    let queue_name = opt.amqp_queue.clone();
+    let (channel, mut consumer) = match consume(connection, &queue_name, opt.max_requests).await {
+        Ok(consumer) => consumer,
+        Err(e) => {
+            error!("Couldn't consume from AMQP queue {}: {}", queue_name, e);
+            return;
+        }
+    };
+
+    info!("Consuming optimizer inputs from AMQP queue {}", queue_name);
+    backoff.reset();
+
+    while let Some(delivery) = consumer.next().await {
+        let delivery = match delivery {
+            Ok(delivery) => delivery,
+            Err(e) => {
+                error!("Couldn't receive from AMQP queue {}: {}", queue_name, e);
+                if connection.status().connected() {
+                    continue;
+                }
+                return;
+            }
+        };
+
+        let routing_key = match (
+            delivery.properties.reply_to(),
+            &opt.amqp_results_routing_key,
+        ) {
+            (Some(reply_to), _) => reply_to.to_string(),
+            (None, Some(routing_key)) => routing_key.clone(),
+            (None, None) => {
+                warn!(
+                    "Rejecting AMQP message on {} without a reply-to",
+                    queue_name
+                );
+                if let Err(e) = delivery.nack(BasicNackOptions::default()).await {
+                    error!("Couldn't reject AMQP message: {}", e);
+                }
+                continue;
+            }
+        };
+
+        // The broker delivers at most `max_requests` unacknowledged messages,
+        // which limits how many are processed at once
+        let channel = channel.clone();
+        let config = config.clone();
+        let exchange = opt.amqp_results_exchange.clone();
+
+        tokio::spawn(async move {
+            respond(&channel, &config, &exchange, &routing_key, delivery).await;
+        });
+    }
+}
+
+async fn consume(
+    connection: &Connection,
+    queue_name: &str,
+    max_requests: usize,
+) -> lapin::Result<(Channel, lapin::Consumer)> {
+    let channel = connection.create_channel().await?;
+    channel
+        .basic_qos(
+            u16::try_from(max_requests).unwrap_or(u16::MAX),
+            BasicQosOptions::default(),
+        )
+        .await?;
+    channel
+        .queue_declare(
+            queue_name,
+            QueueDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+    let consumer = channel
+        .basic_consume(
+            queue_name,
+            "cut-optimizer-2d-server",
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    Ok((channel, consumer))
+}
+
+/// Run the optimizer on a delivery and publish the solution. The delivery is
+/// acknowledged once the solution is published, or rejected if publishing
+/// fails.
+async fn respond(
+    channel: &Channel,
+    config: &OptimizerConfig,
+    exchange: &str,
+    routing_key: &str,
+    delivery: Delivery,
+) {
+    let content_type = delivery
+        .properties
+        .content_type()
+        .as_ref()
+        .map(ShortString::to_string);
+    let accept = delivery
+        .properties
+        .headers()
+        .as_ref()
+        .and_then(|headers| headers.inner().get("Accept"))
+        .and_then(|value| match value {
+            AMQPValue::LongString(value) => Some(value.to_string()),
+            AMQPValue::ShortString(value) => Some(value.to_string()),
+            _ => None,
+        });
+
+    let reply = queue::process(
+        config,
+        content_type.as_deref(),
+        accept.as_deref(),
+        &delivery.data,
+    )
+    .await;
+
+    let mut headers = FieldTable::default();
+    headers.insert(
+        STATUS_HEADER.into(),
+        AMQPValue::LongString(LongString::from(reply.status.as_str())),
+    );
+    let mut properties = BasicProperties::default()
+        .with_content_type(reply.content_type.into())
+        .with_headers(headers);
+    if let Some(correlation_id) = delivery.properties.correlation_id() {
+        properties = properties.with_correlation_id(correlation_id.clone());
+    }
+
+    let published = match channel
+        .basic_publish(
+            exchange,
+            routing_key,
+            BasicPublishOptions::default(),
+            &reply.body,
+            properties,
+        )
+        .await
+    {
+        Ok(confirm) => confirm.await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+
+    let acknowledged = match published {
+        Ok(()) => delivery.ack(BasicAckOptions::default()).await,
+        Err(e) => {
+            error!(
+                "Couldn't publish AMQP reply to {} on exchange {:?}: {}",
+                routing_key, exchange, e
+            );
+            delivery.nack(BasicNackOptions::default()).await
+        }
+    };
+    if let Err(e) = acknowledged {
+        error!("Couldn't acknowledge AMQP message: {}", e);
+    }
+}
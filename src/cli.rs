@@ -0,0 +1,103 @@
+use axum::Json;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+use crate::server::format::Format;
+use crate::server::{self, OptimizerConfig, OptimizerInput};
+use crate::Opt;
+
+#[cfg(test)]
+mod tests;
+
+/// Optimize the JSON input in `input`, or stdin, and write the JSON solution to
+/// `output`, or stdout, without starting any servers. The error returned is the
+/// same JSON error body the HTTP API would respond with.
+pub(crate) async fn optimize(
+    opt: &Opt,
+    input: Option<&Path>,
+    output: Option<&Path>,
+) -> Result<(), String> {
+    let bytes = match input {
+        Some(path) => {
+            std::fs::read(path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?
+        }
+        None => {
+            let mut bytes = Vec::new();
+            io::stdin()
+                .read_to_end(&mut bytes)
+                .map_err(|e| format!("Couldn't read stdin: {}", e))?;
+            bytes
+        }
+    };
+
+    let config = OptimizerConfig::from(opt);
+    let solution = match Format::Json.decode_request::<OptimizerInput>(&bytes) {
+        Ok(input) => server::run_optimizer(&config, input).await,
+        Err(e) => Err(e),
+    }
+    .map_err(|(_, Json(body))| body.to_string())?;
+
+    let mut json = serde_json::to_vec_pretty(&solution).map_err(|e| e.to_string())?;
+    json.push(b'\n');
+
+    match output {
+        Some(path) => std::fs::write(path, json)
+            .map_err(|e| format!("Couldn't write {}: {}", path.display(), e)),
+        None => io::stdout()
+            .write_all(&json)
+            .map_err(|e| format!("Couldn't write stdout: {}", e)),
+    }
+}
+
+/// Optimize newline-delimited JSON inputs from stdin, writing one line to
+/// stdout for each, in order, without starting any servers
+pub(crate) async fn stdio(opt: &Opt) -> io::Result<()> {
+    pipe(
+        &OptimizerConfig::from(opt),
+        BufReader::new(tokio::io::stdin()),
+        tokio::io::stdout(),
+    )
+    .await
+}
+
+/// Optimize each line of `reader` as a JSON input, writing the JSON solution,
+/// or the JSON error body the HTTP API would respond with, as a line of
+/// `writer`. Error bodies always have a `message`, which solutions never have.
+/// Blank lines are skipped.
+async fn pipe(
+    config: &OptimizerConfig,
+    reader: impl AsyncBufRead + Unpin,
+    mut writer: impl AsyncWrite + Unpin,
+) -> io::Result<()> {
+    let mut lines = reader.lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut json = optimize_json(config, line.as_bytes()).await?;
+        json.push(b'\n');
+
+        writer.write_all(&json).await?;
+        writer.flush().await?;
+    }
+
+    Ok(())
+}
+
+/// Optimize a JSON input, returning the JSON solution, or the JSON error body
+/// the HTTP API would respond with
+pub(crate) async fn optimize_json(
+    config: &OptimizerConfig,
+    bytes: &[u8],
+) -> serde_json::Result<Vec<u8>> {
+    let result = match Format::Json.decode_request::<OptimizerInput>(bytes) {
+        Ok(input) => server::run_optimizer(config, input).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(solution) => serde_json::to_vec(&solution),
+        Err((_, Json(body))) => serde_json::to_vec(&body),
+    }
+}
@@ -0,0 +1,71 @@
+use super::*;
+use serde_json::Value;
+use structopt::StructOpt;
+
+#[tokio::test]
+async fn optimize_should_write_solution_to_output() {
+    let dir = std::env::temp_dir().join(format!("cut-optimizer-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.json");
+    let output = dir.join("output.json");
+    std::fs::write(
+        &input,
+        r#"{
+            "method": "guillotine",
+            "cutWidth": 1,
+            "stockPieces": [{ "width": 48, "length": 96, "patternDirection": "none", "price": 0 }],
+            "cutPieces": [
+                { "externalId": 1, "width": 10, "length": 30, "patternDirection": "none", "canRotate": true }
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    let opt = Opt::from_iter(&["cut-optimizer-2d-server"]);
+    optimize(&opt, Some(&input), Some(&output)).await.unwrap();
+
+    let solution: Value = serde_json::from_slice(&std::fs::read(&output).unwrap()).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(solution["stockPieces"][0]["cutPieces"][0]["externalId"], 1);
+}
+
+#[tokio::test]
+async fn optimize_should_return_error_body_for_invalid_input() {
+    let dir = std::env::temp_dir().join(format!("cut-optimizer-cli-err-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.json");
+    std::fs::write(
+        &input,
+        r#"{ "method": "guillotine", "cutWidth": 1, "stockPieces": [], "cutPieces": [] }"#,
+    )
+    .unwrap();
+
+    let opt = Opt::from_iter(&["cut-optimizer-2d-server"]);
+    let error = optimize(&opt, Some(&input), None).await.unwrap_err();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let body: Value = serde_json::from_str(&error).unwrap();
+    assert_eq!(body["data"][0]["code"], "emptyStockPieces");
+}
+
+#[tokio::test]
+async fn pipe_should_write_a_line_for_each_input_line() {
+    let input = concat!(
+        r#"{ "method": "guillotine", "cutWidth": 1, "stockPieces": [{ "width": 48, "length": 96, "patternDirection": "none", "price": 0 }], "cutPieces": [{ "externalId": 1, "width": 10, "length": 30, "patternDirection": "none", "canRotate": true }] }"#,
+        "\n\n",
+        "not json\n",
+    );
+    let mut output = Vec::new();
+    let config = OptimizerConfig::from(&Opt::from_iter(&["cut-optimizer-2d-server"]));
+
+    pipe(&config, input.as_bytes(), &mut output).await.unwrap();
+
+    let lines: Vec<Value> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["stockPieces"][0]["cutPieces"][0]["externalId"], 1);
+    assert_eq!(lines[1]["message"], "Invalid request body");
+}
@@ -0,0 +1,79 @@
+use axum::Json;
+use futures::stream::{self, StreamExt};
+use http::StatusCode;
+use std::convert::TryFrom;
+use std::net::TcpListener;
+use std::time::Duration;
+use tonic::{Code, Request, Response, Status};
+
+use crate::proto::{self, optimizer_server::Optimizer, optimizer_server::OptimizerServer};
+use crate::server::{self, OptimizeError, OptimizerConfig, OptimizerInput};
+use crate::Opt;
+
+#[cfg(test)]
+mod tests;
+
+/// Run gRPC optimizer server
+pub(crate) async fn serve(listeners: Vec<TcpListener>, opt: &Opt) {
+    // Accept connections from every listener on the same server
+    let incoming = stream::select_all(listeners.into_iter().map(|listener| {
+        let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+        stream::unfold(listener, |listener| async {
+            let connection = listener.accept().await.map(|(stream, _)| stream);
+            Some((connection, listener))
+        })
+        .boxed()
+    }));
+
+    tonic::transport::Server::builder()
+        .concurrency_limit_per_connection(opt.max_requests)
+        .timeout(Duration::from_secs(opt.timeout))
+        .add_service(OptimizerServer::new(OptimizerService::new(opt.into())))
+        .serve_with_incoming(incoming)
+        .await
+        .unwrap();
+}
+
+#[derive(Debug)]
+pub(crate) struct OptimizerService {
+    config: OptimizerConfig,
+}
+
+impl OptimizerService {
+    pub(crate) fn new(config: OptimizerConfig) -> Self {
+        Self { config }
+    }
+}
+
+#[tonic::async_trait]
+impl Optimizer for OptimizerService {
+    async fn optimize(
+        &self,
+        request: Request<proto::OptimizeRequest>,
+    ) -> Result<Response<proto::Solution>, Status> {
+        let input = OptimizerInput::try_from(request.into_inner())
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let solution = server::run_optimizer(&self.config, input)
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(solution.into()))
+    }
+}
+
+/// Convert an HTTP optimize error into a gRPC status, keeping the message and
+/// appending any error data as JSON.
+fn into_status((status_code, Json(body)): OptimizeError) -> Status {
+    let code = match status_code {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Code::InvalidArgument,
+        StatusCode::REQUEST_TIMEOUT => Code::DeadlineExceeded,
+        StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+        _ => Code::Internal,
+    };
+
+    let message = body["message"].as_str().unwrap_or_default();
+    match body.get("data") {
+        Some(data) => Status::new(code, format!("{}: {}", message, data)),
+        None => Status::new(code, message),
+    }
+}
@@ -0,0 +1,71 @@
+use super::*;
+use structopt::StructOpt;
+
+fn service() -> OptimizerService {
+    OptimizerService::new(OptimizerConfig::from(&Opt::from_iter(&[
+        "cut-optimizer-2d-server",
+    ])))
+}
+
+fn stock_piece(width: u64, length: u64) -> proto::StockPiece {
+    proto::StockPiece {
+        width,
+        length,
+        pattern_direction: proto::PatternDirection::None as i32,
+        price: 0,
+        quantity: None,
+        ..Default::default()
+    }
+}
+
+fn cut_piece(external_id: u64, width: u64, length: u64) -> proto::CutPiece {
+    proto::CutPiece {
+        external_id: Some(external_id),
+        width,
+        length,
+        pattern_direction: proto::PatternDirection::None as i32,
+        can_rotate: true,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn optimize_should_return_solution() {
+    let request = proto::OptimizeRequest {
+        method: Some(proto::OptimizeMethod::Guillotine as i32),
+        random_seed: Some(1),
+        cut_width: Some(2),
+        stock_pieces: vec![stock_piece(48, 96), stock_piece(48, 120)],
+        cut_pieces: vec![cut_piece(1, 10, 30), cut_piece(2, 45, 100)],
+        ..Default::default()
+    };
+
+    let solution = service()
+        .optimize(Request::new(request))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let placed: usize = solution
+        .stock_pieces
+        .iter()
+        .map(|stock_piece| stock_piece.cut_pieces.len())
+        .sum();
+    assert_eq!(placed, 2);
+}
+
+#[tokio::test]
+async fn non_fitting_piece_should_return_invalid_argument() {
+    let request = proto::OptimizeRequest {
+        method: Some(proto::OptimizeMethod::Nested as i32),
+        random_seed: Some(1),
+        cut_width: Some(2),
+        stock_pieces: vec![stock_piece(48, 96)],
+        cut_pieces: vec![cut_piece(1, 10, 300)],
+        ..Default::default()
+    };
+
+    let status = service().optimize(Request::new(request)).await.unwrap_err();
+
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
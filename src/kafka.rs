@@ -0,0 +1,134 @@
+use futures::StreamExt;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::message::{Header, Headers, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{ClientConfig, Message};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::{error, info};
+
+use crate::queue;
+use crate::server::OptimizerConfig;
+use crate::Opt;
+
+/// Header with the HTTP status code equivalent of the result
+const STATUS_HEADER: &str = "Cut-Optimizer-Status";
+
+/// Consume optimizer inputs from a Kafka topic, producing each solution to the
+/// results topic with the input's key.
+///
+/// An input's offset is stored once its solution is produced. Up to
+/// `max_requests` inputs are processed at once, so after a restart inputs that
+/// were in flight may be skipped if a later input on the same partition had
+/// already finished.
+///
+/// The Kafka client reconnects to brokers by itself, looking their hosts up
+/// again each time, so only its longest backoff is set here.
+pub(crate) async fn serve(brokers: &str, opt: &Opt) {
+    let max_backoff = opt.reconnect_max_seconds.saturating_mul(1000).to_string();
+    let consumer: StreamConsumer = match ClientConfig::new()
+        .set("bootstrap.servers", brokers)
+        .set("reconnect.backoff.max.ms", &max_backoff)
+        .set("group.id", &opt.kafka_group_id)
+        .set("enable.auto.offset.store", "false")
+        .create()
+    {
+        Ok(consumer) => consumer,
+        Err(e) => {
+            error!("Couldn't create Kafka consumer for {}: {}", brokers, e);
+            return;
+        }
+    };
+    let producer: FutureProducer = match ClientConfig::new()
+        .set("bootstrap.servers", brokers)
+        .set("reconnect.backoff.max.ms", &max_backoff)
+        .create()
+    {
+        Ok(producer) => producer,
+        Err(e) => {
+            error!("Couldn't create Kafka producer for {}: {}", brokers, e);
+            return;
+        }
+    };
+
+    let topic = opt.kafka_topic.clone();
+    if let Err(e) = consumer.subscribe(&[&topic]) {
+        error!("Couldn't subscribe to Kafka topic {}: {}", topic, e);
+        return;
+    }
+
+    info!("Consuming optimizer inputs from Kafka topic {}", topic);
+
+    let consumer = Arc::new(consumer);
+    let config = OptimizerConfig::from(opt);
+    let permits = Arc::new(Semaphore::new(opt.max_requests));
+
+    let mut messages = consumer.stream();
+    while let Some(message) = messages.next().await {
+        let message = match message {
+            Ok(message) => message.detach(),
+            Err(e) => {
+                error!("Couldn't receive from Kafka topic {}: {}", topic, e);
+                continue;
+            }
+        };
+
+        // Process at most `max_requests` messages at once
+        let permit = permits.clone().acquire_owned().await.unwrap();
+        let consumer = consumer.clone();
+        let producer = producer.clone();
+        let config = config.clone();
+        let results_topic = opt.kafka_results_topic.clone();
+
+        tokio::spawn(async move {
+            let header = |name: &str| {
+                message.headers().and_then(|headers| {
+                    headers
+                        .iter()
+                        .find(|header| header.key.eq_ignore_ascii_case(name))
+                        .and_then(|header| header.value)
+                        .and_then(|value| std::str::from_utf8(value).ok())
+                        .map(str::to_string)
+                })
+            };
+            let content_type = header("Content-Type");
+            let accept = header("Accept");
+
+            let reply = queue::process(
+                &config,
+                content_type.as_deref(),
+                accept.as_deref(),
+                message.payload().unwrap_or_default(),
+            )
+            .await;
+            drop(permit);
+
+            let headers = OwnedHeaders::new()
+                .insert(Header {
+                    key: "Content-Type",
+                    value: Some(reply.content_type),
+                })
+                .insert(Header {
+                    key: STATUS_HEADER,
+                    value: Some(reply.status.as_str()),
+                });
+            let mut record = FutureRecord::to(&results_topic)
+                .payload(&reply.body)
+                .headers(headers);
+            if let Some(key) = message.key() {
+                record = record.key(key);
+            }
+
+            if let Err((e, _)) = producer.send(record, Duration::from_secs(0)).await {
+                error!("Couldn't produce Kafka reply to {}: {}", results_topic, e);
+                return;
+            }
+            if let Err(e) =
+                consumer.store_offset(message.topic(), message.partition(), message.offset())
+            {
+                error!("Couldn't store Kafka offset: {}", e);
+            }
+        });
+    }
+}
@@ -0,0 +1,70 @@
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+
+#[cfg(test)]
+mod tests;
+
+/// Connections queued on a listener before it starts refusing them
+const BACKLOG: i32 = 1024;
+
+/// Every address `host` resolves to with `port`, without duplicates.
+///
+/// IPv6 listeners are dual-stack unless `ipv6_only` is set, in which case IPv4
+/// addresses are left out. A dual-stack listener on the IPv6 unspecified
+/// address also accepts IPv4 connections, so IPv4 addresses are left out then
+/// too instead of failing to bind the same port twice.
+pub(crate) fn addresses(host: &str, port: u16, ipv6_only: bool) -> Result<Vec<SocketAddr>, String> {
+    let resolved = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("Unable to resolve host {}: {}", host, e))?;
+
+    let mut addrs: Vec<SocketAddr> = Vec::new();
+    for addr in resolved {
+        if !addrs.contains(&addr) {
+            addrs.push(addr);
+        }
+    }
+
+    let dual_stack_any = !ipv6_only
+        && addrs
+            .iter()
+            .any(|addr| addr.is_ipv6() && addr.ip().is_unspecified());
+    if ipv6_only || dual_stack_any {
+        addrs.retain(SocketAddr::is_ipv6);
+    }
+
+    if addrs.is_empty() {
+        return Err(match ipv6_only {
+            true => format!("Host {} has no IPv6 addresses", host),
+            false => format!("Host {} has no addresses", host),
+        });
+    }
+    Ok(addrs)
+}
+
+/// Bind a non-blocking listener to `addr`, making IPv6 listeners dual-stack
+/// unless `ipv6_only` is set
+pub(crate) fn bind(addr: SocketAddr, ipv6_only: bool) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(ipv6_only)?;
+    }
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(BACKLOG)?;
+    socket.set_nonblocking(true)?;
+
+    Ok(socket.into())
+}
+
+/// Bind a listener to each of `addrs`
+pub(crate) fn bind_all(addrs: &[SocketAddr], ipv6_only: bool) -> Result<Vec<TcpListener>, String> {
+    addrs
+        .iter()
+        .map(|&addr| {
+            bind(addr, ipv6_only).map_err(|e| format!("Couldn't listen on {}: {}", addr, e))
+        })
+        .collect()
+}
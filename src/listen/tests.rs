@@ -0,0 +1,25 @@
+use super::*;
+
+#[test]
+fn addresses_should_include_every_resolved_address() {
+    let addrs = addresses("127.0.0.1", 8080, false).unwrap();
+
+    assert_eq!(addrs, vec!["127.0.0.1:8080".parse().unwrap()]);
+}
+
+#[test]
+fn ipv6_only_should_leave_out_ipv4_addresses() {
+    assert!(addresses("127.0.0.1", 8080, true).is_err());
+    assert_eq!(
+        addresses("::1", 8080, true).unwrap(),
+        vec!["[::1]:8080".parse().unwrap()]
+    );
+}
+
+#[test]
+fn listeners_should_bind_to_every_address() {
+    let listeners = bind_all(&["127.0.0.1:0".parse().unwrap()], false).unwrap();
+
+    assert_eq!(listeners.len(), 1);
+    assert!(listeners[0].local_addr().unwrap().ip().is_loopback());
+}
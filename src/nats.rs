@@ -0,0 +1,161 @@
+use async_nats::{Client, ConnectOptions, HeaderMap, ServerAddr, Subscriber};
+use futures::StreamExt;
+use std::convert::TryFrom;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::{error, info, warn};
+
+use crate::queue;
+use crate::reconnect::{self, Backoff};
+use crate::server::OptimizerConfig;
+use crate::Opt;
+
+/// Header with the HTTP status code equivalent of the result
+const STATUS_HEADER: &str = "Cut-Optimizer-Status";
+
+/// Consume optimizer inputs from a NATS subject, publishing each solution to
+/// the message's reply subject, or to the results subject if it has none.
+///
+/// The client reconnects by itself when the connection drops. The server is
+/// also reconnected to when its host resolves to other addresses, and when
+/// connecting or subscribing fails, with jittered backoff in both cases.
+pub(crate) async fn serve(url: &str, opt: &Opt) {
+    let hosts = match hosts(url) {
+        Ok(hosts) => hosts,
+        Err(e) => {
+            error!("Invalid NATS URL {}: {}", url, e);
+            return;
+        }
+    };
+    let max_delay = Duration::from_secs(opt.reconnect_max_seconds);
+    let refresh =
+        (opt.dns_refresh_seconds > 0).then(|| Duration::from_secs(opt.dns_refresh_seconds));
+
+    let config = OptimizerConfig::from(opt);
+    let permits = Arc::new(Semaphore::new(opt.max_requests));
+    let mut backoff = Backoff::new(max_delay);
+
+    loop {
+        let addresses = reconnect::resolve(&hosts).await.unwrap_or_default();
+        if let Some((client, subscriber)) = subscribe(url, opt, max_delay).await {
+            backoff.reset();
+            tokio::select! {
+                () = consume(client, subscriber, opt, &config, &permits) => {
+                    warn!("NATS subscription to {} ended", opt.nats_subject);
+                }
+                () = reconnect::changed(&hosts, &addresses, refresh) => {}
+            }
+            info!("Reconnecting to NATS at {}", url);
+        }
+        backoff.wait().await;
+    }
+}
+
+/// Hosts and ports of the servers in a NATS URL, which may list several
+/// separated by commas
+fn hosts(url: &str) -> io::Result<Vec<(String, u16)>> {
+    url.split(',')
+        .map(|server| {
+            let server: ServerAddr = server.trim().parse()?;
+            Ok((server.host().to_string(), server.port()))
+        })
+        .collect()
+}
+
+async fn subscribe(url: &str, opt: &Opt, max_delay: Duration) -> Option<(Client, Subscriber)> {
+    let client = match ConnectOptions::new()
+        .reconnect_delay_callback(move |attempts| {
+            reconnect::delay(u32::try_from(attempts).unwrap_or(u32::MAX), max_delay)
+        })
+        .connect(url)
+        .await
+    {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Couldn't connect to NATS at {}: {}", url, e);
+            return None;
+        }
+    };
+
+    let subject = opt.nats_subject.clone();
+    let subscriber = match &opt.nats_queue_group {
+        Some(queue_group) => {
+            client
+                .queue_subscribe(subject.clone(), queue_group.clone())
+                .await
+        }
+        None => client.subscribe(subject.clone()).await,
+    };
+    match subscriber {
+        Ok(subscriber) => {
+            info!("Consuming optimizer inputs from NATS subject {}", subject);
+            Some((client, subscriber))
+        }
+        Err(e) => {
+            error!("Couldn't subscribe to NATS subject {}: {}", subject, e);
+            None
+        }
+    }
+}
+
+/// Process messages until the subscription ends
+async fn consume(
+    client: Client,
+    mut subscriber: Subscriber,
+    opt: &Opt,
+    config: &OptimizerConfig,
+    permits: &Arc<Semaphore>,
+) {
+    while let Some(message) = subscriber.next().await {
+        let reply_subject = match (&message.reply, &opt.nats_results_subject) {
+            (Some(reply), _) => reply.to_string(),
+            (None, Some(results_subject)) => results_subject.clone(),
+            (None, None) => {
+                warn!(
+                    "Ignoring NATS message on {} without a reply subject",
+                    message.subject
+                );
+                continue;
+            }
+        };
+
+        // Process at most `max_requests` messages at once
+        let permit = permits.clone().acquire_owned().await.unwrap();
+        let client = client.clone();
+        let config = config.clone();
+
+        tokio::spawn(async move {
+            let header = |name: &str| {
+                message
+                    .headers
+                    .as_ref()
+                    .and_then(|headers| headers.get(name))
+                    .map(|value| value.as_str().to_string())
+            };
+            let content_type = header("Content-Type");
+            let accept = header("Accept");
+
+            let reply = queue::process(
+                &config,
+                content_type.as_deref(),
+                accept.as_deref(),
+                &message.payload,
+            )
+            .await;
+            drop(permit);
+
+            let mut headers = HeaderMap::new();
+            headers.insert("Content-Type", reply.content_type);
+            headers.insert(STATUS_HEADER, reply.status.as_str());
+
+            if let Err(e) = client
+                .publish_with_headers(reply_subject.clone(), headers, reply.body.into())
+                .await
+            {
+                error!("Couldn't publish NATS reply to {}: {}", reply_subject, e);
+            }
+        });
+    }
+}
@@ -0,0 +1,654 @@
+use prost::Message;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+
+use crate::server::expression::Dimension;
+use crate::server::format::BodyError;
+use crate::server::{
+    CutDirection as OutputCutDirection, CutMetrics as OutputCutMetrics,
+    EdgeAllowance as InputAllowance, InputCutPiece, InputStockPiece, Objective as InputObjective,
+    OptimizeMethod as InputMethod, Optimized, OptimizerInput, OptimizerOutput,
+    PanelKind as OutputPanelKind, Pin as InputPin, QualityWeights as InputWeights,
+    SeedScore as OutputSeedScore, SheetCost as OutputSheetCost, SheetStats as OutputSheetStats,
+};
+
+tonic::include_proto!("optimizer");
+
+/// Types that can be decoded from a protobuf message
+pub(crate) trait FromProtobuf: Sized {
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, BodyError>;
+}
+
+/// Types that can be encoded as a protobuf message
+pub(crate) trait IntoProtobuf {
+    fn into_protobuf(self) -> Vec<u8>;
+}
+
+impl FromProtobuf for OptimizerInput {
+    fn from_protobuf(bytes: &[u8]) -> Result<Self, BodyError> {
+        Ok(OptimizeRequest::decode(bytes)?.try_into()?)
+    }
+}
+
+impl IntoProtobuf for Optimized {
+    fn into_protobuf(self) -> Vec<u8> {
+        Solution::from(self).encode_to_vec()
+    }
+}
+
+/// Field of a request whose value doesn't fit in a `usize` on this platform
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TooLarge {
+    /// Path of the field, such as `stockPieces[2].width`
+    pub(crate) path: String,
+    pub(crate) value: u64,
+}
+
+impl TooLarge {
+    /// The same field, within a field of the message holding it
+    fn within(self, field: impl fmt::Display) -> Self {
+        Self {
+            path: format!("{}.{}", field, self.path),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` is {}, which is more than this server supports",
+            self.path, self.value
+        )
+    }
+}
+
+/// Value of a field as a `usize`
+fn size(value: impl Into<u64>, path: &str) -> Result<usize, TooLarge> {
+    let value = value.into();
+    usize::try_from(value).map_err(|_| TooLarge {
+        path: path.to_string(),
+        value,
+    })
+}
+
+fn optional_size(value: Option<impl Into<u64>>, path: &str) -> Result<Option<usize>, TooLarge> {
+    value.map(|value| size(value, path)).transpose()
+}
+
+/// Messages of a repeated field, converted one by one
+fn list<T, U: TryFrom<T, Error = TooLarge>>(
+    items: Vec<T>,
+    field: &str,
+) -> Result<Vec<U>, TooLarge> {
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| U::try_from(item).map_err(|e| e.within(format!("{}[{}]", field, i))))
+        .collect()
+}
+
+impl TryFrom<OptimizeRequest> for OptimizerInput {
+    type Error = TooLarge;
+
+    fn try_from(request: OptimizeRequest) -> Result<Self, TooLarge> {
+        let seeds = if request.seeds.is_empty() {
+            None
+        } else {
+            Some(request.seeds.clone())
+        };
+
+        Ok(Self {
+            preset: request.preset.clone(),
+            language: request.language.clone(),
+            method: request.method.map(|_| match request.method() {
+                OptimizeMethod::Guillotine => InputMethod::Guillotine,
+                OptimizeMethod::Nested => InputMethod::Nested,
+                OptimizeMethod::Best => InputMethod::Best,
+            }),
+            random_seed: request.random_seed,
+            seed_count: optional_size(request.seed_count, "seedCount")?,
+            seeds,
+            include_scores: request.include_scores,
+            include_compute_stats: request.include_compute_stats,
+            currency: request.currency.clone(),
+            price_per_area: request.price_per_area,
+            early_stop_utilization: request.early_stop_utilization,
+            min_offcut_width: optional_size(request.min_offcut_width, "minOffcutWidth")?,
+            min_offcut_length: optional_size(request.min_offcut_length, "minOffcutLength")?,
+            prefer_remnants: request.prefer_remnants,
+            solution_count: optional_size(request.solution_count, "solutionCount")?,
+            allow_partial: request.allow_partial,
+            stock_catalog: request.stock_catalog.clone(),
+            objective: request.objective.map(|_| match request.objective() {
+                Objective::Cost => InputObjective::Cost,
+                Objective::Waste => InputObjective::Waste,
+                Objective::Sheets => InputObjective::Sheets,
+                Objective::CutLength => InputObjective::CutLength,
+            }),
+            max_seconds: request.max_seconds,
+            feed_rate: request.feed_rate,
+            best_effort: request.best_effort,
+            variables: if request.variables.is_empty() {
+                None
+            } else {
+                Some(request.variables.clone())
+            },
+            quality_weights: request.quality_weights.map(|weights| InputWeights {
+                utilization: weights.utilization,
+                offcut_usability: weights.offcut_usability,
+                cut_simplicity: weights.cut_simplicity,
+            }),
+            cut_width: optional_size(request.cut_width, "cutWidth")?,
+            stock_pieces: list(request.stock_pieces, "stockPieces")?,
+            cut_pieces: list(request.cut_pieces, "cutPieces")?,
+            allow_mixed_stock_sizes: request.allow_mixed_stock_sizes,
+            pins: list(request.pins, "pins")?,
+        })
+    }
+}
+
+impl From<PatternDirection> for cut_optimizer_2d::PatternDirection {
+    fn from(pattern_direction: PatternDirection) -> Self {
+        match pattern_direction {
+            PatternDirection::None => Self::None,
+            PatternDirection::ParallelToWidth => Self::ParallelToWidth,
+            PatternDirection::ParallelToLength => Self::ParallelToLength,
+        }
+    }
+}
+
+impl From<cut_optimizer_2d::PatternDirection> for PatternDirection {
+    fn from(pattern_direction: cut_optimizer_2d::PatternDirection) -> Self {
+        match pattern_direction {
+            cut_optimizer_2d::PatternDirection::None => Self::None,
+            cut_optimizer_2d::PatternDirection::ParallelToWidth => Self::ParallelToWidth,
+            cut_optimizer_2d::PatternDirection::ParallelToLength => Self::ParallelToLength,
+        }
+    }
+}
+
+impl TryFrom<StockPiece> for InputStockPiece {
+    type Error = TooLarge;
+
+    fn try_from(stock_piece: StockPiece) -> Result<Self, TooLarge> {
+        Ok(Self {
+            width: size(stock_piece.width, "width")?,
+            length: size(stock_piece.length, "length")?,
+            pattern_direction: stock_piece.pattern_direction().into(),
+            price: size(stock_piece.price, "price")?,
+            quantity: optional_size(stock_piece.quantity, "quantity")?,
+            is_remnant: stock_piece.is_remnant,
+            must_use: stock_piece.must_use,
+            trim_top: size(stock_piece.trim_top, "trimTop")?,
+            trim_bottom: size(stock_piece.trim_bottom, "trimBottom")?,
+            trim_left: size(stock_piece.trim_left, "trimLeft")?,
+            trim_right: size(stock_piece.trim_right, "trimRight")?,
+            cut_width: optional_size(stock_piece.cut_width, "cutWidth")?,
+            defects: list(stock_piece.defects, "defects")?,
+            material: stock_piece.material,
+            metadata: stock_piece.metadata.map(metadata),
+        })
+    }
+}
+
+impl TryFrom<CutPiece> for InputCutPiece {
+    type Error = TooLarge;
+
+    fn try_from(cut_piece: CutPiece) -> Result<Self, TooLarge> {
+        let dimension = |value: u64, expression: Option<String>, path| match expression {
+            Some(expression) => Ok(Dimension::Expression(expression)),
+            None => size(value, path).map(Dimension::Value),
+        };
+
+        Ok(Self {
+            external_id: optional_size(cut_piece.external_id, "externalId")?,
+            width: dimension(cut_piece.width, cut_piece.width_expression.clone(), "width")?,
+            length: dimension(
+                cut_piece.length,
+                cut_piece.length_expression.clone(),
+                "length",
+            )?,
+            pattern_direction: cut_piece.pattern_direction().into(),
+            can_rotate: cut_piece.can_rotate,
+            quantity: optional_size(cut_piece.quantity, "quantity")?,
+            edge_allowance: cut_piece
+                .edge_allowance
+                .map(|allowance| {
+                    InputAllowance::try_from(allowance).map_err(|e| e.within("edgeAllowance"))
+                })
+                .transpose()?,
+            optional: cut_piece.optional,
+            material: cut_piece.material,
+            metadata: cut_piece.metadata.map(metadata),
+        })
+    }
+}
+
+/// Metadata from its JSON text. Text that isn't JSON is kept as a string, so
+/// validation rejects it for not being an object.
+fn metadata(text: String) -> Value {
+    serde_json::from_str(&text).unwrap_or(Value::String(text))
+}
+
+impl TryFrom<Pin> for InputPin {
+    type Error = TooLarge;
+
+    fn try_from(pin: Pin) -> Result<Self, TooLarge> {
+        Ok(Self {
+            cut_piece: size(pin.cut_piece, "cutPiece")?,
+            stock_piece: size(pin.stock_piece, "stockPiece")?,
+            x: size(pin.x, "x")?,
+            y: size(pin.y, "y")?,
+            is_rotated: pin.is_rotated,
+        })
+    }
+}
+
+impl TryFrom<EdgeAllowance> for InputAllowance {
+    type Error = TooLarge;
+
+    fn try_from(allowance: EdgeAllowance) -> Result<Self, TooLarge> {
+        Ok(Self {
+            top: size(allowance.top, "top")?,
+            bottom: size(allowance.bottom, "bottom")?,
+            left: size(allowance.left, "left")?,
+            right: size(allowance.right, "right")?,
+        })
+    }
+}
+
+impl From<InputAllowance> for EdgeAllowance {
+    fn from(allowance: InputAllowance) -> Self {
+        Self {
+            top: allowance.top as u64,
+            bottom: allowance.bottom as u64,
+            left: allowance.left as u64,
+            right: allowance.right as u64,
+        }
+    }
+}
+
+impl From<InputMethod> for OptimizeMethod {
+    fn from(method: InputMethod) -> Self {
+        match method {
+            InputMethod::Guillotine => Self::Guillotine,
+            InputMethod::Nested => Self::Nested,
+            InputMethod::Best => Self::Best,
+        }
+    }
+}
+
+impl From<Optimized> for Solution {
+    fn from(optimized: Optimized) -> Self {
+        match optimized {
+            Optimized::Solution(output) => (*output).into(),
+            Optimized::ByMaterial { materials } => Self {
+                materials: materials
+                    .into_iter()
+                    .map(|(material, output)| (material, output.into()))
+                    .collect(),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl From<OptimizerOutput> for Solution {
+    fn from(output: OptimizerOutput) -> Self {
+        Self {
+            fitness: output.solution.fitness,
+            stock_pieces: output
+                .solution
+                .stock_pieces
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            method: OptimizeMethod::from(output.method) as i32,
+            random_seed: output.random_seed,
+            scores: output
+                .scores
+                .unwrap_or_default()
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            metrics: Some(output.metrics.into()),
+            alternatives: output
+                .alternatives
+                .unwrap_or_default()
+                .into_iter()
+                .map(|alternative| Alternative {
+                    fitness: alternative.solution.fitness,
+                    stock_pieces: alternative
+                        .solution
+                        .stock_pieces
+                        .into_iter()
+                        .map(Into::into)
+                        .collect(),
+                    method: OptimizeMethod::from(alternative.method) as i32,
+                    random_seed: alternative.random_seed,
+                    score: Some(
+                        OutputSeedScore {
+                            seed: alternative.random_seed,
+                            score: alternative.score,
+                        }
+                        .into(),
+                    ),
+                    metrics: Some(alternative.metrics.into()),
+                })
+                .collect(),
+            quality: Some(Quality {
+                score: output.quality.score,
+                utilization: output.quality.utilization,
+                offcut_usability: output.quality.offcut_usability,
+                cut_simplicity: output.quality.cut_simplicity,
+            }),
+            partial: output.partial,
+            cpu_seconds: output.cpu_seconds,
+            stats: Some(SolutionStats {
+                sheet_count: output.stats.sheet_count as u64,
+                remnant_count: output.stats.remnant_count as u64,
+                totals: Some(output.stats.totals.into()),
+                sheets: output.stats.sheets.into_iter().map(Into::into).collect(),
+            }),
+            offcuts: output
+                .offcuts
+                .unwrap_or_default()
+                .into_iter()
+                .map(|offcut| Offcut {
+                    sheet: offcut.sheet as u64,
+                    x: offcut.rect.x as u64,
+                    y: offcut.rect.y as u64,
+                    width: offcut.rect.width as u64,
+                    length: offcut.rect.length as u64,
+                })
+                .collect(),
+            cost: Some(Cost {
+                currency: output.cost.currency,
+                sheets: output.cost.sheets.into_iter().map(Into::into).collect(),
+                material_cost: output.cost.material_cost,
+            }),
+            cut_piece_groups: output
+                .cut_piece_groups
+                .unwrap_or_default()
+                .into_iter()
+                .map(|group| CutPieceGroup {
+                    cut_piece: group.cut_piece as u64,
+                    external_id: group.external_id.map(|id| id as u64),
+                    quantity: group.quantity as u64,
+                    placements: group
+                        .placements
+                        .into_iter()
+                        .map(|placement| Placement {
+                            sheet: placement.sheet as u64,
+                            index: placement.index as u64,
+                        })
+                        .collect(),
+                    optional: group.optional,
+                })
+                .collect(),
+            edge_allowances: output
+                .edge_allowances
+                .unwrap_or_default()
+                .into_iter()
+                .map(|placed| PlacedAllowance {
+                    sheet: placed.sheet as u64,
+                    index: placed.index as u64,
+                    allowance: Some(placed.allowance.into()),
+                    finished: Some(Rect {
+                        x: placed.finished.x as u64,
+                        y: placed.finished.y as u64,
+                        width: placed.finished.width as u64,
+                        length: placed.finished.length as u64,
+                    }),
+                })
+                .collect(),
+            unplaced_pieces: output
+                .unplaced_pieces
+                .unwrap_or_default()
+                .into_iter()
+                .map(|unplaced| UnplacedPiece {
+                    cut_piece: unplaced.cut_piece as u64,
+                    external_id: unplaced.external_id.map(|id| id as u64),
+                    quantity: unplaced.quantity as u64,
+                })
+                .collect(),
+            warnings: output
+                .warnings
+                .into_iter()
+                .map(|warning| Warning {
+                    code: warning.code.to_string(),
+                    path: warning.path,
+                    message: warning.message,
+                    fixes: warning
+                        .fixes
+                        .into_iter()
+                        .map(|fix| Fix {
+                            message: fix.message,
+                            changes: fix
+                                .changes
+                                .into_iter()
+                                .map(|change| Change {
+                                    path: change.path,
+                                    value: change.value.to_string(),
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            compute_stats: output.compute_stats.map(|stats| ComputeStats {
+                cpu_ms: stats.cpu_ms,
+                wall_ms: stats.wall_ms,
+                threads: stats.threads as u32,
+                runs: stats.runs as u32,
+                epochs: stats.epochs,
+                peak_memory_bytes: stats.peak_memory_bytes,
+            }),
+            materials: HashMap::new(),
+            metadata: output.metadata.map(|metadata| PieceMetadata {
+                sheets: metadata
+                    .sheets
+                    .into_iter()
+                    .map(|sheet| SheetMetadata {
+                        sheet: sheet.sheet as u64,
+                        stock_piece: sheet.stock_piece as u64,
+                        metadata: sheet.metadata.to_string(),
+                    })
+                    .collect(),
+                cut_pieces: metadata
+                    .cut_pieces
+                    .into_iter()
+                    .map(|placed| PlacedMetadata {
+                        sheet: placed.sheet as u64,
+                        index: placed.index as u64,
+                        cut_piece: placed.cut_piece as u64,
+                        metadata: placed.metadata.to_string(),
+                    })
+                    .collect(),
+            }),
+            cut_sequence: output
+                .cut_sequence
+                .unwrap_or_default()
+                .into_iter()
+                .map(|step| CutStep {
+                    sheet: step.sheet as u64,
+                    direction: CutDirection::from(step.direction) as i32,
+                    position: step.position as u64,
+                    panel: Some(step.panel.into()),
+                    results: step
+                        .results
+                        .into_iter()
+                        .map(|panel| Panel {
+                            rect: Some(panel.rect.into()),
+                            kind: PanelKind::from(panel.kind) as i32,
+                            cut_piece: panel.cut_piece.map(|index| index as u64),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<OutputCutDirection> for CutDirection {
+    fn from(direction: OutputCutDirection) -> Self {
+        match direction {
+            OutputCutDirection::Rip => Self::Rip,
+            OutputCutDirection::Crosscut => Self::Crosscut,
+        }
+    }
+}
+
+impl From<OutputPanelKind> for PanelKind {
+    fn from(kind: OutputPanelKind) -> Self {
+        match kind {
+            OutputPanelKind::Panel => Self::Panel,
+            OutputPanelKind::CutPiece => Self::CutPiece,
+            OutputPanelKind::Waste => Self::Waste,
+        }
+    }
+}
+
+impl From<OutputSheetCost> for SheetCost {
+    fn from(cost: OutputSheetCost) -> Self {
+        Self {
+            price: cost.price as u64,
+            area_cost: cost.area_cost,
+            total: cost.total,
+        }
+    }
+}
+
+impl From<OutputSheetStats> for SheetStats {
+    fn from(stats: OutputSheetStats) -> Self {
+        Self {
+            used_area: stats.used_area as u64,
+            waste_area: stats.waste_area as u64,
+            utilization_percent: stats.utilization_percent,
+            cut_length: stats.cut_length as u64,
+            price: stats.price as u64,
+            is_remnant: stats.is_remnant.unwrap_or(false),
+        }
+    }
+}
+
+impl From<OutputSeedScore> for SeedScore {
+    fn from(seed_score: OutputSeedScore) -> Self {
+        Self {
+            seed: seed_score.seed,
+            price: seed_score.score.price as u64,
+            waste_area: seed_score.score.waste_area as u64,
+            fitness: seed_score.score.fitness,
+            sheet_count: seed_score.score.sheet_count as u64,
+            cut_length: seed_score.score.cut_length as u64,
+        }
+    }
+}
+
+impl From<OutputCutMetrics> for CutMetrics {
+    fn from(metrics: OutputCutMetrics) -> Self {
+        Self {
+            cut_count: metrics.cut_count as u64,
+            cut_length: metrics.cut_length as u64,
+            sawing_seconds: metrics.sawing_seconds,
+        }
+    }
+}
+
+impl From<cut_optimizer_2d::ResultStockPiece> for ResultStockPiece {
+    fn from(stock_piece: cut_optimizer_2d::ResultStockPiece) -> Self {
+        Self {
+            width: stock_piece.width as u64,
+            length: stock_piece.length as u64,
+            pattern_direction: PatternDirection::from(stock_piece.pattern_direction) as i32,
+            cut_pieces: stock_piece.cut_pieces.into_iter().map(Into::into).collect(),
+            waste_pieces: stock_piece.waste_pieces.iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<cut_optimizer_2d::ResultCutPiece> for ResultCutPiece {
+    fn from(cut_piece: cut_optimizer_2d::ResultCutPiece) -> Self {
+        Self {
+            external_id: cut_piece.external_id.map(|id| id as u64),
+            x: cut_piece.x as u64,
+            y: cut_piece.y as u64,
+            width: cut_piece.width as u64,
+            length: cut_piece.length as u64,
+            pattern_direction: PatternDirection::from(cut_piece.pattern_direction) as i32,
+            is_rotated: cut_piece.is_rotated,
+        }
+    }
+}
+
+/// Mirror of `cut_optimizer_2d::Rect`, whose fields are private but serialized.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[schemars(rename = "Rect")]
+pub(crate) struct RectFields {
+    /// Offset from the left edge of the stock piece
+    pub(crate) x: usize,
+
+    /// Offset from the top edge of the stock piece
+    pub(crate) y: usize,
+    pub(crate) width: usize,
+    pub(crate) length: usize,
+}
+
+impl From<&cut_optimizer_2d::Rect> for RectFields {
+    fn from(rect: &cut_optimizer_2d::Rect) -> Self {
+        serde_json::to_value(rect)
+            .and_then(serde_json::from_value)
+            .expect("Rect should round-trip through JSON")
+    }
+}
+
+impl From<RectFields> for cut_optimizer_2d::Rect {
+    fn from(rect: RectFields) -> Self {
+        serde_json::to_value(rect)
+            .and_then(serde_json::from_value)
+            .expect("Rect should round-trip through JSON")
+    }
+}
+
+impl TryFrom<Rect> for RectFields {
+    type Error = TooLarge;
+
+    fn try_from(rect: Rect) -> Result<Self, TooLarge> {
+        Ok(Self {
+            x: size(rect.x, "x")?,
+            y: size(rect.y, "y")?,
+            width: size(rect.width, "width")?,
+            length: size(rect.length, "length")?,
+        })
+    }
+}
+
+impl From<RectFields> for Rect {
+    fn from(rect: RectFields) -> Self {
+        Self {
+            x: rect.x as u64,
+            y: rect.y as u64,
+            width: rect.width as u64,
+            length: rect.length as u64,
+        }
+    }
+}
+
+impl From<&cut_optimizer_2d::Rect> for Rect {
+    fn from(rect: &cut_optimizer_2d::Rect) -> Self {
+        let rect = RectFields::from(rect);
+
+        Self {
+            x: rect.x as u64,
+            y: rect.y as u64,
+            width: rect.width as u64,
+            length: rect.length as u64,
+        }
+    }
+}
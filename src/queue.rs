@@ -0,0 +1,63 @@
+use axum::Json;
+use http::StatusCode;
+
+use crate::server::format::{unsupported_media_type, Format};
+use crate::server::{self, OptimizeError, OptimizerConfig, OptimizerInput};
+
+#[cfg(test)]
+mod tests;
+
+/// Reply to an optimizer input received from a message queue
+#[derive(Debug)]
+pub(crate) struct Reply {
+    /// HTTP status code equivalent of the result
+    pub(crate) status: StatusCode,
+    pub(crate) content_type: &'static str,
+    pub(crate) body: Vec<u8>,
+}
+
+/// Decode an optimizer input from a message, run the optimizer, and encode the
+/// solution.
+///
+/// The input's format comes from `content_type`, defaulting to JSON. The
+/// solution is encoded in the format from `accept`, defaulting to the input's
+/// format. Like HTTP error responses, errors are always JSON.
+pub(crate) async fn process(
+    config: &OptimizerConfig,
+    content_type: Option<&str>,
+    accept: Option<&str>,
+    payload: &[u8],
+) -> Reply {
+    match optimize(config, content_type, accept, payload).await {
+        Ok(reply) => reply,
+        Err((status, Json(body))) => Reply {
+            status,
+            content_type: Format::Json.content_type(),
+            body: serde_json::to_vec(&body).unwrap_or_default(),
+        },
+    }
+}
+
+async fn optimize(
+    config: &OptimizerConfig,
+    content_type: Option<&str>,
+    accept: Option<&str>,
+    payload: &[u8],
+) -> Result<Reply, OptimizeError> {
+    let format = match content_type {
+        Some(content_type) => {
+            Format::from_media_type(content_type).ok_or_else(unsupported_media_type)?
+        }
+        None => Format::Json,
+    };
+    let accept = accept.and_then(Format::from_media_type).unwrap_or(format);
+
+    let input: OptimizerInput = format.decode_request(payload)?;
+    let output = server::run_optimizer(config, input).await?;
+
+    Ok(Reply {
+        status: StatusCode::OK,
+        content_type: accept.content_type(),
+        body: accept.encode_response(output)?,
+    })
+}
@@ -0,0 +1,53 @@
+use super::*;
+use structopt::StructOpt;
+
+use crate::Opt;
+
+static INPUT: &str = r#"
+    {
+        "method": "guillotine",
+        "cutWidth": 2,
+        "stockPieces": [
+            { "width": 48, "length": 96, "patternDirection": "none", "price": 0 }
+        ],
+        "cutPieces": [
+            { "externalId": 1, "width": 10, "length": 30, "patternDirection": "none", "canRotate": true }
+        ]
+    }
+"#;
+
+fn config() -> OptimizerConfig {
+    OptimizerConfig::from(&Opt::from_iter(&["cut-optimizer-2d-server"]))
+}
+
+#[tokio::test]
+async fn process_should_return_solution() {
+    let reply = process(&config(), None, None, INPUT.as_bytes()).await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert_eq!(reply.content_type, "application/json");
+    let solution: serde_json::Value = serde_json::from_slice(&reply.body).unwrap();
+    assert_eq!(solution["stockPieces"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn process_should_encode_solution_as_accepted_format() {
+    let reply = process(
+        &config(),
+        Some("application/json"),
+        Some("application/cbor"),
+        INPUT.as_bytes(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert_eq!(reply.content_type, "application/cbor");
+}
+
+#[tokio::test]
+async fn process_should_reject_unsupported_content_type() {
+    let reply = process(&config(), Some("text/csv"), None, b"a,b").await;
+
+    assert_eq!(reply.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(reply.content_type, "application/json");
+}
@@ -0,0 +1,89 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tracing::info;
+
+#[cfg(test)]
+mod tests;
+
+/// Delay before the first reconnection attempt
+const INITIAL_DELAY: Duration = Duration::from_millis(100);
+
+/// Exponential backoff between reconnection attempts, jittered so that many
+/// servers don't reconnect to a recovering service all at once
+#[derive(Debug)]
+pub(crate) struct Backoff {
+    attempts: u32,
+    max: Duration,
+}
+
+impl Backoff {
+    /// Backoff whose delays grow up to `max`
+    pub(crate) fn new(max: Duration) -> Self {
+        Self { attempts: 0, max }
+    }
+
+    /// Wait before the next attempt
+    pub(crate) async fn wait(&mut self) {
+        tokio::time::sleep(delay(self.attempts, self.max)).await;
+        self.attempts = self.attempts.saturating_add(1);
+    }
+
+    /// Start over from the shortest delay, such as after connecting
+    pub(crate) fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+/// Delay before a reconnection attempt after `attempts` failed ones: doubling
+/// up to `max`, then a random amount between half of that and all of it
+pub(crate) fn delay(attempts: u32, max: Duration) -> Duration {
+    let ceiling = INITIAL_DELAY
+        .checked_mul(1 << attempts.min(31))
+        .map_or(max, |delay| delay.min(max));
+    let fraction = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+    ceiling.mul_f64(0.5 + fraction / 2.0)
+}
+
+/// Addresses a set of hosts resolve to, sorted so they can be compared
+pub(crate) async fn resolve(hosts: &[(String, u16)]) -> io::Result<Vec<SocketAddr>> {
+    let mut addresses = Vec::new();
+    for (host, port) in hosts {
+        addresses.extend(tokio::net::lookup_host((host.as_str(), *port)).await?);
+    }
+    addresses.sort_unstable();
+    addresses.dedup();
+    Ok(addresses)
+}
+
+/// Wait until the hosts resolve to other addresses than `addresses`, checking
+/// every `interval`, so a connection can follow a service that failed over.
+/// Never finishes without an interval or addresses to compare against.
+///
+/// Failed lookups are ignored, since a DNS outage alone is no reason to drop a
+/// working connection.
+pub(crate) async fn changed(
+    hosts: &[(String, u16)],
+    addresses: &[SocketAddr],
+    interval: Option<Duration>,
+) {
+    let interval = match interval {
+        Some(interval) if !addresses.is_empty() => interval,
+        _ => return futures::future::pending().await,
+    };
+
+    loop {
+        tokio::time::sleep(interval).await;
+        if let Ok(resolved) = resolve(hosts).await {
+            if resolved != addresses {
+                info!(
+                    "{:?} now resolves to {:?} instead of {:?}",
+                    hosts, resolved, addresses
+                );
+                return;
+            }
+        }
+    }
+}
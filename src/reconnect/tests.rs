@@ -0,0 +1,48 @@
+use super::*;
+
+#[test]
+fn delay_should_double_up_to_max_with_jitter() {
+    let max = Duration::from_secs(5);
+    for _ in 0..100 {
+        let first = delay(0, max);
+        assert!(first >= INITIAL_DELAY / 2 && first <= INITIAL_DELAY);
+
+        let fourth = delay(3, max);
+        assert!(fourth >= INITIAL_DELAY * 4 && fourth <= INITIAL_DELAY * 8);
+
+        let capped = delay(u32::MAX, max);
+        assert!(capped >= max / 2 && capped <= max);
+    }
+}
+
+#[tokio::test]
+async fn changed_should_finish_when_addresses_differ() {
+    let hosts = [("localhost".to_string(), 4222)];
+    let addresses = resolve(&hosts).await.unwrap();
+    assert!(!addresses.is_empty());
+    let interval = Some(Duration::from_millis(10));
+
+    let unchanged = changed(&hosts, &addresses, interval);
+    assert!(tokio::time::timeout(Duration::from_millis(100), unchanged)
+        .await
+        .is_err());
+
+    let failed_over = ["192.0.2.1:4222".parse().unwrap()];
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        changed(&hosts, &failed_over, interval),
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn changed_should_wait_forever_without_an_interval() {
+    let hosts = [("localhost".to_string(), 4222)];
+    let addresses = ["192.0.2.1:4222".parse().unwrap()];
+
+    let never = changed(&hosts, &addresses, None);
+    assert!(tokio::time::timeout(Duration::from_millis(50), never)
+        .await
+        .is_err());
+}
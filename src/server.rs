@@ -1,34 +1,120 @@
 use axum::error_handling::HandleErrorLayer;
-use axum::{extract, routing::post, Json, Router};
-use cut_optimizer_2d::{CutPiece, Optimizer, Solution, StockPiece};
-use http::{Method, StatusCode, Uri};
+use axum::extract::Extension;
+use axum::response::{Headers, Html, IntoResponse};
+use axum::routing::{get, post};
+use axum::{AddExtensionLayer, Json, Router};
+use cut_optimizer_2d::{CutPiece, Optimizer, PatternDirection, Solution, StockPiece};
+use futures::future::join_all;
+use http::header::{HeaderName, ACCEPT, ACCEPT_LANGUAGE, CONTENT_TYPE};
+use http::{HeaderMap, Method, StatusCode, Uri};
 use hyper::Body;
+use rayon::prelude::*;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
-use std::net::SocketAddr;
-use std::time::Duration;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::oneshot;
+use tokio_rustls::rustls::ServerConfig;
 use tower::{BoxError, ServiceBuilder};
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
 use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 use tower_http::trace::TraceLayer;
 use tracing::error;
 
-use crate::Opt;
+use crate::proto::RectFields;
+use crate::{tls, Opt};
+use backpressure::BackpressureLayer;
+use cancel::Cancellation;
+use catalog::Catalogs;
+use compute::ComputePool;
+pub(crate) use compute::PanicPolicy;
+use defect::{Defects, MAX_DEFECTS};
+use expression::Dimension;
+use format::{Encoded, Negotiated};
+use health::{Component, Health};
+pub(crate) use metrics::{CutMetrics, QualityWeights, SheetCost, SheetStats};
+use problem::ProblemLayer;
+use quantity::Expansion;
+pub(crate) use ranking::Objective;
+use scheduler::Eco;
+pub(crate) use scheduler::EcoHours;
+pub(crate) use sequence::{CutDirection, PanelKind};
+use store::Store;
+use translation::Translations;
+use validation::{ValidationError, MAX_SECONDS, MAX_SEEDS};
+
+mod backpressure;
+mod cancel;
+mod catalog;
+mod compute;
+mod defect;
+pub(crate) mod expression;
+mod filler;
+pub(crate) mod format;
+mod health;
+mod lint;
+mod material;
+mod metadata;
+mod metrics;
+mod openapi;
+mod pin;
+mod preset;
+mod problem;
+mod quantity;
+mod ranking;
+mod rpc;
+mod scheduler;
+mod sequence;
+mod store;
+mod translation;
+mod validation;
 
 #[cfg(test)]
 mod tests;
 
-/// Run optimizer server
-pub(crate) async fn serve(socket_addr: SocketAddr, opt: &Opt) {
-    // run it with hyper on localhost:3000
-    hyper::Server::bind(&socket_addr)
-        .serve(app(opt).into_make_service())
-        .await
-        .unwrap();
+/// Run optimizer server, terminating TLS with `tls_config` if it's given
+pub(crate) async fn serve(
+    listeners: Vec<TcpListener>,
+    opt: &Opt,
+    tls_config: Option<Arc<ServerConfig>>,
+) {
+    let app = app(opt);
+
+    if let Some(config) = tls_config {
+        let handshake_timeout = Duration::from_secs(opt.tls_handshake_timeout);
+        let servers = listeners
+            .into_iter()
+            .map(|listener| tls::serve(listener, config.clone(), handshake_timeout, app.clone()));
+        join_all(servers).await;
+        return;
+    }
+
+    let servers = listeners.into_iter().map(|listener| {
+        hyper::Server::from_tcp(listener)
+            .expect("Couldn't serve on listener")
+            .serve(app.clone().into_make_service())
+    });
+    for result in join_all(servers).await {
+        result.unwrap();
+    }
 }
 
 fn app(opt: &Opt) -> Router<Body> {
+    let config = OptimizerConfig::from(opt);
     let middleware_stack = ServiceBuilder::new()
+        // Compress response bodies, errors from the layers below included
+        .layer(compression_layer(opt))
+        // Describe errors as problem details for clients that accept them
+        .layer(ProblemLayer::new(&opt.problem_type_base))
+        // Tell clients how busy the server is, shed requests included
+        .layer(BackpressureLayer::new(
+            opt.max_requests,
+            config.compute.clone(),
+        ))
         .layer(HandleErrorLayer::new(handle_error))
         // Return an error after 30 seconds
         .timeout(Duration::from_secs(opt.timeout))
@@ -38,28 +124,519 @@ fn app(opt: &Opt) -> Router<Body> {
         .concurrency_limit(opt.max_requests)
         // Tracing
         .layer(TraceLayer::new_for_http())
-        // Compress response bodies
-        .layer(CompressionLayer::new());
+        // Make server options available to the optimizer
+        .layer(AddExtensionLayer::new(config));
+
+    let mut router = Router::new()
+        .nest("/v1", v1())
+        // Unversioned routes are aliases for the current default version
+        .merge(v1())
+        .route("/openapi.json", get(openapi::openapi_json))
+        .route("/schema", get(openapi::json_schema))
+        .route("/metrics", get(prometheus_metrics))
+        .route("/readyz", get(health::readyz));
+
+    if opt.enable_docs {
+        router = router
+            .route("/docs", get(openapi::docs))
+            .route("/docs/rapidoc-min.js", get(openapi::docs_script));
+    }
+
+    if opt.enable_ui {
+        router = router.route("/", get(ui));
+    }
+
+    if opt.catalog_dir.is_some() {
+        router = router.route(
+            "/catalogs/:name/stock",
+            get(catalog::get_stock)
+                .put(catalog::put_stock)
+                .post(catalog::post_stock)
+                .delete(catalog::delete_stock),
+        );
+    }
+
+    if opt.preset_dir.is_some() {
+        router = router.route(
+            "/presets/:name",
+            get(preset::get_preset)
+                .put(preset::put_preset)
+                .delete(preset::delete_preset),
+        );
+    }
+
+    let router = router.layer(middleware_stack);
 
+    // Answer CORS preflight requests and add CORS headers to all responses
+    match cors_layer(opt) {
+        Some(cors_layer) => router.layer(cors_layer),
+        None => router,
+    }
+}
+
+/// Routes for version 1 of the API. New versions with incompatible input
+/// schemas get their own function and are nested under their own prefix.
+fn v1() -> Router<Body> {
     Router::new()
         .route("/optimize", post(optimize))
-        .layer(middleware_stack)
+        .route("/rpc", post(rpc::rpc))
+}
+
+/// Allow cross-origin requests from the configured origins, if any
+fn cors_layer(opt: &Opt) -> Option<CorsLayer> {
+    if opt.cors_origins.is_empty() {
+        return None;
+    }
+
+    let allow_origin = if opt.cors_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(opt.cors_origins.iter().cloned())
+    };
+
+    Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+            .allow_headers([CONTENT_TYPE, ACCEPT])
+            .expose_headers([
+                HeaderName::from_static(backpressure::QUEUE_DEPTH),
+                HeaderName::from_static(backpressure::CONCURRENCY_REMAINING),
+                HeaderName::from_static(backpressure::SUGGESTED_DELAY),
+            ]),
+    )
+}
+
+/// Compress response bodies with the configured algorithms, level, and minimum size
+fn compression_layer(opt: &Opt) -> CompressionLayer<impl Predicate> {
+    let enabled = |algorithm| {
+        !opt.no_compression && opt.compression_algorithms.iter().any(|a| a == algorithm)
+    };
+
+    CompressionLayer::new()
+        .gzip(enabled("gzip"))
+        .deflate(enabled("deflate"))
+        .br(enabled("br"))
+        .zstd(enabled("zstd"))
+        .quality(opt.compression_level)
+        .compress_when(
+            SizeAbove::new(opt.compression_min_size)
+                .and(NotForContentType::GRPC)
+                .and(NotForContentType::IMAGES),
+        )
 }
 
-/// Run optimizer in a thread pool
 async fn optimize(
-    extract::Json(payload): extract::Json<OptimizerInput>,
-) -> Result<Json<Solution>, OptimizeError> {
+    Extension(config): Extension<OptimizerConfig>,
+    Negotiated { accept, mut body }: Negotiated<OptimizerInput>,
+    // Taken after the body, which needs the headers too
+    headers: HeaderMap,
+) -> Result<Encoded<Optimized>, OptimizeError> {
+    if body.language.is_none() {
+        body.language = accept_language(&headers);
+    }
+    Ok(Encoded(accept, run_optimizer(&config, body).await?))
+}
+
+/// Most preferred language in an `Accept-Language` header, other than `*`
+fn accept_language(headers: &HeaderMap) -> Option<String> {
+    let header = headers.get(ACCEPT_LANGUAGE)?.to_str().ok()?;
+    let mut languages: Vec<(&str, f64)> = header
+        .split(',')
+        .filter_map(|language| {
+            let mut parts = language.split(';').map(str::trim);
+            let tag = parts.next().filter(|tag| !tag.is_empty() && *tag != "*")?;
+            let quality = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse().ok())?;
+            (quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    // Stable, so equally preferred languages keep their order
+    languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+    languages.first().map(|(tag, _)| tag.to_string())
+}
+
+/// Serve the web UI for building cut lists
+async fn ui() -> Html<&'static str> {
+    Html(include_str!("server/ui.html"))
+}
+
+/// Serve server metrics in the Prometheus text format
+async fn prometheus_metrics(Extension(config): Extension<OptimizerConfig>) -> impl IntoResponse {
+    (
+        Headers([(CONTENT_TYPE, "text/plain; version=0.0.4")]),
+        config.compute.metrics(),
+    )
+}
+
+/// Server options that affect how the optimizer runs
+#[derive(Debug, Clone)]
+pub(crate) struct OptimizerConfig {
+    /// How long a request may take before it's timed out
+    pub(crate) timeout: Duration,
+
+    /// Pool that optimizer jobs run on
+    pub(crate) compute: Arc<ComputePool>,
+
+    /// Largest stock or cut piece dimension accepted
+    pub(crate) max_dimension: Option<usize>,
+
+    /// Most calls accepted in a JSON-RPC batch
+    pub(crate) max_batch_size: usize,
+
+    /// Stock catalogs that inputs can refer to, if enabled
+    pub(crate) catalogs: Option<Arc<Catalogs>>,
+
+    /// Presets that inputs can refer to, if enabled
+    pub(crate) presets: Option<Arc<Store>>,
+
+    /// Translations of error and warning messages, if enabled
+    pub(crate) translations: Option<Arc<Translations>>,
+
+    /// Which of the optional components are failing
+    pub(crate) health: Arc<Health>,
+}
+
+impl From<&Opt> for OptimizerConfig {
+    fn from(opt: &Opt) -> Self {
+        let health = Arc::new(Health::default());
+        Self {
+            timeout: Duration::from_secs(opt.timeout),
+            compute: Arc::new(ComputePool::new(
+                opt.compute_threads,
+                opt.max_cpu_seconds.map(Duration::from_secs_f64),
+                opt.panic_policy,
+                opt.time_slice_seconds.map(Duration::from_secs_f64),
+                opt.compute_cpus.clone(),
+                opt.eco_hours.map(|hours| Eco {
+                    hours,
+                    slots: opt.eco_threads,
+                }),
+            )),
+            max_dimension: opt.max_dimension,
+            max_batch_size: opt.max_batch_size,
+            catalogs: opt
+                .catalog_dir
+                .clone()
+                .map(|dir| Arc::new(Catalogs::new(dir, health.clone()))),
+            presets: opt.preset_dir.clone().map(|dir| {
+                Arc::new(Store::new(
+                    dir,
+                    "preset",
+                    Component::Presets,
+                    health.clone(),
+                ))
+            }),
+            translations: opt
+                .translation_dir
+                .clone()
+                .map(|dir| Arc::new(Translations::new(dir, opt.language.clone(), health.clone()))),
+            health,
+        }
+    }
+}
+
+/// Run optimizer in a thread pool. Solutions warn about the optional
+/// components that are failing.
+pub(crate) async fn run_optimizer(
+    config: &OptimizerConfig,
+    input: OptimizerInput,
+) -> Result<Optimized, OptimizeError> {
+    let translations = config.translations.as_deref();
+    let language = input.language.clone();
+    match optimize_materials(config, input).await {
+        Ok(mut optimized) => {
+            for output in optimized.outputs_mut() {
+                if let Some(translations) = translations {
+                    translations
+                        .warnings(language.as_deref(), &mut output.warnings)
+                        .await;
+                }
+                // After translating, so a catalog that just failed is warned
+                // about
+                let mut degraded = config.health.warnings();
+                if let (Some(translations), false) = (translations, degraded.is_empty()) {
+                    translations
+                        .warnings(language.as_deref(), &mut degraded)
+                        .await;
+                }
+                output.warnings.extend(degraded);
+            }
+            Ok(optimized)
+        }
+        Err(mut error) => {
+            if let Some(translations) = translations {
+                translations.error(language.as_deref(), &mut error).await;
+            }
+            Err(error)
+        }
+    }
+}
+
+/// Optimize an input, or each of its materials on its own if it has any
+async fn optimize_materials(
+    config: &OptimizerConfig,
+    mut input: OptimizerInput,
+) -> Result<Optimized, OptimizeError> {
+    preset::resolve(config, &mut input).await?;
+    catalog::resolve(config, &mut input).await?;
+    if material::has_materials(&input) {
+        return material::optimize(config, input).await;
+    }
+    let output = optimize_input(config, input).await?;
+    Ok(Optimized::Solution(Box::new(output)))
+}
+
+/// Optimize an input whose preset and stock catalog are resolved
+async fn optimize_input(
+    config: &OptimizerConfig,
+    input: OptimizerInput,
+) -> Result<OptimizerOutput, OptimizeError> {
+    let cut_pieces = input.resolve_cut_pieces().map_err(invalid_input)?;
+    let validation_errors = validation::validate(&input, &cut_pieces, config.max_dimension);
+    if !validation_errors.is_empty() {
+        return Err(invalid_input(validation_errors));
+    }
+    let warnings = lint::lint(&input, &cut_pieces);
+
+    let start = Instant::now();
+    let max_seconds_deadline = input
+        .max_seconds
+        .map(|max_seconds| start + Duration::from_secs_f64(max_seconds));
+    // Leave time to respond with what's finished before the request times out
+    let best_effort_deadline = input
+        .best_effort
+        .unwrap_or(false)
+        .then(|| start + config.timeout * 9 / 10);
+    let deadline = match (max_seconds_deadline, best_effort_deadline) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    let job = config.compute.job();
+    let cancellation = Cancellation::new(deadline, Some(job.clone()));
+
+    // Stop the optimizer if this future is dropped before it finishes, such as
+    // when the client disconnects or the request times out
+    let _cancel_on_drop = cancellation.cancel_on_drop();
+
     let (tx, rx) = oneshot::channel();
+    let stock_pieces = input.stock_pieces();
+    let ranked_stock_pieces = input.ranked_stock_pieces();
+    let allow_partial = input.allow_partial.unwrap_or(false);
+    let mut expansion = Expansion::new(&cut_pieces, input.cut_piece_quantities());
+    for (i, cut_piece) in input.cut_pieces.iter().enumerate() {
+        if cut_piece.optional {
+            expansion.make_optional(i);
+        }
+    }
+    let pinned = pin::placements(&input, &cut_pieces, &mut expansion);
+    if allow_partial {
+        for i in validation::non_fitting(&input, &cut_pieces) {
+            expansion.leave_out(i);
+        }
+    }
+    let mut indexed_groups =
+        input.cut_width_groups(ranked_stock_pieces.iter().copied().enumerate().collect());
+    // Sheets with pins have to be in every solution, so only their group of
+    // stock pieces is optimized
+    if !input.pins.is_empty() {
+        indexed_groups.retain(|(_, group)| {
+            group
+                .iter()
+                .any(|(i, _)| input.pins.iter().any(|pin| pin.stock_piece == *i))
+        });
+    }
+    let defects = Defects::new(&input, &pinned);
+    let split_groups: Vec<_> = indexed_groups
+        .iter()
+        .map(|(_, group)| defects.split(group))
+        .collect();
+    let cut_width_groups: Vec<_> = indexed_groups
+        .into_iter()
+        .map(|(cut_width, group)| {
+            let group: Vec<_> = group.into_iter().map(|(_, sp)| sp).collect();
+            (cut_width, group)
+        })
+        .collect();
+    let objective = input.objective.unwrap_or_default();
 
-    rayon::spawn(move || {
-        let method = payload.method;
-        let optimizer: Optimizer = payload.into();
-        let result = match method {
-            OptimizeMethod::Guillotine => optimizer.optimize_guillotine(|_| {}),
-            OptimizeMethod::Nested => optimizer.optimize_nested(|_| {}),
+    config.compute.spawn(move || {
+        let results = loop {
+            let results: Vec<_> = input
+                .seeds()
+                .into_par_iter()
+                .map(|seed| {
+                    let groups = cut_width_groups.iter().zip(&split_groups);
+                    let runs = groups.map(|((cut_width, group), split_group)| {
+                        let score = |solution: &Solution| {
+                            ranking::Score::new(solution, group, *cut_width, objective)
+                        };
+                        optimize_with_method(
+                            &input.optimizer(split_group, &expansion.cut_pieces, seed, *cut_width),
+                            // Stock pieces split around defects aren't put
+                            // back together yet, so price their regions
+                            |solution| {
+                                ranking::Score::new(solution, split_group, *cut_width, objective)
+                            },
+                            input.method(),
+                            input.early_stop_utilization,
+                            &cancellation,
+                        )
+                        .and_then(|(method, mut solution)| {
+                            if !defects.reassemble(&mut solution) {
+                                return Err(RunError::DefectOverlap);
+                            }
+                            if !input.uses_required_stock(&solution) {
+                                return Err(RunError::RequiredStockUnused);
+                            }
+                            Ok(Candidate {
+                                seed,
+                                method,
+                                cut_width: *cut_width,
+                                score: score(&solution),
+                                solution,
+                            })
+                        })
+                    });
+                    ranking::best(runs, |candidate| candidate.score)
+                })
+                .collect();
+
+            // A cut piece can fit a stock piece on its own and still not be
+            // placed, such as when stock sizes can't be mixed, so leave it out
+            // and try again
+            match no_fit(&results).and_then(|cut_piece| expansion.source_index(cut_piece)) {
+                Some(i) if allow_partial => expansion.leave_out(i),
+                _ => break results,
+            }
         };
-        if tx.send(result).is_err() {
+
+        let cpu_seconds = job.cpu_time().as_secs_f64();
+        let cpu_limit_exceeded = job.finish();
+        let compute_stats = input
+            .include_compute_stats
+            .unwrap_or(false)
+            .then(|| job.compute_stats(start.elapsed(), expansion.cut_pieces.len()));
+
+        let partial = results
+            .iter()
+            .any(|result| matches!(result, Err(RunError::Cancelled)));
+
+        let scores = input.include_scores.unwrap_or(false).then(|| {
+            results
+                .iter()
+                .flatten()
+                .map(|candidate| SeedScore {
+                    seed: candidate.seed,
+                    score: candidate.score,
+                })
+                .collect()
+        });
+
+        let result = ranking::ranked(results, |candidate| candidate.score).map(|ranked| {
+            let mut ranked = match input.solution_count {
+                Some(_) => distinct(ranked),
+                None => ranked,
+            }
+            .into_iter();
+            let mut best = ranked.next().expect("Ranking should keep a result");
+            let fillers = expansion.fillers();
+            filler::fill(&mut best.solution, &fillers, best.cut_width);
+            let cut_piece_groups = expansion.restore(&mut best.solution);
+            input.restore_trim(&mut best.solution);
+            let alternatives = input.solution_count.map(|count| {
+                ranked
+                    .take(count.saturating_sub(1))
+                    .map(|mut candidate| {
+                        filler::fill(&mut candidate.solution, &fillers, candidate.cut_width);
+                        expansion.restore(&mut candidate.solution);
+                        input.restore_trim(&mut candidate.solution);
+                        Alternative {
+                            metrics: metrics::cut_metrics(
+                                &candidate.solution,
+                                candidate.cut_width,
+                                input.feed_rate,
+                            ),
+                            solution: candidate.solution,
+                            method: candidate.method,
+                            random_seed: candidate.seed,
+                            score: candidate.score,
+                        }
+                    })
+                    .collect()
+            });
+            let allowances: Vec<_> = input
+                .cut_pieces
+                .iter()
+                .map(|cut_piece| cut_piece.edge_allowance)
+                .collect();
+            let edge_allowances = allowances
+                .iter()
+                .any(Option::is_some)
+                .then(|| metrics::edge_allowances(&best.solution, &cut_piece_groups, &allowances));
+            let metadata = metadata::has_metadata(&input)
+                .then(|| metadata::placed(&input, &best.solution, &cut_piece_groups));
+            let cut_sequence = (best.method == OptimizeMethod::Guillotine)
+                .then(|| sequence::sequence(&best.solution, best.cut_width))
+                .flatten();
+            let cut_metrics = metrics::cut_metrics(&best.solution, best.cut_width, input.feed_rate);
+            let quality = metrics::quality(
+                &best.solution,
+                &cut_pieces,
+                &cut_metrics,
+                &input.quality_weights.unwrap_or_default(),
+            );
+            OptimizerOutput {
+                stats: metrics::solution_stats(
+                    &best.solution,
+                    &stock_pieces,
+                    &input.remnants(),
+                    best.cut_width,
+                ),
+                offcuts: input.offcut_size().map(|(min_width, min_length)| {
+                    metrics::offcuts(&best.solution, min_width, min_length)
+                }),
+                cost: metrics::cost(
+                    &best.solution,
+                    &stock_pieces,
+                    input.price_per_area,
+                    input.currency.clone(),
+                ),
+                metrics: cut_metrics,
+                quality,
+                solution: best.solution,
+                method: best.method,
+                random_seed: best.seed,
+                scores,
+                partial,
+                cpu_seconds,
+                compute_stats,
+                edge_allowances,
+                metadata,
+                cut_sequence,
+                alternatives,
+                cut_piece_groups: input
+                    .cut_pieces
+                    .iter()
+                    .any(|cut_piece| cut_piece.quantity.is_some() || cut_piece.optional)
+                    .then_some(cut_piece_groups),
+                unplaced_pieces: allow_partial.then(|| expansion.unplaced()),
+                warnings,
+            }
+        });
+        let result = result.map_err(|e| match e {
+            RunError::Cancelled if cpu_limit_exceeded => RunError::CpuLimitExceeded,
+            RunError::Optimizer(cut_optimizer_2d::Error::NoFitForCutPiece(cut_piece)) => {
+                RunError::Optimizer(cut_optimizer_2d::Error::NoFitForCutPiece(
+                    expansion.source(&cut_piece),
+                ))
+            }
+            e => e,
+        });
+
+        // The receiver is expected to be gone if the run was cancelled
+        if tx.send(result).is_err() && !cancellation.is_cancelled() {
             error!("Error: receiver side of channel closed before the result could be sent.");
         }
     });
@@ -72,44 +649,796 @@ async fn optimize(
         )
     })?;
 
-    let solution = result.map_err(|e| match e {
-        cut_optimizer_2d::Error::NoFitForCutPiece(cut_piece) => error_with_data(
-            StatusCode::UNPROCESSABLE_ENTITY,
-            "Cut piece doesn't fit in any stock pieces",
-            cut_piece,
+    let output = result.map_err(|e| match e {
+        RunError::Optimizer(cut_optimizer_2d::Error::NoFitForCutPiece(cut_piece)) => {
+            error_with_data(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Cut piece doesn't fit in any stock pieces",
+                cut_piece,
+            )
+        }
+        RunError::Cancelled => error(
+            StatusCode::REQUEST_TIMEOUT,
+            "Optimizer didn't finish in time",
+        ),
+        RunError::CpuLimitExceeded => error(
+            StatusCode::REQUEST_TIMEOUT,
+            "Optimizer used more than the CPU time limit",
+        ),
+        RunError::RequiredStockUnused => invalid_input(vec![ValidationError::new(
+            "unsatisfiableMustUse",
+            "stockPieces".to_string(),
+            "No solution found that uses every stock piece with mustUse; try more seeds"
+                .to_string(),
+        )]),
+        RunError::DefectOverlap => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Optimizer placed a cut piece over a defect",
         ),
     })?;
 
-    Ok(Json(solution))
+    Ok(output)
+}
+
+/// Solution found with one random seed
+struct Candidate {
+    seed: u64,
+    method: OptimizeMethod,
+    cut_width: usize,
+    score: ranking::Score,
+    solution: Solution,
 }
 
-#[derive(Deserialize, Debug, Clone, Copy)]
+/// Cut piece that kept every seed from finding a solution, if any
+fn no_fit(results: &[Result<Candidate, RunError>]) -> Option<&CutPiece> {
+    if results.iter().any(Result::is_ok) {
+        return None;
+    }
+    results.iter().find_map(|result| match result {
+        Err(RunError::Optimizer(cut_optimizer_2d::Error::NoFitForCutPiece(cut_piece))) => {
+            Some(cut_piece)
+        }
+        _ => None,
+    })
+}
+
+/// Drop candidates with the same layout as a better one before them
+fn distinct(candidates: Vec<Candidate>) -> Vec<Candidate> {
+    let mut layouts = Vec::new();
+    candidates
+        .into_iter()
+        .filter(|candidate| {
+            let layout = serde_json::to_value(&candidate.solution.stock_pieces).ok();
+            let is_new = !layouts.contains(&layout);
+            if is_new {
+                layouts.push(layout);
+            }
+            is_new
+        })
+        .collect()
+}
+
+/// Why an optimizer run didn't produce a solution
+#[derive(Debug)]
+enum RunError {
+    Optimizer(cut_optimizer_2d::Error),
+    Cancelled,
+    CpuLimitExceeded,
+
+    /// The solution left out stock pieces that must be used
+    RequiredStockUnused,
+
+    /// A cut piece was placed over a stock piece's defect
+    DefectOverlap,
+}
+
+/// Run the optimizer with a method. For the best method, both methods run at
+/// once, and if `early_stop_utilization` is set, the first to find a solution
+/// with at least that utilization stops the other.
+fn optimize_with_method(
+    optimizer: &Optimizer,
+    score: impl Fn(&Solution) -> ranking::Score,
+    method: OptimizeMethod,
+    early_stop_utilization: Option<f64>,
+    cancellation: &Cancellation,
+) -> Result<(OptimizeMethod, Solution), RunError> {
+    let run = |method, cancellation: &Cancellation| {
+        cancellation
+            .run(|progress| match method {
+                OptimizeMethod::Guillotine => optimizer.optimize_guillotine(progress),
+                _ => optimizer.optimize_nested(progress),
+            })
+            .ok_or(RunError::Cancelled)?
+            .map(|solution| (method, solution))
+            .map_err(RunError::Optimizer)
+    };
+
+    match method {
+        OptimizeMethod::Guillotine | OptimizeMethod::Nested => run(method, cancellation),
+        OptimizeMethod::Best => {
+            let guillotine_cancellation = cancellation.child();
+            let nested_cancellation = cancellation.child();
+            let run_and_stop_other = |method, own: &Cancellation, other: &Cancellation| {
+                let result = run(method, own);
+                if let (Ok((_, solution)), Some(threshold)) = (&result, early_stop_utilization) {
+                    if metrics::utilization(solution) >= threshold {
+                        other.cancel();
+                    }
+                }
+                result
+            };
+
+            let (guillotine, nested) = rayon::join(
+                || {
+                    run_and_stop_other(
+                        OptimizeMethod::Guillotine,
+                        &guillotine_cancellation,
+                        &nested_cancellation,
+                    )
+                },
+                || {
+                    run_and_stop_other(
+                        OptimizeMethod::Nested,
+                        &nested_cancellation,
+                        &guillotine_cancellation,
+                    )
+                },
+            );
+            ranking::best([guillotine, nested], |(_, solution)| score(solution))
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
-enum OptimizeMethod {
+pub(crate) enum OptimizeMethod {
+    /// Layouts cut with edge-to-edge cuts
     Guillotine,
+    /// Layouts that don't need edge-to-edge cuts
     Nested,
+    /// Run both methods and keep the better solution by `objective`. Also
+    /// accepted as `auto`.
+    #[serde(alias = "auto")]
+    Best,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
-struct OptimizerInput {
-    method: OptimizeMethod,
-    random_seed: Option<u64>,
-    cut_width: usize,
-    stock_pieces: Vec<StockPiece>,
-    cut_pieces: Vec<CutPiece>,
-    allow_mixed_stock_sizes: Option<bool>,
+pub(crate) struct OptimizerInput {
+    /// Name of a preset on the server that fills in what the input leaves
+    /// unset. `method` and `cutWidth` are required unless the preset sets them.
+    pub(crate) preset: Option<String>,
+
+    /// Language of error and warning messages, such as `fr-CA`, if the server
+    /// has a translation catalog for it or for its primary language. Defaults
+    /// to the request's `Accept-Language`, then to the server's default
+    /// language. Messages without a translation stay in English.
+    pub(crate) language: Option<String>,
+
+    /// Required unless the preset sets it
+    pub(crate) method: Option<OptimizeMethod>,
+    #[schemars(extend("default" = 1))]
+    pub(crate) random_seed: Option<u64>,
+
+    /// Number of seeds to try in parallel, counting up from `randomSeed`. The
+    /// best solution is returned.
+    #[schemars(range(min = 1, max = MAX_SEEDS), extend("default" = 1))]
+    pub(crate) seed_count: Option<usize>,
+
+    /// Seeds to try, overriding `randomSeed` and `seedCount`
+    #[schemars(length(min = 1, max = MAX_SEEDS))]
+    pub(crate) seeds: Option<Vec<u64>>,
+
+    /// Include the score of every seed in the solution
+    #[schemars(extend("default" = false))]
+    pub(crate) include_scores: Option<bool>,
+
+    /// For the `best` method, stop the other method once one finds a solution
+    /// with at least this utilization
+    #[schemars(extend("exclusiveMinimum" = true, "minimum" = 0, "maximum" = 1))]
+    pub(crate) early_stop_utilization: Option<f64>,
+
+    /// Include `computeStats` in the solution
+    #[schemars(extend("default" = false))]
+    pub(crate) include_compute_stats: Option<bool>,
+
+    /// Stop runs that haven't finished after this many seconds. The best
+    /// finished run is returned, or a 408 error if none finished.
+    #[schemars(extend("exclusiveMinimum" = true, "minimum" = 0, "maximum" = MAX_SECONDS))]
+    pub(crate) max_seconds: Option<f64>,
+
+    /// Cut length the saw covers per second, used to estimate
+    /// `metrics.sawingSeconds`
+    #[schemars(extend("exclusiveMinimum" = true, "minimum" = 0))]
+    pub(crate) feed_rate: Option<f64>,
+
+    /// Stop runs shortly before the request would time out and return the best
+    /// finished one, marked `partial`, instead of a 408 error
+    #[schemars(extend("default" = false))]
+    pub(crate) best_effort: Option<bool>,
+
+    pub(crate) quality_weights: Option<QualityWeights>,
+
+    /// Values of the variables used in cut piece dimension expressions
+    pub(crate) variables: Option<HashMap<String, f64>>,
+
+    /// Use stock pieces marked `isRemnant` before new ones where possible
+    #[schemars(extend("default" = false))]
+    pub(crate) prefer_remnants: Option<bool>,
+
+    pub(crate) objective: Option<Objective>,
+
+    /// Number of distinct solutions to return, counting the best one, with the
+    /// rest in `alternatives`. `seedCount` defaults to this.
+    #[schemars(range(min = 1, max = MAX_SEEDS))]
+    pub(crate) solution_count: Option<usize>,
+
+    /// Leave out cut pieces that can't be placed and list them in
+    /// `unplacedPieces` instead of failing with `noFit`
+    #[schemars(extend("default" = false))]
+    pub(crate) allow_partial: Option<bool>,
+
+    /// Report waste pieces at least this wide, in either orientation, as
+    /// `offcuts`
+    pub(crate) min_offcut_width: Option<usize>,
+
+    /// Report waste pieces at least this long, in either orientation, as
+    /// `offcuts`
+    pub(crate) min_offcut_length: Option<usize>,
+
+    /// ISO 4217 code of the prices, passed through to `cost.currency`
+    #[schemars(pattern(r"^[A-Z]{3}$"))]
+    pub(crate) currency: Option<String>,
+
+    /// Cost of each unit of stock area used, on top of stock piece prices
+    #[schemars(range(min = 0))]
+    pub(crate) price_per_area: Option<f64>,
+
+    /// Width of the blade (kerf), required unless the preset sets it
+    pub(crate) cut_width: Option<usize>,
+    #[serde(default)]
+    pub(crate) stock_pieces: Vec<InputStockPiece>,
+
+    /// Name of a stock catalog on the server whose stock pieces are added
+    /// after `stockPieces`
+    pub(crate) stock_catalog: Option<String>,
+    pub(crate) cut_pieces: Vec<InputCutPiece>,
+    #[schemars(extend("default" = true))]
+    pub(crate) allow_mixed_stock_sizes: Option<bool>,
+
+    /// Cut pieces already placed by hand, such as on a partly used sheet being
+    /// re-cut. Each takes one copy of its cut piece, and the rest are optimized
+    /// around them on one sheet of the stock piece, with kerf around each pin,
+    /// so the sheet is always in the solution. Needs `allowMixedStockSizes`,
+    /// and every stock piece with pins needs the same cut width.
+    #[serde(default)]
+    pub(crate) pins: Vec<Pin>,
+}
+
+/// Stock piece as given in the input, which may be a remnant saved from an
+/// earlier job
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[schemars(rename = "StockPiece")]
+pub(crate) struct InputStockPiece {
+    /// Width of the stock piece
+    pub(crate) width: usize,
+
+    /// Length of the stock piece
+    pub(crate) length: usize,
+    #[schemars(with = "openapi::PatternDirection")]
+    pub(crate) pattern_direction: PatternDirection,
+    pub(crate) price: usize,
+
+    /// Number of pieces available, unlimited if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) quantity: Option<usize>,
+
+    /// Whether this is a remnant saved from an earlier job
+    #[serde(default)]
+    pub(crate) is_remnant: bool,
+
+    /// Every one of this stock piece's quantity must appear in the solution,
+    /// such as boards already pulled from inventory. Fails with
+    /// `unsatisfiableMustUse` if that's impossible.
+    #[serde(default)]
+    pub(crate) must_use: bool,
+
+    /// Width trimmed off the top edge before cutting, such as an uneven
+    /// factory edge. Placements are still measured from the edges of the whole
+    /// stock piece.
+    #[serde(default)]
+    pub(crate) trim_top: usize,
+
+    /// Width trimmed off the bottom edge before cutting
+    #[serde(default)]
+    pub(crate) trim_bottom: usize,
+
+    /// Width trimmed off the left edge before cutting
+    #[serde(default)]
+    pub(crate) trim_left: usize,
+
+    /// Width trimmed off the right edge before cutting
+    #[serde(default)]
+    pub(crate) trim_right: usize,
+
+    /// Width of the blade (kerf) used on this stock piece instead of the
+    /// input's `cutWidth`. Stock pieces with different cut widths are
+    /// optimized separately and never mixed in one solution.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cut_width: Option<usize>,
+
+    /// Areas no cut piece may overlap, such as knots or damage, measured from
+    /// the corner of the whole stock piece. The usable area is cut into
+    /// defect-free regions around them before optimizing, and regions no cut
+    /// piece uses are waste pieces. Needs `allowMixedStockSizes`, and regions
+    /// can't be the same size as a stock piece without defects.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(length(max = MAX_DEFECTS))]
+    pub(crate) defects: Vec<RectFields>,
+
+    /// What the stock piece is made of. Cut pieces are only placed on stock
+    /// pieces of their own material. Once any piece has a material, every
+    /// piece needs one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) material: Option<String>,
+    /// Anything to get back with the stock piece's sheets in the solution's
+    /// `metadata`, such as an order line ID. It has to be a JSON object.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<Map<String, Value>>")]
+    pub(crate) metadata: Option<Value>,
+}
+
+impl InputStockPiece {
+    /// Area left for cut pieces once the trim margins are removed
+    pub(crate) fn usable(&self) -> StockPiece {
+        StockPiece {
+            width: self
+                .width
+                .saturating_sub(self.trim_left.saturating_add(self.trim_right)),
+            length: self
+                .length
+                .saturating_sub(self.trim_top.saturating_add(self.trim_bottom)),
+            ..self.into()
+        }
+    }
+
+    pub(crate) fn is_trimmed(&self) -> bool {
+        self.trim_top > 0 || self.trim_bottom > 0 || self.trim_left > 0 || self.trim_right > 0
+    }
+}
+
+impl From<&InputStockPiece> for StockPiece {
+    fn from(stock_piece: &InputStockPiece) -> Self {
+        Self {
+            width: stock_piece.width,
+            length: stock_piece.length,
+            pattern_direction: stock_piece.pattern_direction,
+            price: stock_piece.price,
+            quantity: stock_piece.quantity,
+        }
+    }
+}
+
+/// Cut piece as given in the input, whose dimensions may be expressions
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(rename = "CutPiece")]
+pub(crate) struct InputCutPiece {
+    pub(crate) external_id: Option<usize>,
+
+    /// Width of the cut piece
+    pub(crate) width: Dimension,
+
+    /// Length of the cut piece
+    pub(crate) length: Dimension,
+    #[schemars(with = "openapi::PatternDirection")]
+    pub(crate) pattern_direction: PatternDirection,
+    pub(crate) can_rotate: bool,
+
+    /// Number of identical pieces to cut. The solution's `cutPieceGroups` says
+    /// where each copy was placed.
+    #[schemars(range(min = 1), extend("default" = 1))]
+    pub(crate) quantity: Option<usize>,
+
+    pub(crate) edge_allowance: Option<EdgeAllowance>,
+
+    /// Place only in room left over by the required cut pieces, such as for
+    /// filler parts and spare blanks. No stock piece is added just for
+    /// optional cut pieces, and any that don't fit are left out. The solution's
+    /// `cutPieceGroups` says which copies were placed.
+    #[serde(default)]
+    pub(crate) optional: bool,
+
+    /// What the cut piece is cut from, which the stock pieces it's placed on
+    /// have to match
+    pub(crate) material: Option<String>,
+    /// Anything to get back with the cut piece's placements in the solution's
+    /// `metadata`, such as a part name or barcode. It has to be a JSON object.
+    #[serde(default)]
+    #[schemars(with = "Option<Map<String, Value>>")]
+    pub(crate) metadata: Option<Value>,
+}
+
+/// Copy of a cut piece placed by hand, such as one laid out on a partly used
+/// sheet before the rest of the job is optimized. Pins on the same stock piece
+/// are on the same sheet of it.
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Pin {
+    /// Index of the cut piece in `cutPieces`, which can't be optional
+    pub(crate) cut_piece: usize,
+
+    /// Index of the stock piece in `stockPieces`
+    pub(crate) stock_piece: usize,
+
+    /// Corner of the cut piece, measured from the corner of the whole stock
+    /// piece and within its trim
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+    #[serde(default)]
+    pub(crate) is_rotated: bool,
+}
+
+/// Extra material on each edge of a cut piece, such as for edge banding,
+/// that's added to its dimensions before optimizing. Left and right add to the
+/// width, top and bottom to the length.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub(crate) struct EdgeAllowance {
+    pub(crate) top: usize,
+    pub(crate) bottom: usize,
+    pub(crate) left: usize,
+    pub(crate) right: usize,
+}
+
+/// Output of an optimize request
+#[derive(Serialize, JsonSchema)]
+#[serde(untagged)]
+pub(crate) enum Optimized {
+    Solution(Box<OptimizerOutput>),
+
+    /// Solution for each material, if any stock piece or cut piece has a
+    /// `material`. Each material is optimized on its own, and its cut piece
+    /// indexes and paths refer to the whole input.
+    ByMaterial {
+        materials: BTreeMap<String, OptimizerOutput>,
+    },
+}
+
+impl Optimized {
+    fn outputs_mut(&mut self) -> Vec<&mut OptimizerOutput> {
+        match self {
+            Self::Solution(output) => vec![output],
+            Self::ByMaterial { materials } => materials.values_mut().collect(),
+        }
+    }
+}
+
+/// Optimized solution, along with the method that produced it
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[schemars(rename = "Solution")]
+pub(crate) struct OptimizerOutput {
+    #[serde(flatten)]
+    #[schemars(with = "openapi::Solution")]
+    pub(crate) solution: Solution,
+
+    /// Method that produced the solution
+    #[schemars(extend("enum" = ["guillotine", "nested"]))]
+    pub(crate) method: OptimizeMethod,
+
+    /// Seed that produced the solution
+    pub(crate) random_seed: u64,
+
+    /// Score of every seed that produced a solution, if `includeScores` was set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) scores: Option<Vec<SeedScore>>,
+
+    pub(crate) metrics: metrics::CutMetrics,
+
+    pub(crate) quality: metrics::Quality,
+
+    pub(crate) stats: metrics::SolutionStats,
+
+    pub(crate) cost: metrics::Cost,
+
+    /// Non-overlapping waste pieces big enough to reuse, if `minOffcutWidth`
+    /// or `minOffcutLength` was given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) offcuts: Option<Vec<metrics::Offcut>>,
+
+    /// Whether some runs were stopped early by `maxSeconds` or `bestEffort`,
+    /// so a better solution may exist
+    pub(crate) partial: bool,
+
+    /// CPU time used to find the solution, summed over every run
+    #[schemars(range(min = 0))]
+    pub(crate) cpu_seconds: f64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) compute_stats: Option<compute::ComputeStats>,
+
+    /// Where the copies of each input cut piece were placed, in the same order
+    /// as the input's `cutPieces`, if any cut piece has a `quantity` or is
+    /// `optional`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cut_piece_groups: Option<Vec<quantity::CutPieceGroup>>,
+
+    /// Edge allowances of the placed cut pieces, if any cut piece has an
+    /// `edgeAllowance`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) edge_allowances: Option<Vec<metrics::PlacedAllowance>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) metadata: Option<metadata::Metadata>,
+
+    /// Saw cuts that free the cut pieces, in the order they're made, if the
+    /// solution was optimized with the `guillotine` method. Each panel has
+    /// strips cut off it one after another, starting with rips for whole stock
+    /// pieces, then each strip is cut up the other way.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cut_sequence: Option<Vec<sequence::CutStep>>,
+
+    /// Next best solutions with distinct layouts, best first, if
+    /// `solutionCount` was given. There may be fewer than asked for if seeds
+    /// found the same layout.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) alternatives: Option<Vec<Alternative>>,
+
+    /// Cut pieces left out of the solution, every copy of each, if
+    /// `allowPartial` was set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) unplaced_pieces: Option<Vec<quantity::UnplacedPiece>>,
+
+    /// Likely mistakes in the input, with fixes where there's an obvious one.
+    /// Omitted if there are none.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) warnings: Vec<lint::Warning>,
 }
 
-impl From<OptimizerInput> for Optimizer {
-    fn from(input: OptimizerInput) -> Self {
+/// Solution other than the best one, for picking a layout that's easier to
+/// cut
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Alternative {
+    #[serde(flatten)]
+    #[schemars(with = "openapi::Solution")]
+    pub(crate) solution: Solution,
+
+    /// Method that produced the solution
+    #[schemars(extend("enum" = ["guillotine", "nested"]))]
+    pub(crate) method: OptimizeMethod,
+
+    /// Seed that produced the solution
+    pub(crate) random_seed: u64,
+    pub(crate) score: ranking::Score,
+    pub(crate) metrics: metrics::CutMetrics,
+}
+
+#[derive(Serialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SeedScore {
+    pub(crate) seed: u64,
+    #[serde(flatten)]
+    pub(crate) score: ranking::Score,
+}
+
+impl OptimizerInput {
+    /// Method to optimize with, which validation requires to be set
+    fn method(&self) -> OptimizeMethod {
+        self.method.unwrap_or(OptimizeMethod::Guillotine)
+    }
+
+    /// Cut width, which validation requires to be set
+    pub(crate) fn cut_width(&self) -> usize {
+        self.cut_width.unwrap_or_default()
+    }
+
+    /// Stock pieces with their prices as given
+    fn stock_pieces(&self) -> Vec<StockPiece> {
+        self.stock_pieces.iter().map(StockPiece::from).collect()
+    }
+
+    /// Usable area of the stock pieces, with the prices used to pick between
+    /// solutions. When preferring remnants, each new sheet costs more than
+    /// every remnant together, so solutions using fewer new sheets win. Stock
+    /// pieces that must be used are free, so using them never makes a solution
+    /// lose.
+    fn ranked_stock_pieces(&self) -> Vec<StockPiece> {
+        let premium = if self.prefer_remnants.unwrap_or(false) {
+            self.stock_pieces
+                .iter()
+                .filter(|sp| sp.is_remnant)
+                .map(|sp| sp.price.saturating_mul(sp.quantity.unwrap_or(1)))
+                .fold(1, usize::saturating_add)
+        } else {
+            0
+        };
+        self.stock_pieces
+            .iter()
+            .map(|sp| StockPiece {
+                price: if sp.must_use {
+                    0
+                } else if sp.is_remnant {
+                    sp.price
+                } else {
+                    sp.price.saturating_add(premium)
+                },
+                ..sp.usable()
+            })
+            .collect()
+    }
+
+    /// Whether a solution uses every stock piece that must be used. Sheets in
+    /// solutions are told apart by their size and pattern direction, so this
+    /// counts sheets of each such stock piece's usable size.
+    fn uses_required_stock(&self, solution: &Solution) -> bool {
+        let mut required: HashMap<(usize, usize, PatternDirection), usize> = HashMap::new();
+        for stock_piece in self.stock_pieces.iter().filter(|sp| sp.must_use) {
+            let usable = stock_piece.usable();
+            *required
+                .entry((usable.width, usable.length, usable.pattern_direction))
+                .or_default() += stock_piece.quantity.unwrap_or(1);
+        }
+        required
+            .into_iter()
+            .all(|((width, length, pattern_direction), count)| {
+                solution
+                    .stock_pieces
+                    .iter()
+                    .filter(|sp| {
+                        sp.width == width
+                            && sp.length == length
+                            && sp.pattern_direction == pattern_direction
+                    })
+                    .count()
+                    >= count
+            })
+    }
+
+    /// Put the trim margins back on the stock pieces of a solution found for
+    /// their usable area, offsetting the placements so they're measured from
+    /// the edges of the whole stock piece. Trimmed edges aren't waste pieces.
+    fn restore_trim(&self, solution: &mut Solution) {
+        for used in &mut solution.stock_pieces {
+            let stock_piece = self.stock_pieces.iter().find(|sp| {
+                let usable = sp.usable();
+                usable.width == used.width
+                    && usable.length == used.length
+                    && usable.pattern_direction == used.pattern_direction
+            });
+            let stock_piece = match stock_piece {
+                Some(sp) if sp.is_trimmed() => sp,
+                _ => continue,
+            };
+
+            used.width = stock_piece.width;
+            used.length = stock_piece.length;
+            for cut_piece in &mut used.cut_pieces {
+                cut_piece.x += stock_piece.trim_left;
+                cut_piece.y += stock_piece.trim_top;
+            }
+            for waste_piece in &mut used.waste_pieces {
+                let rect = RectFields::from(&*waste_piece);
+                *waste_piece = RectFields {
+                    x: rect.x + stock_piece.trim_left,
+                    y: rect.y + stock_piece.trim_top,
+                    ..rect
+                }
+                .into();
+            }
+        }
+    }
+
+    /// Remnants in the input
+    fn remnants(&self) -> Vec<StockPiece> {
+        self.stock_pieces
+            .iter()
+            .filter(|sp| sp.is_remnant)
+            .map(StockPiece::from)
+            .collect()
+    }
+
+    /// Minimum width and length of offcuts to report, if either was given
+    fn offcut_size(&self) -> Option<(usize, usize)> {
+        match (self.min_offcut_width, self.min_offcut_length) {
+            (None, None) => None,
+            (width, length) => Some((width.unwrap_or(0), length.unwrap_or(0))),
+        }
+    }
+
+    /// Seeds to run the optimizer with
+    fn seeds(&self) -> Vec<u64> {
+        if let Some(seeds) = &self.seeds {
+            return seeds.clone();
+        }
+
+        let first = self.random_seed.unwrap_or(1);
+        let count = self.seed_count.or(self.solution_count).unwrap_or(1) as u64;
+        (0..count).map(|i| first.wrapping_add(i)).collect()
+    }
+
+    /// Quantity of each cut piece
+    fn cut_piece_quantities(&self) -> Vec<usize> {
+        self.cut_pieces
+            .iter()
+            .map(|cut_piece| cut_piece.quantity.unwrap_or(1))
+            .collect()
+    }
+
+    /// Evaluate the dimensions of the cut pieces
+    fn resolve_cut_pieces(&self) -> Result<Vec<CutPiece>, Vec<ValidationError>> {
+        let variables = self.variables.clone().unwrap_or_default();
+        let mut errors = Vec::new();
+        let mut resolve = |dimension: &Dimension, path: String| {
+            dimension.resolve(&variables).unwrap_or_else(|message| {
+                errors.push(ValidationError::new("invalidExpression", path, message));
+                0
+            })
+        };
+
+        let cut_pieces = self
+            .cut_pieces
+            .iter()
+            .enumerate()
+            .map(|(i, cut_piece)| {
+                let allowance = cut_piece.edge_allowance.unwrap_or_default();
+                let width = resolve(&cut_piece.width, format!("cutPieces[{}].width", i));
+                let length = resolve(&cut_piece.length, format!("cutPieces[{}].length", i));
+                CutPiece {
+                    external_id: cut_piece.external_id,
+                    width: width
+                        .saturating_add(allowance.left)
+                        .saturating_add(allowance.right),
+                    length: length
+                        .saturating_add(allowance.top)
+                        .saturating_add(allowance.bottom),
+                    pattern_direction: cut_piece.pattern_direction,
+                    can_rotate: cut_piece.can_rotate,
+                }
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(cut_pieces)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Group the stock pieces, or values given for each of them, by the cut
+    /// width used on them, in the order each cut width first appears. Each
+    /// optimizer run has a single cut width, so every group is optimized
+    /// separately.
+    fn cut_width_groups<T>(&self, stock_pieces: Vec<T>) -> Vec<(usize, Vec<T>)> {
+        let mut groups: Vec<(usize, Vec<T>)> = Vec::new();
+        for (input, stock_piece) in self.stock_pieces.iter().zip(stock_pieces) {
+            let cut_width = input.cut_width.unwrap_or(self.cut_width());
+            match groups.iter_mut().find(|(width, _)| *width == cut_width) {
+                Some((_, group)) => group.push(stock_piece),
+                None => groups.push((cut_width, vec![stock_piece])),
+            }
+        }
+        groups
+    }
+
+    /// Build an optimizer for this input with the given stock pieces, cut
+    /// pieces, random seed, and cut width
+    fn optimizer(
+        &self,
+        stock_pieces: &[StockPiece],
+        cut_pieces: &[CutPiece],
+        random_seed: u64,
+        cut_width: usize,
+    ) -> Optimizer {
         let mut optimizer = Optimizer::new();
         optimizer
-            .set_random_seed(input.random_seed.unwrap_or(1))
-            .set_cut_width(input.cut_width)
-            .add_stock_pieces(input.stock_pieces)
-            .add_cut_pieces(input.cut_pieces)
-            .allow_mixed_stock_sizes(input.allow_mixed_stock_sizes.unwrap_or(true));
+            .set_random_seed(random_seed)
+            .set_cut_width(cut_width)
+            .add_stock_pieces(stock_pieces.iter().copied())
+            .add_cut_pieces(cut_pieces.iter().cloned())
+            .allow_mixed_stock_sizes(self.allow_mixed_stock_sizes.unwrap_or(true));
         optimizer
     }
 }
@@ -117,6 +1446,11 @@ impl From<OptimizerInput> for Optimizer {
 async fn handle_error(method: Method, uri: Uri, err: BoxError) -> OptimizeError {
     if err.is::<tower::timeout::error::Elapsed>() {
         error(StatusCode::REQUEST_TIMEOUT, "Request took too long")
+    } else if err.is::<tower::load_shed::error::Overloaded>() {
+        error(
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many requests at once; wait for the time in x-suggested-delay-ms",
+        )
     } else {
         error(
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -125,7 +1459,15 @@ async fn handle_error(method: Method, uri: Uri, err: BoxError) -> OptimizeError
     }
 }
 
-type OptimizeError = (StatusCode, Json<Value>);
+pub(crate) type OptimizeError = (StatusCode, Json<Value>);
+
+fn invalid_input(errors: Vec<ValidationError>) -> OptimizeError {
+    error_with_data(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "Invalid optimizer input",
+        errors,
+    )
+}
 
 fn error(status_code: StatusCode, message: &str) -> OptimizeError {
     (status_code, Json(json!({ "message": message })))
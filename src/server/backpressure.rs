@@ -0,0 +1,128 @@
+use axum::response::Response;
+use futures::future::BoxFuture;
+use http::header::{HeaderName, HeaderValue};
+use http::Request;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tower::{Layer, Service};
+
+use super::compute::ComputePool;
+
+pub(crate) const QUEUE_DEPTH: &str = "x-queue-depth";
+pub(crate) const CONCURRENCY_REMAINING: &str = "x-concurrency-remaining";
+pub(crate) const SUGGESTED_DELAY: &str = "x-suggested-delay-ms";
+
+/// Shortest delay suggested while the server is busy, for before any job has
+/// finished to go by
+const MIN_BUSY_DELAY: Duration = Duration::from_millis(100);
+
+/// Tell clients how busy the server is on every response, so batch clients
+/// can slow down before requests are turned away
+#[derive(Debug, Clone)]
+pub(crate) struct BackpressureLayer {
+    load: Arc<Load>,
+}
+
+#[derive(Debug)]
+struct Load {
+    max_requests: usize,
+    in_flight: AtomicUsize,
+    compute: Arc<ComputePool>,
+}
+
+impl BackpressureLayer {
+    /// Layer for a server that handles at most `max_requests` at once and runs
+    /// optimizer jobs on `compute`
+    pub(crate) fn new(max_requests: usize, compute: Arc<ComputePool>) -> Self {
+        Self {
+            load: Arc::new(Load {
+                max_requests,
+                in_flight: AtomicUsize::new(0),
+                compute,
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for BackpressureLayer {
+    type Service = Backpressure<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Backpressure {
+            inner,
+            load: self.load.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Backpressure<S> {
+    inner: S,
+    load: Arc<Load>,
+}
+
+/// Counts a request as in flight until it's dropped
+struct InFlight(Arc<Load>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<S, B> Service<Request<B>> for Backpressure<S>
+where
+    S: Service<Request<B>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        self.load.in_flight.fetch_add(1, Ordering::Relaxed);
+        let in_flight = InFlight(self.load.clone());
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            let mut response = response.await?;
+            let load = in_flight.0.clone();
+            // Count the requests still in flight once this one is done
+            drop(in_flight);
+
+            let remaining = load
+                .max_requests
+                .saturating_sub(load.in_flight.load(Ordering::Relaxed));
+            let queued = load.compute.waiting_jobs();
+            let delay = suggested_delay(remaining, queued, load.compute.average_job_time());
+
+            let headers = response.headers_mut();
+            for (name, value) in [
+                (QUEUE_DEPTH, queued as u64),
+                (CONCURRENCY_REMAINING, remaining as u64),
+                (SUGGESTED_DELAY, delay.as_millis() as u64),
+            ] {
+                headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// How long a client should wait before its next request: not at all while
+/// there's room, and otherwise about as long as the waiting jobs and one more
+/// would take
+fn suggested_delay(remaining: usize, queued: usize, average_job: Duration) -> Duration {
+    if remaining > 0 && queued == 0 {
+        return Duration::ZERO;
+    }
+    let jobs = u32::try_from(queued).unwrap_or(u32::MAX).saturating_add(1);
+    average_job.max(MIN_BUSY_DELAY).saturating_mul(jobs)
+}
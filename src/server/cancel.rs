@@ -0,0 +1,99 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::compute::Job;
+
+/// Lets a running optimizer be stopped early, either explicitly, once a
+/// deadline passes, or once its job uses too much CPU time.
+///
+/// The optimizer has no cancellation API, so it's stopped by unwinding out of
+/// its progress callback, which it calls after each epoch.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Cancellation {
+    deadline: Option<Instant>,
+    cancelled: Arc<AtomicBool>,
+
+    /// Flag of the cancellation this one was split from, if any
+    parent: Option<Arc<AtomicBool>>,
+
+    job: Option<Arc<Job>>,
+}
+
+/// Unwind payload used to stop the optimizer
+struct Cancelled;
+
+impl Cancellation {
+    pub(crate) fn new(deadline: Option<Instant>, job: Option<Arc<Job>>) -> Self {
+        Self {
+            deadline,
+            cancelled: Arc::default(),
+            parent: None,
+            job,
+        }
+    }
+
+    /// Cancellation with the same deadline and job that can be cancelled on its
+    /// own, and is also cancelled when this one is
+    pub(crate) fn child(&self) -> Self {
+        Self {
+            deadline: self.deadline,
+            cancelled: Arc::default(),
+            parent: Some(self.cancelled.clone()),
+            job: self.job.clone(),
+        }
+    }
+
+    pub(crate) fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.load(Ordering::Relaxed))
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            || self.job.as_ref().is_some_and(|job| job.exceeded_limit())
+    }
+
+    /// Cancel when the returned guard is dropped, such as when the future
+    /// awaiting the optimizer is dropped because the client went away.
+    pub(crate) fn cancel_on_drop(&self) -> CancelOnDrop {
+        CancelOnDrop(self.clone())
+    }
+
+    /// Run `f`, passing it a progress callback for the optimizer. Returns `None`
+    /// if the run was cancelled before it finished.
+    pub(crate) fn run<T>(&self, f: impl FnOnce(&dyn Fn(f64)) -> T) -> Option<T> {
+        let worker = self.job.as_ref().map(|job| job.worker());
+        let progress = |_: f64| {
+            if let Some(worker) = &worker {
+                worker.finish_epoch();
+            }
+            if self.is_cancelled() {
+                // Unlike `panic!`, this doesn't run the panic hook
+                panic::resume_unwind(Box::new(Cancelled));
+            }
+        };
+
+        match panic::catch_unwind(AssertUnwindSafe(|| f(&progress))) {
+            Ok(value) => Some(value),
+            Err(payload) if payload.is::<Cancelled>() => None,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+/// Cancels a run when dropped
+pub(crate) struct CancelOnDrop(Cancellation);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
@@ -0,0 +1,140 @@
+use axum::body::Bytes;
+use axum::extract::{Extension, Path};
+use axum::Json;
+use http::StatusCode;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use super::health::{Component, Health};
+use super::store::{self, Store};
+use super::validation::ValidationError;
+use super::{error, invalid_input, InputStockPiece, OptimizeError};
+use super::{OptimizerConfig, OptimizerInput};
+
+/// Named lists of stock pieces that inputs can refer to by `stockCatalog`,
+/// stored as one JSON file per catalog
+#[derive(Debug)]
+pub(crate) struct Catalogs {
+    store: Store,
+
+    /// Held while changing a catalog, so appends don't lose each other's
+    /// stock pieces
+    writes: Mutex<()>,
+}
+
+impl Catalogs {
+    pub(crate) fn new(dir: PathBuf, health: Arc<Health>) -> Self {
+        Self {
+            store: Store::new(dir, "stock catalog", Component::StockCatalogs, health),
+            writes: Mutex::new(()),
+        }
+    }
+
+    pub(crate) fn dir(&self) -> &std::path::Path {
+        self.store.dir()
+    }
+
+    /// Stock pieces in a catalog, or `None` if there's no such catalog
+    pub(crate) async fn get(
+        &self,
+        name: &str,
+    ) -> Result<Option<Vec<InputStockPiece>>, OptimizeError> {
+        self.store.get(name).await
+    }
+}
+
+fn catalogs(config: &OptimizerConfig) -> Result<&Catalogs, OptimizeError> {
+    config
+        .catalogs
+        .as_deref()
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Stock catalogs aren't enabled"))
+}
+
+/// Add the input's catalog's stock pieces after its own
+pub(crate) async fn resolve(
+    config: &OptimizerConfig,
+    input: &mut OptimizerInput,
+) -> Result<(), OptimizeError> {
+    let name = match &input.stock_catalog {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+
+    let unknown = |message: String| {
+        invalid_input(vec![ValidationError::new(
+            "unknownStockCatalog",
+            "stockCatalog".to_string(),
+            message,
+        )])
+    };
+    let catalogs = config
+        .catalogs
+        .as_deref()
+        .ok_or_else(|| unknown("Stock catalogs aren't enabled".to_string()))?;
+    let stock_pieces = catalogs
+        .get(name)
+        .await?
+        .ok_or_else(|| unknown(format!("No stock catalog named `{}`", name)))?;
+    input.stock_pieces.extend(stock_pieces);
+
+    Ok(())
+}
+
+/// List the stock pieces in a catalog
+pub(super) async fn get_stock(
+    Extension(config): Extension<OptimizerConfig>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<InputStockPiece>>, OptimizeError> {
+    let catalogs = catalogs(&config)?;
+    catalogs
+        .get(&name)
+        .await?
+        .map(Json)
+        .ok_or_else(|| catalogs.store.not_found())
+}
+
+/// Create or replace a catalog
+pub(super) async fn put_stock(
+    Extension(config): Extension<OptimizerConfig>,
+    Path(name): Path<String>,
+    body: Bytes,
+) -> Result<Json<Vec<InputStockPiece>>, OptimizeError> {
+    let catalogs = catalogs(&config)?;
+    let stock_pieces: Vec<InputStockPiece> = store::decode(&body)?;
+
+    let _write = catalogs.writes.lock().await;
+    catalogs.store.put(&name, &stock_pieces).await?;
+    Ok(Json(stock_pieces))
+}
+
+/// Add stock pieces to a catalog, creating it if needed
+pub(super) async fn post_stock(
+    Extension(config): Extension<OptimizerConfig>,
+    Path(name): Path<String>,
+    body: Bytes,
+) -> Result<Json<Vec<InputStockPiece>>, OptimizeError> {
+    let catalogs = catalogs(&config)?;
+    let added: Vec<InputStockPiece> = store::decode(&body)?;
+
+    let _write = catalogs.writes.lock().await;
+    let mut stock_pieces = catalogs.get(&name).await?.unwrap_or_default();
+    stock_pieces.extend(added);
+    catalogs.store.put(&name, &stock_pieces).await?;
+    Ok(Json(stock_pieces))
+}
+
+/// Delete a catalog
+pub(super) async fn delete_stock(
+    Extension(config): Extension<OptimizerConfig>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, OptimizeError> {
+    let catalogs = catalogs(&config)?;
+
+    let _write = catalogs.writes.lock().await;
+    if catalogs.store.delete(&name).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(catalogs.store.not_found())
+    }
+}
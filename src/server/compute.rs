@@ -0,0 +1,482 @@
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tracing::error;
+
+use super::scheduler::{Eco, Scheduler, Slot};
+use crate::affinity::{self, CpuSet};
+
+/// Thread pool dedicated to optimizer jobs, which keeps track of the CPU time
+/// each job uses.
+///
+/// CPU time is measured as the time workers spend running a job. Optimizer runs
+/// are single-threaded and only block to wait for their turn when time slicing,
+/// which isn't counted, so this is close to the CPU time used.
+#[derive(Debug)]
+pub(crate) struct ComputePool {
+    /// Current pool, which is replaced when restarting after a panic
+    pool: Arc<RwLock<Arc<ThreadPool>>>,
+    threads: usize,
+    max_cpu_time: Option<Duration>,
+    panic_policy: PanicPolicy,
+    stats: Arc<PoolStats>,
+
+    /// CPUs the workers are pinned to, if any
+    cpus: Option<CpuSet>,
+
+    /// Shares the CPUs between runs when time slicing
+    scheduler: Option<Arc<Scheduler>>,
+}
+
+#[derive(Debug, Default)]
+struct PoolStats {
+    active_workers: AtomicUsize,
+    jobs: AtomicU64,
+    jobs_killed: AtomicU64,
+    cpu_nanos: AtomicU64,
+    panics: AtomicU64,
+    restarts: AtomicU64,
+
+    /// Jobs that haven't had a worker yet
+    waiting_jobs: AtomicUsize,
+
+    /// Moving average of the time jobs took, from starting to finishing
+    job_nanos: AtomicU64,
+}
+
+/// What to do after an optimizer job panics
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PanicPolicy {
+    /// Keep using the pool
+    Continue,
+
+    /// Let running jobs finish on the old pool and start new jobs on a new one
+    Restart,
+
+    /// Exit the process so a supervisor can restart it
+    #[default]
+    Exit,
+}
+
+impl FromStr for PanicPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "continue" => Ok(Self::Continue),
+            "restart" => Ok(Self::Restart),
+            "exit" => Ok(Self::Exit),
+            _ => Err(format!("Unknown panic policy `{}`", s)),
+        }
+    }
+}
+
+/// Pool threads per CPU slot when time slicing, so runs of newer jobs have
+/// threads to wait for a slot on while older runs hold every slot
+const THREADS_PER_SLOT: usize = 4;
+
+/// Exit status after a panic under `PanicPolicy::Exit`, `EX_SOFTWARE` from
+/// sysexits.h
+const PANIC_EXIT_CODE: i32 = 70;
+
+fn build_pool(threads: usize, cpus: Option<CpuSet>) -> ThreadPool {
+    let mut builder = ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|i| format!("optimizer-{}", i));
+    if let Some(cpus) = cpus {
+        builder = builder.start_handler(move |_| {
+            if let Err(e) = affinity::pin_current_thread(&cpus) {
+                error!("{}", e);
+            }
+        });
+    }
+    builder
+        .build()
+        .expect("Couldn't create optimizer thread pool")
+}
+
+impl ComputePool {
+    /// Create a pool with `threads` workers, or one per CPU if `threads` is 0.
+    /// Jobs using more than `max_cpu_time` are stopped, and `panic_policy`
+    /// decides what happens after a job panics.
+    ///
+    /// With a `time_slice`, `threads` runs at most run at once, taking turns
+    /// of `time_slice` each so that jobs share the CPUs fairly. With `cpus`,
+    /// workers only run on those CPUs, and there's one per CPU if `threads` is
+    /// 0. With `eco`, fewer runs run at once during its hours.
+    pub(crate) fn new(
+        threads: usize,
+        max_cpu_time: Option<Duration>,
+        panic_policy: PanicPolicy,
+        time_slice: Option<Duration>,
+        cpus: Option<CpuSet>,
+        eco: Option<Eco>,
+    ) -> Self {
+        let threads = match (threads, &cpus) {
+            (0, Some(cpus)) => cpus.len(),
+            (threads, _) => threads,
+        };
+        let slots = || match threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            threads => threads,
+        };
+        let (threads, scheduler) = match (time_slice, eco) {
+            (Some(slice), _) => (
+                slots() * THREADS_PER_SLOT,
+                Some(Arc::new(Scheduler::new(slots(), slice, eco))),
+            ),
+            // Runs never yield, so they only wait for slots when eco mode
+            // starts
+            (None, Some(eco)) => (
+                threads,
+                Some(Arc::new(Scheduler::new(slots(), Duration::MAX, Some(eco)))),
+            ),
+            (None, None) => (threads, None),
+        };
+
+        Self {
+            pool: Arc::new(RwLock::new(Arc::new(build_pool(threads, cpus.clone())))),
+            threads,
+            max_cpu_time,
+            panic_policy,
+            stats: Arc::default(),
+            scheduler,
+            cpus,
+        }
+    }
+
+    fn current(&self) -> Arc<ThreadPool> {
+        self.pool.read().expect("Pool lock was poisoned").clone()
+    }
+
+    /// Start accounting for a new job
+    pub(crate) fn job(&self) -> Arc<Job> {
+        self.stats.jobs.fetch_add(1, Ordering::Relaxed);
+        self.stats.waiting_jobs.fetch_add(1, Ordering::Relaxed);
+        Arc::new(Job {
+            created: Instant::now(),
+            started: AtomicBool::new(false),
+            cpu_nanos: AtomicU64::new(0),
+            max_cpu_time: self.max_cpu_time,
+            stats: self.stats.clone(),
+            runs: AtomicUsize::new(0),
+            epochs: AtomicU64::new(0),
+            active_workers: AtomicUsize::new(0),
+            peak_workers: AtomicUsize::new(0),
+            threads: Mutex::default(),
+            scheduler: self.scheduler.clone(),
+        })
+    }
+
+    /// Run `f` on the pool. Parallel iterators and joins inside `f` also run on
+    /// the pool, and their panics are handled by the panic policy once they
+    /// reach `f`.
+    pub(crate) fn spawn(&self, f: impl FnOnce() + Send + 'static) {
+        let pool = self.pool.clone();
+        let stats = self.stats.clone();
+        let (threads, panic_policy) = (self.threads, self.panic_policy);
+        let cpus = self.cpus.clone();
+
+        self.current().spawn(move || {
+            if panic::catch_unwind(AssertUnwindSafe(f)).is_ok() {
+                return;
+            }
+
+            stats.panics.fetch_add(1, Ordering::Relaxed);
+            match panic_policy {
+                PanicPolicy::Continue => error!("Optimizer job panicked"),
+                PanicPolicy::Restart => {
+                    error!("Optimizer job panicked, restarting the optimizer pool");
+                    stats.restarts.fetch_add(1, Ordering::Relaxed);
+                    *pool.write().expect("Pool lock was poisoned") =
+                        Arc::new(build_pool(threads, cpus));
+                }
+                PanicPolicy::Exit => {
+                    error!("Optimizer job panicked, exiting");
+                    std::process::exit(PANIC_EXIT_CODE);
+                }
+            }
+        });
+    }
+
+    /// Jobs that haven't had a worker yet, such as while every worker is busy
+    /// with older jobs
+    pub(crate) fn waiting_jobs(&self) -> usize {
+        self.stats.waiting_jobs.load(Ordering::Relaxed)
+    }
+
+    /// Moving average of the time jobs took, or zero before any finished
+    pub(crate) fn average_job_time(&self) -> Duration {
+        Duration::from_nanos(self.stats.job_nanos.load(Ordering::Relaxed))
+    }
+
+    /// Pool statistics in the Prometheus text format
+    pub(crate) fn metrics(&self) -> String {
+        let stats = &self.stats;
+        let mut metrics = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            let _ = writeln!(metrics, "# HELP {} {}", name, help);
+            let _ = writeln!(metrics, "# TYPE {} {}", name, kind);
+            let _ = writeln!(metrics, "{} {}", name, value);
+        };
+
+        metric(
+            "cut_optimizer_compute_threads",
+            "gauge",
+            "Worker threads in the optimizer pool.",
+            self.current().current_num_threads().to_string(),
+        );
+        metric(
+            "cut_optimizer_compute_active_workers",
+            "gauge",
+            "Workers currently running an optimizer run.",
+            stats.active_workers.load(Ordering::Relaxed).to_string(),
+        );
+        metric(
+            "cut_optimizer_compute_waiting_jobs",
+            "gauge",
+            "Optimizer jobs that haven't had a worker yet.",
+            self.waiting_jobs().to_string(),
+        );
+        metric(
+            "cut_optimizer_compute_jobs_total",
+            "counter",
+            "Optimizer jobs started.",
+            stats.jobs.load(Ordering::Relaxed).to_string(),
+        );
+        metric(
+            "cut_optimizer_compute_jobs_killed_total",
+            "counter",
+            "Optimizer jobs stopped for exceeding the CPU time limit.",
+            stats.jobs_killed.load(Ordering::Relaxed).to_string(),
+        );
+        metric(
+            "cut_optimizer_compute_panics_total",
+            "counter",
+            "Optimizer jobs that panicked.",
+            stats.panics.load(Ordering::Relaxed).to_string(),
+        );
+        metric(
+            "cut_optimizer_compute_pool_restarts_total",
+            "counter",
+            "Times the optimizer pool was replaced after a panic.",
+            stats.restarts.load(Ordering::Relaxed).to_string(),
+        );
+        metric(
+            "cut_optimizer_compute_cpu_seconds_total",
+            "counter",
+            "CPU time used by optimizer jobs.",
+            (stats.cpu_nanos.load(Ordering::Relaxed) as f64 / 1e9).to_string(),
+        );
+        if let Some(scheduler) = &self.scheduler {
+            metric(
+                "cut_optimizer_compute_eco_mode",
+                "gauge",
+                "Whether eco mode is limiting the optimizer runs at once.",
+                (scheduler.is_eco() as u8).to_string(),
+            );
+            metric(
+                "cut_optimizer_compute_waiting_runs",
+                "gauge",
+                "Optimizer runs waiting for their turn on a CPU.",
+                scheduler.waiting().to_string(),
+            );
+            metric(
+                "cut_optimizer_compute_slice_yields_total",
+                "counter",
+                "Times an optimizer run gave its CPU to a waiting one after its time slice.",
+                scheduler.yields().to_string(),
+            );
+        }
+
+        metrics
+    }
+}
+
+/// CPU time accounting for one optimizer job, which may be spread over many
+/// workers
+#[derive(Debug)]
+pub(crate) struct Job {
+    created: Instant,
+
+    /// Whether a worker has started on the job
+    started: AtomicBool,
+
+    cpu_nanos: AtomicU64,
+    max_cpu_time: Option<Duration>,
+    stats: Arc<PoolStats>,
+    runs: AtomicUsize,
+    epochs: AtomicU64,
+    active_workers: AtomicUsize,
+    peak_workers: AtomicUsize,
+
+    /// Indexes of the pool threads that ran this job
+    threads: Mutex<HashSet<usize>>,
+
+    scheduler: Option<Arc<Scheduler>>,
+}
+
+/// Each finished job moves the average job time 1/`JOB_TIME_WEIGHT` of the
+/// way towards its own time
+const JOB_TIME_WEIGHT: u64 = 8;
+
+/// Rough memory each layout in a run's population uses per cut piece, for
+/// placing the piece and tracking the free space around it
+const BYTES_PER_PLACED_PIECE: u64 = 96;
+
+/// Resources the job used, if `includeComputeStats` was set
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ComputeStats {
+    /// CPU time summed over every run
+    pub(crate) cpu_ms: f64,
+
+    /// Time from starting the job to finishing its last run
+    pub(crate) wall_ms: f64,
+
+    /// Pool threads that ran at least one run
+    pub(crate) threads: usize,
+
+    /// Optimizer runs started, one per seed and method
+    pub(crate) runs: usize,
+
+    /// Genetic algorithm epochs finished over every run
+    pub(crate) epochs: u64,
+
+    /// Estimated peak memory used by runs at once. It assumes each run's
+    /// population has about as many layouts as there are cut pieces.
+    pub(crate) peak_memory_bytes: u64,
+}
+
+impl Job {
+    /// Start counting the time the current worker spends on this job, until the
+    /// returned guard is dropped. When time slicing, this waits for the worker's
+    /// turn first.
+    pub(crate) fn worker(&self) -> Worker<'_> {
+        self.stats.active_workers.fetch_add(1, Ordering::Relaxed);
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        let active_workers = self.active_workers.fetch_add(1, Ordering::Relaxed) + 1;
+        self.peak_workers
+            .fetch_max(active_workers, Ordering::Relaxed);
+        if let Some(index) = rayon::current_thread_index() {
+            self.threads.lock().unwrap().insert(index);
+        }
+
+        let slot = self.scheduler.as_deref().map(Scheduler::acquire);
+        if !self.started.swap(true, Ordering::Relaxed) {
+            self.stats.waiting_jobs.fetch_sub(1, Ordering::Relaxed);
+        }
+        Worker {
+            job: self,
+            started: Instant::now(),
+            recorded: Cell::new(Duration::ZERO),
+            slot,
+        }
+    }
+
+    pub(crate) fn cpu_time(&self) -> Duration {
+        Duration::from_nanos(self.cpu_nanos.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn exceeded_limit(&self) -> bool {
+        self.max_cpu_time
+            .is_some_and(|max_cpu_time| self.cpu_time() > max_cpu_time)
+    }
+
+    /// Finish the job once all of its runs are done, returning whether it was
+    /// stopped for exceeding the CPU time limit
+    pub(crate) fn finish(&self) -> bool {
+        let nanos = self.created.elapsed().as_nanos() as u64;
+        let _ =
+            self.stats
+                .job_nanos
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |average| {
+                    Some(match average {
+                        0 => nanos,
+                        average => average - average / JOB_TIME_WEIGHT + nanos / JOB_TIME_WEIGHT,
+                    })
+                });
+
+        let killed = self.exceeded_limit();
+        if killed {
+            self.stats.jobs_killed.fetch_add(1, Ordering::Relaxed);
+        }
+        killed
+    }
+
+    /// Resources used by the job, which started `wall_time` ago and optimized
+    /// `cut_pieces` cut pieces per run
+    pub(crate) fn compute_stats(&self, wall_time: Duration, cut_pieces: usize) -> ComputeStats {
+        let cut_pieces = cut_pieces as u64;
+        let peak_workers = self.peak_workers.load(Ordering::Relaxed) as u64;
+
+        ComputeStats {
+            cpu_ms: self.cpu_time().as_secs_f64() * 1000.0,
+            wall_ms: wall_time.as_secs_f64() * 1000.0,
+            threads: self.threads.lock().unwrap().len(),
+            runs: self.runs.load(Ordering::Relaxed),
+            epochs: self.epochs.load(Ordering::Relaxed),
+            peak_memory_bytes: peak_workers * cut_pieces * cut_pieces * BYTES_PER_PLACED_PIECE,
+        }
+    }
+
+    fn add(&self, time: Duration) {
+        let nanos = time.as_nanos() as u64;
+        self.cpu_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.stats.cpu_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        if !self.started.load(Ordering::Relaxed) {
+            self.stats.waiting_jobs.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Counts the time a worker spends on a job
+pub(crate) struct Worker<'a> {
+    job: &'a Job,
+    started: Instant,
+    recorded: Cell<Duration>,
+    slot: Option<Slot<'a>>,
+}
+
+impl Worker<'_> {
+    /// Add the time spent since the last call to the job's CPU time
+    pub(crate) fn record(&self) {
+        let elapsed = self.started.elapsed();
+        self.job.add(elapsed - self.recorded.replace(elapsed));
+    }
+
+    /// Record the time spent so far and count a finished epoch, then let a
+    /// waiting run have a turn if this one's time slice is up
+    pub(crate) fn finish_epoch(&self) {
+        self.record();
+        self.job.epochs.fetch_add(1, Ordering::Relaxed);
+        if self.slot.as_ref().is_some_and(Slot::yield_if_due) {
+            // Don't count the time spent waiting
+            self.recorded.set(self.started.elapsed());
+        }
+    }
+}
+
+impl Drop for Worker<'_> {
+    fn drop(&mut self) {
+        self.record();
+        self.job.active_workers.fetch_sub(1, Ordering::Relaxed);
+        self.job
+            .stats
+            .active_workers
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,450 @@
+use cut_optimizer_2d::{
+    PatternDirection, Rect, ResultCutPiece, ResultStockPiece, Solution, StockPiece,
+};
+
+use super::{InputStockPiece, OptimizerInput};
+use crate::proto::RectFields;
+
+/// Most defects one stock piece can have
+pub(crate) const MAX_DEFECTS: usize = 16;
+
+/// Stock pieces with defects or pinned cut pieces, which are optimized as the
+/// free regions of their usable area and put back together afterwards. Like
+/// trimmed stock pieces, they're told apart in solutions by their usable size.
+pub(crate) struct Defects {
+    pieces: Vec<DefectivePiece>,
+}
+
+struct DefectivePiece {
+    /// Index of the stock piece in `stockPieces`
+    index: usize,
+
+    width: usize,
+    length: usize,
+    pattern_direction: PatternDirection,
+
+    /// Number of these sheets, unlimited if not set
+    quantity: Option<usize>,
+
+    /// Defects and pinned cut pieces, measured from the corner of the usable
+    /// area
+    blocked: Vec<RectFields>,
+
+    /// Cut pieces pinned on the sheet, which is then the only one
+    pinned: Vec<ResultCutPiece>,
+
+    regions: Vec<RectFields>,
+}
+
+impl DefectivePiece {
+    /// Distinct region sizes, each with the indexes of the regions that size
+    fn region_sizes(&self) -> Vec<((usize, usize), Vec<usize>)> {
+        let mut sizes: Vec<((usize, usize), Vec<usize>)> = Vec::new();
+        for (i, region) in self.regions.iter().enumerate() {
+            let size = (region.width, region.length);
+            match sizes.iter_mut().find(|(s, _)| *s == size) {
+                Some((_, indexes)) => indexes.push(i),
+                None => sizes.push((size, vec![i])),
+            }
+        }
+        sizes
+    }
+
+    fn key(&self, region: &RectFields) -> (usize, usize, PatternDirection) {
+        (region.width, region.length, self.pattern_direction)
+    }
+}
+
+impl Defects {
+    /// Defects of the input's stock pieces, and the cut pieces pinned on them
+    /// by index of the stock piece. A stock piece with pins has one sheet
+    /// with them, and the rest of its quantity without.
+    pub(crate) fn new(input: &OptimizerInput, pinned: &[(usize, ResultCutPiece)]) -> Self {
+        let mut pieces = Vec::new();
+        for (index, stock_piece) in input.stock_pieces.iter().enumerate() {
+            let pins: Vec<_> = pinned
+                .iter()
+                .filter(|(i, _)| *i == index)
+                .map(|(_, cut_piece)| cut_piece.clone())
+                .collect();
+            if stock_piece.defects.is_empty() && pins.is_empty() {
+                continue;
+            }
+
+            let cut_width = stock_piece.cut_width.unwrap_or(input.cut_width());
+            let usable = stock_piece.usable();
+            let piece = |quantity, pinned: Vec<ResultCutPiece>| DefectivePiece {
+                index,
+                width: usable.width,
+                length: usable.length,
+                pattern_direction: usable.pattern_direction,
+                quantity,
+                blocked: usable_defects(stock_piece)
+                    .into_iter()
+                    .chain(pinned.iter().map(placed))
+                    .collect(),
+                regions: regions(stock_piece, &pinned, cut_width),
+                pinned,
+            };
+            let rest = if pins.is_empty() {
+                stock_piece.quantity
+            } else {
+                pieces.push(piece(Some(1), pins));
+                stock_piece.quantity.map(|q| q.saturating_sub(1))
+            };
+            if rest != Some(0) {
+                pieces.push(piece(rest, Vec::new()));
+            }
+        }
+        Self { pieces }
+    }
+
+    /// Replace the stock pieces with defects or pins by their regions, given
+    /// with their indexes in `stockPieces`. Each region gets a share of the
+    /// price by area, so using all of them costs as much as the whole stock
+    /// piece, and the stock piece's quantity for each copy of its size.
+    pub(crate) fn split(&self, stock_pieces: &[(usize, StockPiece)]) -> Vec<StockPiece> {
+        let mut split = Vec::new();
+        for (index, stock_piece) in stock_pieces {
+            let pieces: Vec<_> = self.pieces.iter().filter(|p| p.index == *index).collect();
+            if pieces.is_empty() {
+                split.push(*stock_piece);
+                continue;
+            }
+
+            for piece in pieces {
+                // Regions lie within the stock piece, so their areas add up
+                // without overflowing in 128 bits, and a share is never more
+                // than the whole price
+                let area = |region: &RectFields| region.width as u128 * region.length as u128;
+                let total: u128 = piece.regions.iter().map(area).sum();
+                let share = |region: &RectFields| {
+                    let share =
+                        (stock_piece.price as u128).saturating_mul(area(region)) / total.max(1);
+                    share.min(stock_piece.price as u128) as usize
+                };
+                let shared = piece
+                    .regions
+                    .iter()
+                    .map(share)
+                    .fold(0, usize::saturating_add);
+                for (k, ((width, length), indexes)) in piece.region_sizes().into_iter().enumerate()
+                {
+                    let price = indexes
+                        .iter()
+                        .map(|&i| share(&piece.regions[i]))
+                        .fold(0, usize::saturating_add);
+                    split.push(StockPiece {
+                        width,
+                        length,
+                        pattern_direction: stock_piece.pattern_direction,
+                        // Give what's lost to rounding to the largest region
+                        price: price.saturating_add(if k == 0 {
+                            stock_piece.price.saturating_sub(shared)
+                        } else {
+                            0
+                        }),
+                        quantity: piece.quantity.map(|q| q.saturating_mul(indexes.len())),
+                    });
+                }
+            }
+        }
+        split
+    }
+
+    /// Put the regions in a solution back together into whole stock pieces,
+    /// filling each one before starting the next, and put the pinned cut
+    /// pieces on their sheets, which are kept even if no region of them was
+    /// used. Unused regions become waste pieces, and defects are neither cut
+    /// pieces nor waste. Regions the same size may go on any stock piece that
+    /// has one.
+    ///
+    /// Returns whether every placed cut piece stays clear of the defects and
+    /// pinned cut pieces.
+    pub(crate) fn reassemble(&self, solution: &mut Solution) -> bool {
+        let mut pools: Vec<((usize, usize, PatternDirection), Vec<ResultStockPiece>)> = Vec::new();
+        for piece in &self.pieces {
+            for region in &piece.regions {
+                let key = piece.key(region);
+                if !pools.iter().any(|(k, _)| *k == key) {
+                    pools.push((key, Vec::new()));
+                }
+            }
+        }
+        let sheets = std::mem::take(&mut solution.stock_pieces);
+        for sheet in sheets {
+            let key = (sheet.width, sheet.length, sheet.pattern_direction);
+            match pools.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, pool)) => pool.push(sheet),
+                None => solution.stock_pieces.push(sheet),
+            }
+        }
+        // Take sheets in the order the optimizer used them
+        for (_, pool) in &mut pools {
+            pool.reverse();
+        }
+
+        let mut avoided = true;
+        for piece in &self.pieces {
+            let mut count = 0;
+            while piece.quantity.is_none_or(|q| count < q) {
+                let has_regions = piece.regions.iter().any(|region| {
+                    pools
+                        .iter()
+                        .any(|(k, pool)| *k == piece.key(region) && !pool.is_empty())
+                });
+                if !has_regions && (count > 0 || piece.pinned.is_empty()) {
+                    break;
+                }
+
+                let mut whole = ResultStockPiece {
+                    width: piece.width,
+                    length: piece.length,
+                    pattern_direction: piece.pattern_direction,
+                    cut_pieces: Vec::new(),
+                    waste_pieces: Vec::new(),
+                };
+                for region in &piece.regions {
+                    let sheet = pools
+                        .iter_mut()
+                        .find(|(k, _)| *k == piece.key(region))
+                        .and_then(|(_, pool)| pool.pop());
+                    match sheet {
+                        Some(sheet) => {
+                            whole
+                                .cut_pieces
+                                .extend(sheet.cut_pieces.into_iter().map(|cp| ResultCutPiece {
+                                    x: cp.x + region.x,
+                                    y: cp.y + region.y,
+                                    ..cp
+                                }));
+                            whole
+                                .waste_pieces
+                                .extend(sheet.waste_pieces.iter().map(|rect| {
+                                    let rect = RectFields::from(rect);
+                                    Rect::from(RectFields {
+                                        x: rect.x + region.x,
+                                        y: rect.y + region.y,
+                                        ..rect
+                                    })
+                                }));
+                        }
+                        None => whole.waste_pieces.push((*region).into()),
+                    }
+                }
+
+                avoided &= whole.cut_pieces.iter().all(|cp| {
+                    piece
+                        .blocked
+                        .iter()
+                        .all(|blocked| intersection(&placed(cp), blocked).is_none())
+                });
+                whole.cut_pieces.extend(piece.pinned.iter().cloned());
+                solution.stock_pieces.push(whole);
+                count += 1;
+            }
+        }
+
+        // Regions past every stock piece's quantity, which the optimizer
+        // doesn't use, would stay as they are
+        for (_, pool) in pools {
+            solution.stock_pieces.extend(pool.into_iter().rev());
+        }
+        avoided
+    }
+}
+
+/// Area a placed cut piece covers
+pub(super) fn placed(cut_piece: &ResultCutPiece) -> RectFields {
+    RectFields {
+        x: cut_piece.x,
+        y: cut_piece.y,
+        width: cut_piece.width,
+        length: cut_piece.length,
+    }
+}
+
+/// Whether a defect has an area and lies within its stock piece
+pub(super) fn within(defect: &RectFields, stock_piece: &InputStockPiece) -> bool {
+    defect.width > 0
+        && defect.length > 0
+        && defect
+            .x
+            .checked_add(defect.width)
+            .is_some_and(|right| right <= stock_piece.width)
+        && defect
+            .y
+            .checked_add(defect.length)
+            .is_some_and(|bottom| bottom <= stock_piece.length)
+}
+
+/// Defects of a stock piece that reach into its usable area, measured from
+/// the corner of the usable area. Defects outside the stock piece, which
+/// validation rejects, are left out.
+pub(super) fn usable_defects(stock_piece: &InputStockPiece) -> Vec<RectFields> {
+    let area = usable_area(stock_piece);
+    stock_piece
+        .defects
+        .iter()
+        .filter(|defect| within(defect, stock_piece))
+        .filter_map(|defect| {
+            let left = defect.x.saturating_sub(stock_piece.trim_left);
+            let top = defect.y.saturating_sub(stock_piece.trim_top);
+            let right = (defect.x + defect.width).saturating_sub(stock_piece.trim_left);
+            let bottom = (defect.y + defect.length).saturating_sub(stock_piece.trim_top);
+            intersection(
+                &area,
+                &RectFields {
+                    x: left,
+                    y: top,
+                    width: right - left,
+                    length: bottom - top,
+                },
+            )
+        })
+        .collect()
+}
+
+fn usable_area(stock_piece: &InputStockPiece) -> RectFields {
+    let usable = stock_piece.usable();
+    RectFields {
+        x: 0,
+        y: 0,
+        width: usable.width,
+        length: usable.length,
+    }
+}
+
+/// Free regions of a stock piece's usable area, around its defects and the
+/// cut pieces pinned on it, measured from its corner. The area is cut around
+/// the largest defect with guillotine cuts, along its sides one way and then
+/// across it the other way, whichever leaves the largest region, and the
+/// pieces are cut the same way until no defects are left. Cuts along a defect
+/// take their kerf from the defect where they can, and pinned cut pieces are
+/// cut around like defects with the kerf around them.
+pub(crate) fn regions(
+    stock_piece: &InputStockPiece,
+    pinned: &[ResultCutPiece],
+    cut_width: usize,
+) -> Vec<RectFields> {
+    let area = usable_area(stock_piece);
+    let kerfs = pinned.iter().filter_map(|cut_piece| {
+        let left = cut_piece.x.saturating_sub(cut_width);
+        let top = cut_piece.y.saturating_sub(cut_width);
+        intersection(
+            &area,
+            &RectFields {
+                x: left,
+                y: top,
+                width: (cut_piece.x + cut_piece.width).saturating_add(cut_width) - left,
+                length: (cut_piece.y + cut_piece.length).saturating_add(cut_width) - top,
+            },
+        )
+    });
+    let blocked: Vec<_> = usable_defects(stock_piece)
+        .into_iter()
+        .chain(kerfs)
+        .collect();
+
+    let mut regions = Vec::new();
+    split_around(area, &blocked, cut_width, &mut regions);
+    regions.sort_by_key(|region| std::cmp::Reverse(area_of(region)));
+    regions
+}
+
+fn split_around(
+    area: RectFields,
+    defects: &[RectFields],
+    cut_width: usize,
+    regions: &mut Vec<RectFields>,
+) {
+    let inside: Vec<_> = defects
+        .iter()
+        .filter_map(|defect| intersection(&area, defect))
+        .collect();
+    let defect = match inside.iter().max_by_key(|d| area_of(d)) {
+        Some(defect) => *defect,
+        None => {
+            regions.push(area);
+            return;
+        }
+    };
+
+    let (right, bottom) = (area.x + area.width, area.y + area.length);
+    let (defect_right, defect_bottom) = (defect.x + defect.width, defect.y + defect.length);
+    let rect = |x: usize, y: usize, to_x: usize, to_y: usize| RectFields {
+        x,
+        y,
+        width: to_x.saturating_sub(x),
+        length: to_y.saturating_sub(y),
+    };
+    // A cut along the side of a defect runs into the rest of the area past
+    // the defect's ends, unless the defect reaches the edge
+    let kerf = |at_edge: bool| if at_edge { 0 } else { cut_width };
+
+    let inner_x = defect.x.saturating_add(kerf(defect.x == area.x));
+    let inner_right = defect_right.saturating_sub(kerf(defect_right == right));
+    let along_length = [
+        rect(area.x, area.y, defect.x, bottom),
+        rect(defect_right, area.y, right, bottom),
+        rect(inner_x, area.y, inner_right, defect.y),
+        rect(inner_x, defect_bottom, inner_right, bottom),
+    ];
+
+    let inner_y = defect.y.saturating_add(kerf(defect.y == area.y));
+    let inner_bottom = defect_bottom.saturating_sub(kerf(defect_bottom == bottom));
+    let along_width = [
+        rect(area.x, area.y, right, defect.y),
+        rect(area.x, defect_bottom, right, bottom),
+        rect(area.x, inner_y, defect.x, inner_bottom),
+        rect(defect_right, inner_y, right, inner_bottom),
+    ];
+
+    let largest = |parts: &[RectFields]| parts.iter().map(area_of).max();
+    let parts = if largest(&along_width) > largest(&along_length) {
+        along_width
+    } else {
+        along_length
+    };
+    for part in parts {
+        if part.width > 0 && part.length > 0 {
+            split_around(part, &inside, cut_width, regions);
+        }
+    }
+}
+
+/// Usable area of a stock piece, or its regions if it has defects
+pub(crate) fn usable_regions(stock_piece: &InputStockPiece, cut_width: usize) -> Vec<StockPiece> {
+    let usable = stock_piece.usable();
+    if stock_piece.defects.is_empty() {
+        return vec![usable];
+    }
+    regions(stock_piece, &[], cut_width)
+        .into_iter()
+        .map(|region| StockPiece {
+            width: region.width,
+            length: region.length,
+            ..usable
+        })
+        .collect()
+}
+
+/// Area of a rectangle, saturating for sizes no stock piece has
+fn area_of(rect: &RectFields) -> usize {
+    rect.width.saturating_mul(rect.length)
+}
+
+/// Overlap of two rectangles, if they overlap. A rectangle whose far edge
+/// overflows overlaps nothing.
+pub(super) fn intersection(a: &RectFields, b: &RectFields) -> Option<RectFields> {
+    let x = a.x.max(b.x);
+    let y = a.y.max(b.y);
+    let right = a.x.checked_add(a.width)?.min(b.x.checked_add(b.width)?);
+    let bottom = a.y.checked_add(a.length)?.min(b.y.checked_add(b.length)?);
+    (x < right && y < bottom).then(|| RectFields {
+        x,
+        y,
+        width: right - x,
+        length: bottom - y,
+    })
+}
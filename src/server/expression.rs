@@ -0,0 +1,249 @@
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::de::{self, Deserializer, Unexpected, Visitor};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Dimension of a cut piece, either a number or an arithmetic expression over
+/// the input's variables, such as `W - 2*t`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Dimension {
+    Value(usize),
+    Expression(String),
+}
+
+impl Dimension {
+    /// Evaluate the dimension, rounding expressions to the nearest whole number
+    pub(crate) fn resolve(&self, variables: &HashMap<String, f64>) -> Result<usize, String> {
+        match self {
+            Dimension::Value(value) => Ok(*value),
+            Dimension::Expression(expression) => {
+                let value = evaluate(expression, variables)?;
+                if value < 0.0 {
+                    return Err(format!("`{}` evaluates to {}", expression, value));
+                }
+                Ok(value.round() as usize)
+            }
+        }
+    }
+}
+
+impl From<usize> for Dimension {
+    fn from(value: usize) -> Self {
+        Dimension::Value(value)
+    }
+}
+
+impl<'de> Deserialize<'de> for Dimension {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct DimensionVisitor;
+
+        impl<'de> Visitor<'de> for DimensionVisitor {
+            type Value = Dimension;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a non-negative integer or an expression")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Dimension, E> {
+                usize::try_from(value)
+                    .map(Dimension::Value)
+                    .map_err(|_| E::invalid_value(Unexpected::Unsigned(value), &self))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Dimension, E> {
+                usize::try_from(value)
+                    .map(Dimension::Value)
+                    .map_err(|_| E::invalid_value(Unexpected::Signed(value), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Dimension, E> {
+                Ok(Dimension::Expression(value.to_string()))
+            }
+        }
+
+        deserializer.deserialize_any(DimensionVisitor)
+    }
+}
+
+impl JsonSchema for Dimension {
+    fn schema_name() -> Cow<'static, str> {
+        "Dimension".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "Whole number, or an expression over `variables` using `+`, `-`, `*`, `/`, and parentheses, rounded to the nearest whole number",
+            "oneOf": [
+                { "type": "integer", "minimum": 0 },
+                { "type": "string", "example": "W - 2*t" }
+            ]
+        })
+    }
+}
+
+/// Evaluate an arithmetic expression with `+`, `-`, `*`, `/`, parentheses,
+/// numbers, and variables
+pub(crate) fn evaluate(expression: &str, variables: &HashMap<String, f64>) -> Result<f64, String> {
+    let mut parser = Parser {
+        tokens: tokenize(expression)?,
+        position: 0,
+        variables,
+    };
+
+    let value = parser.expression()?;
+    match parser.peek() {
+        None => Ok(value),
+        Some(token) => Err(format!("Unexpected {} in `{}`", token, expression)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Variable(String),
+    Operator(char),
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "`{}`", number),
+            Token::Variable(name) => write!(f, "`{}`", name),
+            Token::Operator(operator) => write!(f, "`{}`", operator),
+            Token::Open => f.write_str("`(`"),
+            Token::Close => f.write_str("`)`"),
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '+' | '-' | '*' | '/' => {
+                tokens.push(Token::Operator(c));
+                chars.next();
+            }
+            '(' => {
+                tokens.push(Token::Open);
+                chars.next();
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                chars.next();
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                let number = &expression[start..end];
+                tokens.push(Token::Number(
+                    number
+                        .parse()
+                        .map_err(|_| format!("Invalid number `{}`", number))?,
+                ));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token::Variable(expression[start..end].to_string()));
+            }
+            c => return Err(format!("Unexpected `{}` in `{}`", c, expression)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    variables: &'a HashMap<String, f64>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// expression = term (("+" | "-") term)*
+    fn expression(&mut self) -> Result<f64, String> {
+        let mut value = self.term()?;
+        while let Some(Token::Operator(operator @ ('+' | '-'))) = self.peek().cloned() {
+            self.next();
+            let rhs = self.term()?;
+            value = if operator == '+' {
+                value + rhs
+            } else {
+                value - rhs
+            };
+        }
+        Ok(value)
+    }
+
+    /// term = factor (("*" | "/") factor)*
+    fn term(&mut self) -> Result<f64, String> {
+        let mut value = self.factor()?;
+        while let Some(Token::Operator(operator @ ('*' | '/'))) = self.peek().cloned() {
+            self.next();
+            let rhs = self.factor()?;
+            value = if operator == '*' {
+                value * rhs
+            } else if rhs == 0.0 {
+                return Err("Division by zero".to_string());
+            } else {
+                value / rhs
+            };
+        }
+        Ok(value)
+    }
+
+    /// factor = "-" factor | number | variable | "(" expression ")"
+    fn factor(&mut self) -> Result<f64, String> {
+        match self.next() {
+            Some(Token::Operator('-')) => Ok(-self.factor()?),
+            Some(Token::Number(number)) => Ok(number),
+            Some(Token::Variable(name)) => self
+                .variables
+                .get(&name)
+                .copied()
+                .ok_or_else(|| format!("Unknown variable `{}`", name)),
+            Some(Token::Open) => {
+                let value = self.expression()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(value),
+                    _ => Err("Missing `)`".to_string()),
+                }
+            }
+            Some(token) => Err(format!("Unexpected {}", token)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use cut_optimizer_2d::{CutPiece, ResultCutPiece, ResultStockPiece, Solution};
+
+use super::validation::rotated;
+use crate::proto::RectFields;
+
+/// Place optional cut pieces in the waste of a solution's sheets, largest
+/// first, without adding sheets. Pieces that don't fit anywhere are left out.
+///
+/// Each piece goes in the corner of the waste piece that fits it most tightly,
+/// and what's left of that waste piece is split in two with a cut across it,
+/// so layouts that could be cut with guillotine cuts still can be.
+pub(crate) fn fill(solution: &mut Solution, cut_pieces: &[CutPiece], cut_width: usize) {
+    let mut cut_pieces: Vec<_> = cut_pieces.iter().collect();
+    cut_pieces.sort_by_key(|cp| std::cmp::Reverse(cp.width * cp.length));
+
+    let mut free: Vec<Vec<RectFields>> = solution
+        .stock_pieces
+        .iter()
+        .map(|sheet| sheet.waste_pieces.iter().map(RectFields::from).collect())
+        .collect();
+
+    for cut_piece in cut_pieces {
+        let mut best: Option<(usize, usize, bool, usize)> = None;
+        for (sheet, stock_piece) in solution.stock_pieces.iter().enumerate() {
+            for (i, rect) in free[sheet].iter().enumerate() {
+                for is_rotated in orientations(cut_piece, stock_piece) {
+                    let (width, length) = size(cut_piece, is_rotated);
+                    if width <= rect.width && length <= rect.length {
+                        let leftover = rect.width * rect.length - width * length;
+                        if best.is_none_or(|(.., best)| leftover < best) {
+                            best = Some((sheet, i, is_rotated, leftover));
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some((sheet, i, is_rotated, _)) = best {
+            let rect = free[sheet].remove(i);
+            let (width, length) = size(cut_piece, is_rotated);
+            solution.stock_pieces[sheet]
+                .cut_pieces
+                .push(ResultCutPiece {
+                    external_id: cut_piece.external_id,
+                    x: rect.x,
+                    y: rect.y,
+                    width,
+                    length,
+                    pattern_direction: if is_rotated {
+                        rotated(cut_piece.pattern_direction)
+                    } else {
+                        cut_piece.pattern_direction
+                    },
+                    is_rotated,
+                });
+
+            let placed = RectFields {
+                x: rect.x,
+                y: rect.y,
+                width,
+                length,
+            };
+            // The nested method's waste pieces overlap, so take the piece and
+            // the kerf around it out of the others too
+            let kerf = RectFields {
+                x: placed.x.saturating_sub(cut_width),
+                y: placed.y.saturating_sub(cut_width),
+                width: placed.width + 2 * cut_width,
+                length: placed.length + 2 * cut_width,
+            };
+            let others = std::mem::take(&mut free[sheet]);
+            free[sheet] = others
+                .into_iter()
+                .flat_map(|other| subtract(&other, &kerf))
+                .chain(split(&rect, &placed, cut_width))
+                .collect();
+        }
+    }
+
+    for (stock_piece, free) in solution.stock_pieces.iter_mut().zip(free) {
+        stock_piece.waste_pieces = free.into_iter().map(Into::into).collect();
+    }
+}
+
+/// Whether a cut piece may be placed upright and rotated on a stock piece,
+/// with its pattern running the same way as the stock piece's
+fn orientations(cut_piece: &CutPiece, stock_piece: &ResultStockPiece) -> Vec<bool> {
+    let mut orientations = Vec::new();
+    if cut_piece.pattern_direction == stock_piece.pattern_direction {
+        orientations.push(false);
+    }
+    if cut_piece.can_rotate && rotated(cut_piece.pattern_direction) == stock_piece.pattern_direction
+    {
+        orientations.push(true);
+    }
+    orientations
+}
+
+fn size(cut_piece: &CutPiece, is_rotated: bool) -> (usize, usize) {
+    if is_rotated {
+        (cut_piece.length, cut_piece.width)
+    } else {
+        (cut_piece.width, cut_piece.length)
+    }
+}
+
+/// What's left of a waste piece after placing a piece in its corner, split
+/// along the axis that leaves the bigger waste piece
+fn split(rect: &RectFields, placed: &RectFields, cut_width: usize) -> Vec<RectFields> {
+    let right_width = rect.width.saturating_sub(placed.width + cut_width);
+    let below_length = rect.length.saturating_sub(placed.length + cut_width);
+    let (right_length, below_width) = if right_width < below_length {
+        (placed.length, rect.width)
+    } else {
+        (rect.length, placed.width)
+    };
+
+    let right = RectFields {
+        x: rect.x + placed.width + cut_width,
+        y: rect.y,
+        width: right_width,
+        length: right_length,
+    };
+    let below = RectFields {
+        x: rect.x,
+        y: rect.y + placed.length + cut_width,
+        width: below_width,
+        length: below_length,
+    };
+    vec![right, below]
+        .into_iter()
+        .filter(|rect| rect.width > 0 && rect.length > 0)
+        .collect()
+}
+
+/// Parts of `rect` outside of `other`, which may overlap each other
+fn subtract(rect: &RectFields, other: &RectFields) -> Vec<RectFields> {
+    let overlaps = rect.x < other.x + other.width
+        && other.x < rect.x + rect.width
+        && rect.y < other.y + other.length
+        && other.y < rect.y + rect.length;
+    if !overlaps {
+        return vec![*rect];
+    }
+
+    let (right, bottom) = (rect.x + rect.width, rect.y + rect.length);
+    let (other_right, other_bottom) = (other.x + other.width, other.y + other.length);
+    vec![
+        RectFields {
+            width: other.x.saturating_sub(rect.x),
+            ..*rect
+        },
+        RectFields {
+            x: other_right,
+            width: right.saturating_sub(other_right),
+            ..*rect
+        },
+        RectFields {
+            length: other.y.saturating_sub(rect.y),
+            ..*rect
+        },
+        RectFields {
+            y: other_bottom,
+            length: bottom.saturating_sub(other_bottom),
+            ..*rect
+        },
+    ]
+    .into_iter()
+    .filter(|rect| rect.width > 0 && rect.length > 0)
+    .collect()
+}
@@ -0,0 +1,256 @@
+use axum::async_trait;
+use axum::body::{self, Bytes, Full, HttpBody};
+use axum::extract::{FromRequest, RequestParts};
+use axum::response::{IntoResponse, Response};
+use http::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE};
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt::Display;
+use tower::BoxError;
+
+use super::{error, error_with_data, OptimizeError};
+use crate::proto::{FromProtobuf, IntoProtobuf, TooLarge};
+
+/// Wire format of a request or response body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Json,
+    Cbor,
+    Protobuf,
+}
+
+impl Format {
+    /// Format for a media type, ignoring parameters such as `charset`
+    pub(crate) fn from_media_type(value: &str) -> Option<Self> {
+        match media_type(value) {
+            "application/json" => Some(Format::Json),
+            "application/cbor" => Some(Format::Cbor),
+            "application/x-protobuf" | "application/protobuf" => Some(Format::Protobuf),
+            _ => None,
+        }
+    }
+
+    /// Format of the request body, from the `Content-Type` header
+    fn from_content_type(headers: &HeaderMap) -> Option<Self> {
+        let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+        Self::from_media_type(content_type)
+    }
+
+    /// Format to respond with, from the supported type the `Accept` header
+    /// prefers most, defaulting to JSON
+    fn from_accept(headers: &HeaderMap) -> Self {
+        let mut formats: Vec<(Self, f64)> = accepted(headers)
+            .into_iter()
+            .filter_map(|(media_type, quality)| Some((Self::from_media_type(media_type)?, quality)))
+            .collect();
+        // Stable, so equally preferred formats keep their order
+        formats.sort_by(|a, b| b.1.total_cmp(&a.1));
+        formats.first().map_or(Format::Json, |(format, _)| *format)
+    }
+
+    pub(crate) fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Cbor => "application/cbor",
+            Format::Protobuf => "application/x-protobuf",
+        }
+    }
+
+    fn decode<T: DeserializeOwned + FromProtobuf>(self, bytes: &[u8]) -> Result<T, BodyError> {
+        match self {
+            Format::Json => {
+                let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+                serde_path_to_error::deserialize(&mut deserializer).map_err(Into::into)
+            }
+            Format::Cbor => {
+                // ciborium doesn't expose its deserializer, so go through a
+                // `Value` to track the path of the failing field
+                let value: Value = ciborium::de::from_reader(bytes).map_err(BodyError::new)?;
+                from_value(value)
+            }
+            Format::Protobuf => T::from_protobuf(bytes),
+        }
+    }
+
+    /// Decode a request body, returning the error response if it's invalid
+    pub(crate) fn decode_request<T: DeserializeOwned + FromProtobuf>(
+        self,
+        bytes: &[u8],
+    ) -> Result<T, OptimizeError> {
+        self.decode(bytes)
+            .map_err(|e| error_with_data(StatusCode::BAD_REQUEST, "Invalid request body", e))
+    }
+
+    /// Encode a response body, returning the error response if that fails
+    pub(crate) fn encode_response<T: Serialize + IntoProtobuf>(
+        self,
+        value: T,
+    ) -> Result<Vec<u8>, OptimizeError> {
+        self.encode(value).map_err(|e| {
+            error_with_data(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Couldn't encode response",
+                e,
+            )
+        })
+    }
+
+    fn encode<T: Serialize + IntoProtobuf>(self, value: T) -> Result<Vec<u8>, String> {
+        match self {
+            Format::Json => serde_json::to_vec(&value).map_err(|e| e.to_string()),
+            Format::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::ser::into_writer(&value, &mut bytes).map_err(|e| e.to_string())?;
+                Ok(bytes)
+            }
+            Format::Protobuf => Ok(value.into_protobuf()),
+        }
+    }
+}
+
+/// Where and why a request body couldn't be decoded
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct BodyError {
+    /// Path of the offending field, such as `cutPieces[2].width`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    reason: String,
+}
+
+impl BodyError {
+    fn new<E: Display>(error: E) -> Self {
+        Self {
+            path: None,
+            reason: error.to_string(),
+        }
+    }
+}
+
+impl<E: Display> From<serde_path_to_error::Error<E>> for BodyError {
+    fn from(error: serde_path_to_error::Error<E>) -> Self {
+        let path = error.path().to_string();
+        Self {
+            // "." is the root of the document, which doesn't point anywhere useful
+            path: if path == "." { None } else { Some(path) },
+            reason: error.inner().to_string(),
+        }
+    }
+}
+
+impl From<prost::DecodeError> for BodyError {
+    fn from(error: prost::DecodeError) -> Self {
+        Self::new(error)
+    }
+}
+
+impl From<TooLarge> for BodyError {
+    fn from(error: TooLarge) -> Self {
+        Self {
+            reason: error.to_string(),
+            path: Some(error.path),
+        }
+    }
+}
+
+/// Deserialize a value, reporting the path of the field that failed
+pub(crate) fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, BodyError> {
+    serde_path_to_error::deserialize(value).map_err(Into::into)
+}
+
+/// Error for a request body in an unsupported format
+pub(crate) fn unsupported_media_type() -> OptimizeError {
+    error(
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "Expected request with `Content-Type: application/json`, `application/cbor`, or `application/x-protobuf`",
+    )
+}
+
+/// Strip parameters (such as `charset` or `q`) from a media type
+pub(super) fn media_type(value: &str) -> &str {
+    value.split(';').next().unwrap_or_default().trim()
+}
+
+/// Media types of the `Accept` headers with their quality, leaving out the ones
+/// with `q=0`, which the client refuses
+pub(super) fn accepted(headers: &HeaderMap) -> Vec<(&str, f64)> {
+    headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|value| {
+            let quality = value
+                .split(';')
+                .skip(1)
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse().ok())?;
+            (quality > 0.0).then_some((media_type(value), quality))
+        })
+        .collect()
+}
+
+/// Request body decoded according to its `Content-Type`, along with the format
+/// the client accepts for the response.
+///
+/// Error responses are always JSON, or problem details for clients that ask
+/// for them.
+#[derive(Debug)]
+pub(crate) struct Negotiated<T> {
+    pub(crate) accept: Format,
+    pub(crate) body: T,
+}
+
+#[async_trait]
+impl<T, B> FromRequest<B> for Negotiated<T>
+where
+    T: DeserializeOwned + FromProtobuf,
+    B: HttpBody + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = OptimizeError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let headers = req.headers().cloned().unwrap_or_default();
+        let format = Format::from_content_type(&headers).ok_or_else(unsupported_media_type)?;
+
+        let bytes = Bytes::from_request(req).await.map_err(|e| {
+            error_with_data(
+                StatusCode::BAD_REQUEST,
+                "Couldn't read request body",
+                e.to_string(),
+            )
+        })?;
+
+        let body = format.decode_request(&bytes)?;
+
+        Ok(Negotiated {
+            accept: Format::from_accept(&headers),
+            body,
+        })
+    }
+}
+
+/// Response body encoded in the given format
+#[derive(Debug)]
+pub(crate) struct Encoded<T>(pub(crate) Format, pub(crate) T);
+
+impl<T: Serialize + IntoProtobuf> IntoResponse for Encoded<T> {
+    fn into_response(self) -> Response {
+        let Encoded(format, value) = self;
+        match format.encode_response(value) {
+            Ok(bytes) => {
+                let mut res = Response::new(body::boxed(Full::from(bytes)));
+                res.headers_mut().insert(
+                    CONTENT_TYPE,
+                    HeaderValue::from_static(format.content_type()),
+                );
+                res
+            }
+            Err(e) => e.into_response(),
+        }
+    }
+}
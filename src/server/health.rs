@@ -0,0 +1,144 @@
+use axum::extract::Extension;
+use axum::Json;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+use super::lint::Warning;
+use super::OptimizerConfig;
+
+/// Optional parts of the server that optimizing goes on without while they're
+/// failing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Component {
+    StockCatalogs,
+    Presets,
+    Translations,
+}
+
+impl Component {
+    fn name(self) -> &'static str {
+        match self {
+            Self::StockCatalogs => "stockCatalogs",
+            Self::Presets => "presets",
+            Self::Translations => "translations",
+        }
+    }
+
+    /// Input field whose value the component looks up
+    fn field(self) -> &'static str {
+        match self {
+            Self::StockCatalogs => "stockCatalog",
+            Self::Presets => "preset",
+            Self::Translations => "language",
+        }
+    }
+}
+
+type Failures = BTreeMap<Component, String>;
+
+/// Components that failed the last time they were used, or the last time
+/// readiness was probed, with the error
+#[derive(Debug, Default)]
+pub(crate) struct Health {
+    degraded: Mutex<Failures>,
+
+    /// Kept apart so that a directory the probe can reach doesn't hide a
+    /// failed read or write in it
+    probed: Mutex<Failures>,
+}
+
+impl Health {
+    /// Record that a component failed
+    pub(crate) fn fail(&self, component: Component, error: impl ToString) {
+        lock(&self.degraded).insert(component, error.to_string());
+    }
+
+    /// Record that a component worked, so it's no longer degraded
+    pub(crate) fn succeed(&self, component: Component) {
+        lock(&self.degraded).remove(&component);
+    }
+
+    /// Degraded components with the error, preferring the one from the last
+    /// use over the probe's
+    fn failures(&self) -> Failures {
+        let mut failures = lock(&self.probed).clone();
+        failures.extend(lock(&self.degraded).clone());
+        failures
+    }
+
+    /// Warnings about the degraded components, for responses that were
+    /// optimized without them
+    pub(crate) fn warnings(&self) -> Vec<Warning> {
+        self.failures()
+            .keys()
+            .map(|component| Warning {
+                code: "degradedComponent",
+                path: component.field().to_string(),
+                message: format!(
+                    "`{}` is failing on the server, so inputs that use it may not be optimized",
+                    component.name()
+                ),
+                fixes: Vec::new(),
+            })
+            .collect()
+    }
+}
+
+fn lock(failures: &Mutex<Failures>) -> std::sync::MutexGuard<'_, Failures> {
+    failures.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Check that a component's directory can be reached. A directory that
+/// doesn't exist yet just has nothing in it. Only the probe's own failures
+/// are cleared, since reaching the directory says nothing about what's in it.
+async fn probe(health: &Health, component: Component, dir: &Path) {
+    let result = match tokio::fs::metadata(dir).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Some(e.to_string()),
+        _ => None,
+    };
+    let mut probed = lock(&health.probed);
+    match result {
+        Some(error) => probed.insert(component, error),
+        None => probed.remove(&component),
+    };
+}
+
+/// Readiness of the server and each enabled component. The server stays ready
+/// while optional components are degraded, since it can still optimize.
+pub(super) async fn readyz(Extension(config): Extension<OptimizerConfig>) -> Json<Value> {
+    let health = &config.health;
+    let mut enabled = Vec::new();
+    if let Some(catalogs) = &config.catalogs {
+        enabled.push((Component::StockCatalogs, catalogs.dir()));
+    }
+    if let Some(presets) = &config.presets {
+        enabled.push((Component::Presets, presets.dir()));
+    }
+    if let Some(translations) = &config.translations {
+        enabled.push((Component::Translations, translations.dir()));
+    }
+    for (component, dir) in &enabled {
+        probe(health, *component, dir).await;
+    }
+
+    let degraded = health.failures();
+    let mut components = Map::new();
+    components.insert("optimizer".to_string(), json!({ "status": "ok" }));
+    for (component, _) in enabled {
+        let status = match degraded.get(&component) {
+            Some(error) => json!({ "status": "degraded", "error": error }),
+            None => json!({ "status": "ok" }),
+        };
+        components.insert(component.name().to_string(), status);
+    }
+
+    let status = if degraded.is_empty() {
+        "ready"
+    } else {
+        "degraded"
+    };
+    Json(json!({ "status": status, "components": components }))
+}
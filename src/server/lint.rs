@@ -0,0 +1,144 @@
+use cut_optimizer_2d::CutPiece;
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::validation::fits;
+use super::{InputStockPiece, OptimizerInput};
+
+/// Every code a `Warning` can have, for the schemas. `degradedComponent` comes
+/// from the server's health rather than the input.
+pub(crate) const CODES: &[&str] = &[
+    "zeroCutWidth",
+    "largeCutWidth",
+    "unusableStockPiece",
+    "degradedComponent",
+];
+
+/// Something in an input that's allowed but probably not what was meant, or a
+/// `degradedComponent` of the server that the input's field at `path` would use
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Warning {
+    /// Machine-readable warning code
+    #[schemars(extend("enum" = CODES))]
+    pub(crate) code: &'static str,
+
+    /// Path of the field it's about, such as `cutPieces[2].width`
+    pub(crate) path: String,
+
+    pub(crate) message: String,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) fixes: Vec<Fix>,
+}
+
+/// Change to the input that would resolve an error or warning
+#[derive(Debug, Serialize, JsonSchema, PartialEq)]
+pub(crate) struct Fix {
+    #[schemars(example = "Reduce the width by 1")]
+    pub(crate) message: String,
+    pub(crate) changes: Vec<Change>,
+}
+
+/// New value for a field of the input
+#[derive(Debug, Serialize, JsonSchema, PartialEq)]
+pub(crate) struct Change {
+    #[schemars(example = "cutPieces[14].width")]
+    pub(crate) path: String,
+    pub(crate) value: Value,
+}
+
+impl Fix {
+    pub(crate) fn new(message: String, changes: Vec<Change>) -> Self {
+        Self { message, changes }
+    }
+}
+
+impl Change {
+    pub(crate) fn new(path: String, value: impl Into<Value>) -> Self {
+        Self {
+            path,
+            value: value.into(),
+        }
+    }
+}
+
+/// Check a valid input for likely mistakes, such as dimensions given in the
+/// wrong unit
+pub(crate) fn lint(input: &OptimizerInput, cut_pieces: &[CutPiece]) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+
+    let cut_width = input.cut_width();
+    if cut_width == 0 {
+        warnings.push(Warning {
+            code: "zeroCutWidth",
+            path: "cutWidth".to_string(),
+            message: "cutWidth 0 means pieces will touch, leaving no room for the blade"
+                .to_string(),
+            fixes: Vec::new(),
+        });
+    }
+
+    // A kerf as wide as a cut piece usually means the dimensions are in
+    // another unit than the cut width
+    if let Some((i, cut_piece)) = cut_pieces
+        .iter()
+        .enumerate()
+        .find(|(_, cp)| cut_width > 0 && cut_width >= cp.width.min(cp.length))
+    {
+        warnings.push(Warning {
+            code: "largeCutWidth",
+            path: "cutWidth".to_string(),
+            message: format!(
+                "cutWidth {} is at least as big as cut piece {} ({}x{}); check that they use the same unit",
+                cut_width, i, cut_piece.width, cut_piece.length
+            ),
+            fixes: Vec::new(),
+        });
+    }
+
+    for (i, stock_piece) in input.stock_pieces.iter().enumerate() {
+        let usable = stock_piece.usable();
+        if !stock_piece.must_use && !cut_pieces.iter().any(|cp| fits(cp, &usable)) {
+            warnings.push(Warning {
+                code: "unusableStockPiece",
+                path: format!("stockPieces[{}]", i),
+                message: format!(
+                    "No cut piece fits in stock piece {}x{}, so it's never used",
+                    stock_piece.width, stock_piece.length
+                ),
+                fixes: rotation_fixes(i, stock_piece, cut_pieces),
+            });
+        }
+    }
+
+    warnings
+}
+
+/// Fixes for a stock piece that some cut pieces would fit if they could
+/// rotate
+fn rotation_fixes(
+    index: usize,
+    stock_piece: &InputStockPiece,
+    cut_pieces: &[CutPiece],
+) -> Vec<Fix> {
+    let usable = stock_piece.usable();
+    cut_pieces
+        .iter()
+        .enumerate()
+        .filter(|(_, cp)| {
+            let rotatable = CutPiece {
+                can_rotate: true,
+                ..(*cp).clone()
+            };
+            fits(&rotatable, &usable)
+        })
+        .map(|(i, _)| {
+            Fix::new(
+                format!("Let cut piece {} rotate to fit stock piece {}", i, index),
+                vec![Change::new(format!("cutPieces[{}].canRotate", i), true)],
+            )
+        })
+        .collect()
+}
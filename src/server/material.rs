@@ -0,0 +1,288 @@
+use futures::future::join_all;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use super::validation::ValidationError;
+use super::{invalid_input, optimize_input, OptimizeError, Optimized, OptimizerConfig};
+use super::{OptimizerInput, OptimizerOutput};
+
+/// Whether any stock piece or cut piece of the input has a material
+pub(crate) fn has_materials(input: &OptimizerInput) -> bool {
+    input
+        .stock_pieces
+        .iter()
+        .any(|stock_piece| stock_piece.material.is_some())
+        || input
+            .cut_pieces
+            .iter()
+            .any(|cut_piece| cut_piece.material.is_some())
+}
+
+/// Optimize the pieces of each material on their own, all at once. The first
+/// material to fail fails the whole input.
+pub(crate) async fn optimize(
+    config: &OptimizerConfig,
+    input: OptimizerInput,
+) -> Result<Optimized, OptimizeError> {
+    let errors = validate(&input);
+    if !errors.is_empty() {
+        return Err(invalid_input(errors));
+    }
+
+    // Without cut pieces there's nothing to split, and the input is rejected
+    // for it
+    if input.cut_pieces.is_empty() {
+        let output = optimize_input(config, input).await?;
+        return Ok(Optimized::Solution(Box::new(output)));
+    }
+
+    let parts = partition(&input);
+    let results = join_all(
+        parts
+            .iter()
+            .map(|part| optimize_input(config, part.input.clone())),
+    )
+    .await;
+
+    let mut materials = BTreeMap::new();
+    for (part, result) in parts.into_iter().zip(results) {
+        match result {
+            Ok(mut output) => {
+                part.restore(&mut output);
+                materials.insert(part.material, output);
+            }
+            Err((status, mut body)) => {
+                part.restore_json(&mut body.0);
+                return Err((status, body));
+            }
+        }
+    }
+    Ok(Optimized::ByMaterial { materials })
+}
+
+/// Check that the pieces of every material can be optimized on their own
+fn validate(input: &OptimizerInput) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    let missing = |path: String| {
+        ValidationError::new(
+            "missingMaterial",
+            path,
+            "A material is required once any piece has one".to_string(),
+        )
+    };
+
+    for (i, stock_piece) in input.stock_pieces.iter().enumerate() {
+        match &stock_piece.material {
+            None => errors.push(missing(format!("stockPieces[{}].material", i))),
+            Some(material) => {
+                let is_cut = input
+                    .cut_pieces
+                    .iter()
+                    .any(|cut_piece| cut_piece.material.as_ref() == Some(material));
+                if stock_piece.must_use && !is_cut {
+                    errors.push(ValidationError::new(
+                        "unsatisfiableMustUse",
+                        format!("stockPieces[{}].mustUse", i),
+                        format!("No cut piece is `{}`", material),
+                    ));
+                }
+            }
+        }
+    }
+
+    for (i, cut_piece) in input.cut_pieces.iter().enumerate() {
+        match &cut_piece.material {
+            None => errors.push(missing(format!("cutPieces[{}].material", i))),
+            Some(material) => {
+                let is_stocked = input
+                    .stock_pieces
+                    .iter()
+                    .any(|stock_piece| stock_piece.material.as_ref() == Some(material));
+                if !is_stocked {
+                    errors.push(ValidationError::new(
+                        "noStockForMaterial",
+                        format!("cutPieces[{}].material", i),
+                        format!("No stock piece is `{}`", material),
+                    ));
+                }
+            }
+        }
+    }
+
+    for (k, pin) in input.pins.iter().enumerate() {
+        let path = format!("pins[{}]", k);
+        let invalid = |field: &str, message: String| {
+            ValidationError::new("invalidPin", format!("{}{}", path, field), message)
+        };
+        match (
+            input.cut_pieces.get(pin.cut_piece),
+            input.stock_pieces.get(pin.stock_piece),
+        ) {
+            (Some(cut_piece), Some(stock_piece)) => {
+                if cut_piece.material != stock_piece.material {
+                    errors.push(invalid(
+                        "",
+                        format!(
+                            "Cut piece {} isn't the same material as stock piece {}",
+                            pin.cut_piece, pin.stock_piece
+                        ),
+                    ));
+                }
+            }
+            (None, _) => errors.push(invalid(
+                ".cutPiece",
+                format!("There's no cut piece {}", pin.cut_piece),
+            )),
+            (_, None) => errors.push(invalid(
+                ".stockPiece",
+                format!("There's no stock piece {}", pin.stock_piece),
+            )),
+        }
+    }
+
+    errors
+}
+
+/// Pieces of one material, as an input of their own
+struct Part {
+    material: String,
+    input: OptimizerInput,
+
+    /// Index in the whole input of each stock piece, cut piece and pin
+    stock_pieces: Vec<usize>,
+    cut_pieces: Vec<usize>,
+    pins: Vec<usize>,
+}
+
+/// Split a validated input into one input for each material with cut pieces
+fn partition(input: &OptimizerInput) -> Vec<Part> {
+    let mut materials: Vec<&String> = input
+        .cut_pieces
+        .iter()
+        .filter_map(|cut_piece| cut_piece.material.as_ref())
+        .collect();
+    materials.sort_unstable();
+    materials.dedup();
+
+    materials
+        .into_iter()
+        .map(|material| {
+            let is = |piece_material: &Option<String>| piece_material.as_ref() == Some(material);
+            let stock_pieces: Vec<usize> = (0..input.stock_pieces.len())
+                .filter(|&i| is(&input.stock_pieces[i].material))
+                .collect();
+            let cut_pieces: Vec<usize> = (0..input.cut_pieces.len())
+                .filter(|&i| is(&input.cut_pieces[i].material))
+                .collect();
+            let pins: Vec<usize> = (0..input.pins.len())
+                .filter(|&k| is(&input.cut_pieces[input.pins[k].cut_piece].material))
+                .collect();
+
+            let local = |indexes: &[usize], index: usize| {
+                indexes
+                    .iter()
+                    .position(|&i| i == index)
+                    .expect("Pinned pieces should be the same material")
+            };
+            let mut part = input.clone();
+            part.stock_pieces = stock_pieces
+                .iter()
+                .map(|&i| input.stock_pieces[i].clone())
+                .collect();
+            part.cut_pieces = cut_pieces
+                .iter()
+                .map(|&i| input.cut_pieces[i].clone())
+                .collect();
+            part.pins = pins
+                .iter()
+                .map(|&k| {
+                    let mut pin = input.pins[k];
+                    pin.cut_piece = local(&cut_pieces, pin.cut_piece);
+                    pin.stock_piece = local(&stock_pieces, pin.stock_piece);
+                    pin
+                })
+                .collect();
+
+            Part {
+                material: material.clone(),
+                input: part,
+                stock_pieces,
+                cut_pieces,
+                pins,
+            }
+        })
+        .collect()
+}
+
+impl Part {
+    /// Path in the whole input of a path in the part's input. Messages still
+    /// count pieces within the part.
+    fn path(&self, path: &str) -> String {
+        for (field, indexes) in [
+            ("stockPieces[", &self.stock_pieces),
+            ("cutPieces[", &self.cut_pieces),
+            ("pins[", &self.pins),
+        ] {
+            let rest = match path.strip_prefix(field) {
+                Some(rest) => rest,
+                None => continue,
+            };
+            let end = rest.find(']').unwrap_or_default();
+            if let Some(&index) = rest[..end].parse().ok().and_then(|i: usize| indexes.get(i)) {
+                return format!("{}{}{}", field, index, &rest[end..]);
+            }
+        }
+        path.to_string()
+    }
+
+    /// Refer to the whole input from an output of the part's input
+    fn restore(&self, output: &mut OptimizerOutput) {
+        for group in output.cut_piece_groups.iter_mut().flatten() {
+            group.cut_piece = self.cut_pieces[group.cut_piece];
+        }
+        for unplaced in output.unplaced_pieces.iter_mut().flatten() {
+            unplaced.cut_piece = self.cut_pieces[unplaced.cut_piece];
+        }
+        if let Some(metadata) = &mut output.metadata {
+            for sheet in &mut metadata.sheets {
+                sheet.stock_piece = self.stock_pieces[sheet.stock_piece];
+            }
+            for placed in &mut metadata.cut_pieces {
+                placed.cut_piece = self.cut_pieces[placed.cut_piece];
+            }
+        }
+        for warning in &mut output.warnings {
+            warning.path = self.path(&warning.path);
+            for change in warning.fixes.iter_mut().flat_map(|fix| &mut fix.changes) {
+                change.path = self.path(&change.path);
+            }
+        }
+    }
+
+    /// Refer to the whole input from an error body of the part's input
+    fn restore_json(&self, value: &mut Value) {
+        match value {
+            Value::Object(object) => {
+                for (key, value) in object.iter_mut() {
+                    match (key.as_str(), &mut *value) {
+                        ("path", Value::String(path)) => *path = self.path(path),
+                        ("closestStockPiece", Value::Object(closest)) => {
+                            if let Some(index) = closest.get_mut("index") {
+                                if let Some(i) = index.as_u64() {
+                                    *index = self.stock_pieces[i as usize].into();
+                                }
+                            }
+                        }
+                        (_, value) => self.restore_json(value),
+                    }
+                }
+            }
+            Value::Array(values) => {
+                for value in values {
+                    self.restore_json(value);
+                }
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use cut_optimizer_2d::Solution;
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::quantity::CutPieceGroup;
+use super::OptimizerInput;
+
+/// Metadata of the input pieces, attached to where they ended up in a
+/// solution, if any input piece has `metadata`. Sheets go to the first input
+/// stock piece of their size with copies left.
+#[derive(Serialize, JsonSchema, Debug)]
+#[schemars(rename = "PieceMetadata")]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Metadata {
+    /// Metadata of each stock piece in the solution whose input stock piece has
+    /// some
+    pub(crate) sheets: Vec<SheetMetadata>,
+
+    /// Metadata of each placed cut piece whose input cut piece has some
+    pub(crate) cut_pieces: Vec<PlacedMetadata>,
+}
+
+#[derive(Serialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SheetMetadata {
+    /// Index of the stock piece in the solution
+    pub(crate) sheet: usize,
+
+    /// Index of the input stock piece in `stockPieces`
+    pub(crate) stock_piece: usize,
+
+    #[schemars(with = "Map<String, Value>")]
+    pub(crate) metadata: Value,
+}
+
+#[derive(Serialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PlacedMetadata {
+    /// Index of the stock piece in the solution
+    pub(crate) sheet: usize,
+
+    /// Index of the cut piece in the stock piece's `cutPieces`
+    pub(crate) index: usize,
+
+    /// Index of the input cut piece in `cutPieces`
+    pub(crate) cut_piece: usize,
+
+    #[schemars(with = "Map<String, Value>")]
+    pub(crate) metadata: Value,
+}
+
+/// Whether any stock piece or cut piece of the input has metadata
+pub(crate) fn has_metadata(input: &OptimizerInput) -> bool {
+    input
+        .stock_pieces
+        .iter()
+        .any(|stock_piece| stock_piece.metadata.is_some())
+        || input
+            .cut_pieces
+            .iter()
+            .any(|cut_piece| cut_piece.metadata.is_some())
+}
+
+/// Attach the metadata of the input pieces to a solution, whose trim has been
+/// restored. `groups` has an entry for each input cut piece.
+///
+/// Solutions only give the size of each stock piece used, so each sheet goes
+/// to the first input stock piece of its size that has copies left.
+pub(crate) fn placed(
+    input: &OptimizerInput,
+    solution: &Solution,
+    groups: &[CutPieceGroup],
+) -> Metadata {
+    let mut remaining: Vec<Option<usize>> = input
+        .stock_pieces
+        .iter()
+        .map(|stock_piece| stock_piece.quantity)
+        .collect();
+    let mut sheets = Vec::new();
+    for (sheet, used) in solution.stock_pieces.iter().enumerate() {
+        let stock_piece = (0..input.stock_pieces.len()).find(|&i| {
+            let sp = &input.stock_pieces[i];
+            sp.width == used.width
+                && sp.length == used.length
+                && sp.pattern_direction == used.pattern_direction
+                && remaining[i] != Some(0)
+        });
+        let i = match stock_piece {
+            Some(i) => i,
+            None => continue,
+        };
+        if let Some(remaining) = &mut remaining[i] {
+            *remaining -= 1;
+        }
+        if let Some(metadata) = &input.stock_pieces[i].metadata {
+            sheets.push(SheetMetadata {
+                sheet,
+                stock_piece: i,
+                metadata: metadata.clone(),
+            });
+        }
+    }
+
+    let mut cut_pieces = Vec::new();
+    for group in groups {
+        let metadata = match &input.cut_pieces[group.cut_piece].metadata {
+            Some(metadata) => metadata,
+            None => continue,
+        };
+        for placement in &group.placements {
+            cut_pieces.push(PlacedMetadata {
+                sheet: placement.sheet,
+                index: placement.index,
+                cut_piece: group.cut_piece,
+                metadata: metadata.clone(),
+            });
+        }
+    }
+
+    Metadata { sheets, cut_pieces }
+}
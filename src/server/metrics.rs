@@ -0,0 +1,526 @@
+use cut_optimizer_2d::{CutPiece, ResultStockPiece, Solution, StockPiece};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::quantity::CutPieceGroup;
+use super::ranking::{sheet_price, used_area};
+use super::EdgeAllowance;
+use crate::proto::RectFields;
+
+/// Estimated cutting work needed to free every cut piece in a solution,
+/// counting each edge of a cut piece that isn't on the edge of its stock piece
+#[derive(Serialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CutMetrics {
+    /// Number of straight cuts
+    pub(crate) cut_count: usize,
+
+    /// Total length of all cuts
+    pub(crate) cut_length: usize,
+
+    /// `cutLength` divided by `feedRate`, if `feedRate` was given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) sawing_seconds: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Orientation {
+    /// Runs along the length of the stock piece, at an `x` offset
+    AlongLength,
+
+    /// Runs along the width of the stock piece, at a `y` offset
+    AlongWidth,
+}
+
+/// Compute cut metrics for a solution. `feed_rate` is the cut length the saw
+/// covers per second.
+pub(crate) fn cut_metrics(
+    solution: &Solution,
+    cut_width: usize,
+    feed_rate: Option<f64>,
+) -> CutMetrics {
+    let (cut_count, cut_length) = solution
+        .stock_pieces
+        .iter()
+        .map(|stock_piece| stock_piece_cuts(stock_piece, cut_width))
+        .fold((0, 0), |(count, length), (c, l)| (count + c, length + l));
+
+    CutMetrics {
+        cut_count,
+        cut_length,
+        sawing_seconds: feed_rate.map(|feed_rate| cut_length as f64 / feed_rate),
+    }
+}
+
+/// Count the cuts along every edge of a cut piece that isn't on the edge of the
+/// stock piece. Both sides of a kerf are one cut, and cuts along the same line
+/// that are only separated by a kerf are merged.
+fn stock_piece_cuts(stock_piece: &ResultStockPiece, cut_width: usize) -> (usize, usize) {
+    let mut lines: HashMap<(Orientation, usize), Vec<(usize, usize)>> = HashMap::new();
+    let mut add = |orientation, offset, start, end| {
+        lines
+            .entry((orientation, offset))
+            .or_default()
+            .push((start, end));
+    };
+
+    for cp in &stock_piece.cut_pieces {
+        let (x_end, y_end) = (cp.x + cp.width, cp.y + cp.length);
+        if cp.x > 0 {
+            add(
+                Orientation::AlongLength,
+                cp.x.saturating_sub(cut_width),
+                cp.y,
+                y_end,
+            );
+        }
+        if x_end < stock_piece.width {
+            add(Orientation::AlongLength, x_end, cp.y, y_end);
+        }
+        if cp.y > 0 {
+            add(
+                Orientation::AlongWidth,
+                cp.y.saturating_sub(cut_width),
+                cp.x,
+                x_end,
+            );
+        }
+        if y_end < stock_piece.length {
+            add(Orientation::AlongWidth, y_end, cp.x, x_end);
+        }
+    }
+
+    let mut count = 0;
+    let mut length = 0;
+    for segments in lines.values_mut() {
+        segments.sort_unstable();
+
+        let mut current: Option<(usize, usize)> = None;
+        for &(start, end) in segments.iter() {
+            current = match current {
+                Some((cur_start, cur_end)) if start <= cur_end + cut_width => {
+                    Some((cur_start, cur_end.max(end)))
+                }
+                Some((cur_start, cur_end)) => {
+                    count += 1;
+                    length += cur_end - cur_start;
+                    Some((start, end))
+                }
+                None => Some((start, end)),
+            };
+        }
+        if let Some((cur_start, cur_end)) = current {
+            count += 1;
+            length += cur_end - cur_start;
+        }
+    }
+
+    (count, length)
+}
+
+/// Area, waste, cut length, and price of a solution, overall and per stock piece
+#[derive(Serialize, JsonSchema, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SolutionStats {
+    /// Number of stock pieces used
+    pub(crate) sheet_count: usize,
+
+    /// Number of stock pieces used that match one of the input's remnants
+    pub(crate) remnant_count: usize,
+    #[serde(flatten)]
+    pub(crate) totals: SheetStats,
+
+    /// Stats of each stock piece, in the same order as `stockPieces`
+    pub(crate) sheets: Vec<SheetStats>,
+}
+
+#[derive(Serialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SheetStats {
+    /// Area covered by cut pieces
+    pub(crate) used_area: usize,
+
+    /// Area not covered by cut pieces, including kerf
+    pub(crate) waste_area: usize,
+
+    /// Used area as a percentage of the stock area
+    #[schemars(range(min = 0, max = 100))]
+    pub(crate) utilization_percent: f64,
+
+    /// Total length of all cuts
+    pub(crate) cut_length: usize,
+
+    /// Price of the matching input stock piece
+    pub(crate) price: usize,
+
+    /// Whether the stock piece matches one of the input's remnants, for the
+    /// stats of a single stock piece
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) is_remnant: Option<bool>,
+}
+
+/// Compute area, waste, cut length, and price stats for a solution
+pub(crate) fn solution_stats(
+    solution: &Solution,
+    stock_pieces: &[StockPiece],
+    remnants: &[StockPiece],
+    cut_width: usize,
+) -> SolutionStats {
+    let sheets: Vec<SheetStats> = solution
+        .stock_pieces
+        .iter()
+        .map(|stock_piece| {
+            let stock_area = stock_piece.width * stock_piece.length;
+            let used_area = used_area(stock_piece);
+            SheetStats {
+                used_area,
+                waste_area: stock_area - used_area,
+                utilization_percent: percent(used_area, stock_area),
+                cut_length: stock_piece_cuts(stock_piece, cut_width).1,
+                price: sheet_price(stock_piece, stock_pieces),
+                is_remnant: Some(remnants.iter().any(|remnant| {
+                    remnant.width == stock_piece.width
+                        && remnant.length == stock_piece.length
+                        && remnant.pattern_direction == stock_piece.pattern_direction
+                })),
+            }
+        })
+        .collect();
+
+    let mut totals = sheets
+        .iter()
+        .fold(SheetStats::default(), |totals, sheet| SheetStats {
+            used_area: totals.used_area + sheet.used_area,
+            waste_area: totals.waste_area + sheet.waste_area,
+            utilization_percent: 0.0,
+            cut_length: totals.cut_length + sheet.cut_length,
+            price: totals.price + sheet.price,
+            is_remnant: None,
+        });
+    totals.utilization_percent = percent(totals.used_area, totals.used_area + totals.waste_area);
+
+    SolutionStats {
+        sheet_count: sheets.len(),
+        remnant_count: sheets
+            .iter()
+            .filter(|sheet| sheet.is_remnant == Some(true))
+            .count(),
+        totals,
+        sheets,
+    }
+}
+
+fn percent(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        100.0
+    } else {
+        100.0 * part as f64 / whole as f64
+    }
+}
+
+/// Material cost of a solution
+#[derive(Serialize, JsonSchema, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Cost {
+    /// `currency` from the input, if given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) currency: Option<String>,
+
+    /// Cost of each stock piece, in the same order as `stockPieces`
+    pub(crate) sheets: Vec<SheetCost>,
+
+    /// Total cost of every stock piece used
+    #[schemars(range(min = 0))]
+    pub(crate) material_cost: f64,
+}
+
+#[derive(Serialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SheetCost {
+    /// Price of the matching input stock piece
+    pub(crate) price: usize,
+
+    /// Stock area times `pricePerArea`, if given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0))]
+    pub(crate) area_cost: Option<f64>,
+
+    /// `price` plus `areaCost`
+    #[schemars(range(min = 0))]
+    pub(crate) total: f64,
+}
+
+/// Compute the material cost of a solution, charging `price_per_area` for
+/// each unit of stock area on top of each stock piece's price
+pub(crate) fn cost(
+    solution: &Solution,
+    stock_pieces: &[StockPiece],
+    price_per_area: Option<f64>,
+    currency: Option<String>,
+) -> Cost {
+    let sheets: Vec<SheetCost> = solution
+        .stock_pieces
+        .iter()
+        .map(|stock_piece| {
+            let price = sheet_price(stock_piece, stock_pieces);
+            let area_cost = price_per_area.map(|price_per_area| {
+                (stock_piece.width * stock_piece.length) as f64 * price_per_area
+            });
+            SheetCost {
+                price,
+                area_cost,
+                total: price as f64 + area_cost.unwrap_or_default(),
+            }
+        })
+        .collect();
+
+    Cost {
+        currency,
+        material_cost: sheets.iter().map(|sheet| sheet.total).sum(),
+        sheets,
+    }
+}
+
+/// Relative weights of the parts of the quality score. Missing weights use the
+/// defaults.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct QualityWeights {
+    #[schemars(range(min = 0), extend("default" = Self::DEFAULT_UTILIZATION))]
+    pub(crate) utilization: Option<f64>,
+    #[schemars(range(min = 0), extend("default" = Self::DEFAULT_OFFCUT_USABILITY))]
+    pub(crate) offcut_usability: Option<f64>,
+    #[schemars(range(min = 0), extend("default" = Self::DEFAULT_CUT_SIMPLICITY))]
+    pub(crate) cut_simplicity: Option<f64>,
+}
+
+impl QualityWeights {
+    const DEFAULT_UTILIZATION: f64 = 0.6;
+    const DEFAULT_OFFCUT_USABILITY: f64 = 0.2;
+    const DEFAULT_CUT_SIMPLICITY: f64 = 0.2;
+
+    pub(crate) fn utilization(&self) -> f64 {
+        self.utilization.unwrap_or(Self::DEFAULT_UTILIZATION)
+    }
+
+    pub(crate) fn offcut_usability(&self) -> f64 {
+        self.offcut_usability
+            .unwrap_or(Self::DEFAULT_OFFCUT_USABILITY)
+    }
+
+    pub(crate) fn cut_simplicity(&self) -> f64 {
+        self.cut_simplicity.unwrap_or(Self::DEFAULT_CUT_SIMPLICITY)
+    }
+}
+
+/// Quality of a layout, with each part normalized from 0 to 1
+#[derive(Serialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Quality {
+    /// Weighted score from 0 to 100
+    #[schemars(range(min = 0, max = 100))]
+    pub(crate) score: f64,
+
+    /// Fraction of the used stock area covered by cut pieces
+    #[schemars(range(min = 0, max = 1))]
+    pub(crate) utilization: f64,
+
+    /// Fraction of the waste area in offcuts that at least one cut piece would fit in
+    #[schemars(range(min = 0, max = 1))]
+    pub(crate) offcut_usability: f64,
+
+    /// Cut pieces per cut, capped at 1
+    #[schemars(range(min = 0, max = 1))]
+    pub(crate) cut_simplicity: f64,
+}
+
+/// Leftover rectangle of a stock piece that's big enough to reuse
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Offcut {
+    /// Index of the stock piece in the solution
+    pub(crate) sheet: usize,
+    #[serde(flatten)]
+    pub(crate) rect: RectFields,
+}
+
+/// Find the waste pieces of a solution that are at least `min_width` by
+/// `min_length` in either orientation.
+///
+/// The nested method reports overlapping waste pieces, so larger offcuts are
+/// picked first and any that overlap an offcut already picked are skipped.
+pub(crate) fn offcuts(solution: &Solution, min_width: usize, min_length: usize) -> Vec<Offcut> {
+    let (min_short, min_long) = (min_width.min(min_length), min_width.max(min_length));
+    let mut offcuts = Vec::new();
+
+    for (sheet, stock_piece) in solution.stock_pieces.iter().enumerate() {
+        let mut candidates: Vec<RectFields> = stock_piece
+            .waste_pieces
+            .iter()
+            .map(RectFields::from)
+            .filter(|rect| {
+                rect.width.min(rect.length) >= min_short && rect.width.max(rect.length) >= min_long
+            })
+            .collect();
+        candidates.sort_by_key(|rect| std::cmp::Reverse(rect.width * rect.length));
+
+        let mut picked: Vec<RectFields> = Vec::new();
+        for rect in candidates {
+            if !picked.iter().any(|other| overlaps(&rect, other)) {
+                picked.push(rect);
+            }
+        }
+        offcuts.extend(picked.into_iter().map(|rect| Offcut { sheet, rect }));
+    }
+
+    offcuts
+}
+
+fn overlaps(a: &RectFields, b: &RectFields) -> bool {
+    a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.length && b.y < a.y + a.length
+}
+
+/// Edge allowance of a placed cut piece, turned to match how it was placed.
+/// Rotated pieces are turned a quarter turn clockwise, so their left edge ends
+/// up at the top.
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PlacedAllowance {
+    /// Index of the stock piece in the solution
+    pub(crate) sheet: usize,
+
+    /// Index of the cut piece in the stock piece's `cutPieces`
+    pub(crate) index: usize,
+
+    #[serde(flatten)]
+    pub(crate) allowance: EdgeAllowance,
+
+    /// Rectangle of the piece once the allowance is trimmed off
+    pub(crate) finished: RectFields,
+}
+
+/// Report the edge allowance of every placed copy of a cut piece that has
+/// one. `allowances` has an entry for each input cut piece.
+///
+/// Rotated pieces are turned a quarter turn clockwise, so their left edge ends
+/// up at the top.
+pub(crate) fn edge_allowances(
+    solution: &Solution,
+    groups: &[CutPieceGroup],
+    allowances: &[Option<EdgeAllowance>],
+) -> Vec<PlacedAllowance> {
+    let mut placed = Vec::new();
+    for (group, allowance) in groups.iter().zip(allowances) {
+        let allowance = match allowance {
+            Some(allowance) => *allowance,
+            None => continue,
+        };
+        for placement in &group.placements {
+            let cut_piece = &solution.stock_pieces[placement.sheet].cut_pieces[placement.index];
+            let allowance = if cut_piece.is_rotated {
+                EdgeAllowance {
+                    top: allowance.left,
+                    right: allowance.top,
+                    bottom: allowance.right,
+                    left: allowance.bottom,
+                }
+            } else {
+                allowance
+            };
+            placed.push(PlacedAllowance {
+                sheet: placement.sheet,
+                index: placement.index,
+                allowance,
+                finished: RectFields {
+                    x: cut_piece.x + allowance.left,
+                    y: cut_piece.y + allowance.top,
+                    width: cut_piece.width - allowance.left - allowance.right,
+                    length: cut_piece.length - allowance.top - allowance.bottom,
+                },
+            });
+        }
+    }
+
+    placed
+}
+
+/// Fraction of the used stock area covered by cut pieces
+pub(crate) fn utilization(solution: &Solution) -> f64 {
+    let stock_area: usize = solution
+        .stock_pieces
+        .iter()
+        .map(|stock_piece| stock_piece.width * stock_piece.length)
+        .sum();
+    let used_area: usize = solution.stock_pieces.iter().map(used_area).sum();
+    if stock_area == 0 {
+        1.0
+    } else {
+        used_area as f64 / stock_area as f64
+    }
+}
+
+/// Score the quality of a solution
+pub(crate) fn quality(
+    solution: &Solution,
+    cut_pieces: &[CutPiece],
+    cut_metrics: &CutMetrics,
+    weights: &QualityWeights,
+) -> Quality {
+    let mut stock_area = 0;
+    let mut used_area = 0;
+    let mut waste_area = 0;
+    let mut usable_waste_area = 0;
+    let mut piece_count = 0;
+
+    for stock_piece in &solution.stock_pieces {
+        stock_area += stock_piece.width * stock_piece.length;
+        used_area += stock_piece
+            .cut_pieces
+            .iter()
+            .map(|cp| cp.width * cp.length)
+            .sum::<usize>();
+        piece_count += stock_piece.cut_pieces.len();
+
+        for rect in stock_piece.waste_pieces.iter().map(RectFields::from) {
+            let area = rect.width * rect.length;
+            waste_area += area;
+            if cut_pieces.iter().any(|cp| fits(cp, &rect)) {
+                usable_waste_area += area;
+            }
+        }
+    }
+
+    let ratio = |part: usize, whole: usize| {
+        if whole == 0 {
+            1.0
+        } else {
+            part as f64 / whole as f64
+        }
+    };
+    let utilization = ratio(used_area, stock_area);
+    let offcut_usability = ratio(usable_waste_area, waste_area);
+    let cut_simplicity = ratio(piece_count, cut_metrics.cut_count).min(1.0);
+
+    let total_weight =
+        weights.utilization() + weights.offcut_usability() + weights.cut_simplicity();
+    let score = 100.0
+        * (weights.utilization() * utilization
+            + weights.offcut_usability() * offcut_usability
+            + weights.cut_simplicity() * cut_simplicity)
+        / total_weight;
+
+    Quality {
+        score,
+        utilization,
+        offcut_usability,
+        cut_simplicity,
+    }
+}
+
+/// Whether a cut piece fits in a rectangle, ignoring pattern direction
+fn fits(cut_piece: &CutPiece, rect: &RectFields) -> bool {
+    let (w, l) = (cut_piece.width, cut_piece.length);
+    (w <= rect.width && l <= rect.length)
+        || (cut_piece.can_rotate && l <= rect.width && w <= rect.length)
+}
@@ -0,0 +1,592 @@
+use axum::extract::Extension;
+use axum::response::{Headers, Html, IntoResponse};
+use axum::Json;
+use http::header::CONTENT_TYPE;
+use schemars::generate::SchemaSettings;
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde_json::{json, Map, Value};
+use std::borrow::Cow;
+
+use super::format::BodyError;
+use super::preset::Preset;
+use super::validation::ValidationError;
+use super::{Optimized, OptimizerConfig, OptimizerInput};
+use crate::proto::RectFields;
+
+/// Serve the interactive API explorer, backed by the OpenAPI document
+pub(super) async fn docs() -> Html<&'static str> {
+    Html(include_str!("docs.html"))
+}
+
+/// Serve RapiDoc 9.3.4, which the explorer runs on. It's bundled so the
+/// explorer works without internet access and never loads third-party code.
+pub(super) async fn docs_script() -> impl IntoResponse {
+    (
+        Headers([(CONTENT_TYPE, "text/javascript")]),
+        include_str!("rapidoc-min.js"),
+    )
+}
+
+/// Serve the OpenAPI document
+pub(super) async fn openapi_json(Extension(config): Extension<OptimizerConfig>) -> Json<Value> {
+    Json(openapi(config.max_dimension))
+}
+
+/// Serve JSON Schemas of the request, response, and error bodies
+pub(super) async fn json_schema(Extension(config): Extension<OptimizerConfig>) -> Json<Value> {
+    Json(json_schemas(config.max_dimension))
+}
+
+/// JSON Schema document with every body schema under `$defs`, and which of
+/// them the optimizer input, solution, and each error status use
+pub(super) fn json_schemas(max_dimension: Option<usize>) -> Value {
+    let mut defs = schemas(max_dimension);
+    rewrite_refs(&mut defs);
+    let def_ref = |name: &str| json!({ "$ref": format!("#/$defs/{}", name) });
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Cut Optimizer 2D Server bodies",
+        "input": def_ref("OptimizerInput"),
+        "solution": def_ref("Optimized"),
+        "errors": {
+            "400": def_ref("InvalidBodyError"),
+            "408": def_ref("Error"),
+            "415": def_ref("Error"),
+            "422": { "oneOf": [def_ref("ValidationErrors"), def_ref("NoFitError")] },
+            "429": def_ref("Error"),
+            "500": def_ref("Error")
+        },
+        "$defs": defs
+    })
+}
+
+/// Point OpenAPI component refs at `$defs` instead
+fn rewrite_refs(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::String(reference)) = map.get_mut("$ref") {
+                *reference = reference.replace("#/components/schemas/", "#/$defs/");
+            }
+            map.values_mut().for_each(rewrite_refs);
+        }
+        Value::Array(values) => values.iter_mut().for_each(rewrite_refs),
+        _ => {}
+    }
+}
+
+/// OpenAPI 3 document describing the HTTP API, with input dimensions bounded
+/// by the server's `--max-dimension`
+pub(super) fn openapi(max_dimension: Option<usize>) -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Cut Optimizer 2D Server",
+            "description": "Optimize rectangular cut pieces from sheet goods. Unversioned paths such as `/optimize` are aliases for the `/v1` paths.",
+            "version": env!("CARGO_PKG_VERSION"),
+            "license": { "name": "MIT OR Apache-2.0" }
+        },
+        "paths": {
+            "/v1/optimize": {
+                "post": {
+                    "operationId": "optimize",
+                    "summary": "Optimize cut pieces against the available stock pieces",
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "application/json": { "schema": schema_ref("OptimizerInput") },
+                            "application/cbor": { "schema": schema_ref("OptimizerInput") },
+                            "application/x-protobuf": {
+                                "schema": {
+                                    "type": "string",
+                                    "format": "binary",
+                                    "description": "`optimizer.OptimizeRequest` from `proto/optimizer.proto`"
+                                }
+                            }
+                        }
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "Optimized solution",
+                            "headers": backpressure_headers(),
+                            "content": {
+                                "application/json": { "schema": schema_ref("Optimized") },
+                                "application/cbor": { "schema": schema_ref("Optimized") },
+                                "application/x-protobuf": {
+                                    "schema": {
+                                        "type": "string",
+                                        "format": "binary",
+                                        "description": "`optimizer.Solution` from `proto/optimizer.proto`"
+                                    }
+                                }
+                            }
+                        },
+                        "400": error_response("Request body couldn't be decoded", "InvalidBodyError"),
+                        "408": error_response("Request took too long, no run finished within `maxSeconds` or the `bestEffort` deadline, or the job used more than the server's CPU time limit", "Error"),
+                        "415": error_response("Unsupported `Content-Type`", "Error"),
+                        "422": {
+                            "description": "Input failed validation, or a cut piece doesn't fit in any stock pieces",
+                            "content": {
+                                "application/json": {
+                                    "schema": {
+                                        "oneOf": [schema_ref("ValidationErrors"), schema_ref("NoFitError")]
+                                    }
+                                },
+                                "application/problem+json": { "schema": schema_ref("Problem") }
+                            }
+                        },
+                        "429": {
+                            "description": "Too many requests at once; wait for `X-Suggested-Delay-Ms` before trying again",
+                            "headers": backpressure_headers(),
+                            "content": {
+                                "application/json": { "schema": schema_ref("Error") },
+                                "application/problem+json": { "schema": schema_ref("Problem") }
+                            }
+                        },
+                        "500": error_response("Internal error", "Error")
+                    }
+                }
+            },
+            "/v1/rpc": {
+                "post": {
+                    "operationId": "rpc",
+                    "summary": "JSON-RPC 2.0 endpoint supporting the `optimize` method and batch calls",
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "oneOf": [
+                                        schema_ref("RpcRequest"),
+                                        {
+                                            "type": "array",
+                                            "items": schema_ref("RpcRequest"),
+                                            "description": "Batch of at most `--max-batch-size` calls, which run at once"
+                                        }
+                                    ]
+                                }
+                            }
+                        }
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "JSON-RPC response, or an array of responses for a batch",
+                            "content": {
+                                "application/json": {
+                                    "schema": {
+                                        "oneOf": [
+                                            schema_ref("RpcResponse"),
+                                            { "type": "array", "items": schema_ref("RpcResponse") }
+                                        ]
+                                    }
+                                }
+                            }
+                        },
+                        "204": { "description": "Every call was a notification" }
+                    }
+                }
+            },
+            "/catalogs/{name}/stock": catalog_path(),
+            "/presets/{name}": preset_path(),
+            "/metrics": {
+                "get": {
+                    "operationId": "metrics",
+                    "summary": "Optimizer pool metrics in the Prometheus text format",
+                    "responses": {
+                        "200": {
+                            "description": "Prometheus metrics",
+                            "content": { "text/plain": { "schema": { "type": "string" } } }
+                        }
+                    }
+                }
+            },
+            "/readyz": {
+                "get": {
+                    "operationId": "readyz",
+                    "summary": "Readiness of the server and its optional components",
+                    "description": "Checks each enabled component. The server stays ready while components are degraded, since it can still optimize inputs that don't use them, and solutions warn about them with `degradedComponent`.",
+                    "responses": {
+                        "200": {
+                            "description": "`status` is `ready`, or `degraded` if any component is failing",
+                            "content": { "application/json": { "schema": schema_ref("Readiness") } }
+                        }
+                    }
+                }
+            },
+            "/schema": {
+                "get": {
+                    "operationId": "schema",
+                    "summary": "JSON Schemas of the input, solution, and error bodies",
+                    "responses": {
+                        "200": {
+                            "description": "JSON Schema document with the body schemas under `$defs`, and `input`, `solution`, and `errors` by status code referring to them",
+                            "content": { "application/json": { "schema": { "type": "object" } } }
+                        }
+                    }
+                }
+            },
+            "/openapi.json": {
+                "get": {
+                    "operationId": "openapi",
+                    "summary": "This OpenAPI document",
+                    "responses": {
+                        "200": {
+                            "description": "OpenAPI document",
+                            "content": { "application/json": { "schema": { "type": "object" } } }
+                        }
+                    }
+                }
+            }
+        },
+        "components": {
+            "schemas": schemas(max_dimension)
+        }
+    })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+/// Operations on a stock catalog, which are only served if the server has a
+/// catalog directory
+fn catalog_path() -> Value {
+    let stock_pieces = json!({
+        "application/json": {
+            "schema": { "type": "array", "items": schema_ref("StockPiece") }
+        }
+    });
+    let catalog =
+        |description: &str| json!({ "description": description, "content": stock_pieces });
+    let name_error = error_response("Invalid catalog name or request body", "InvalidBodyError");
+    let not_found = error_response(
+        "No catalog with that name, or catalogs aren't enabled",
+        "Error",
+    );
+
+    json!({
+        "parameters": [{
+            "name": "name",
+            "in": "path",
+            "required": true,
+            "description": "Catalog name, made of letters, digits, `-`, `_`, and `.`",
+            "schema": { "type": "string", "pattern": "^[A-Za-z0-9_-][A-Za-z0-9._-]*$" }
+        }],
+        "get": {
+            "operationId": "getStockCatalog",
+            "summary": "List the stock pieces in a catalog",
+            "responses": { "200": catalog("Stock pieces in the catalog"), "400": name_error, "404": not_found }
+        },
+        "put": {
+            "operationId": "putStockCatalog",
+            "summary": "Create or replace a catalog",
+            "requestBody": { "required": true, "content": stock_pieces },
+            "responses": { "200": catalog("Stock pieces now in the catalog"), "400": name_error, "404": not_found }
+        },
+        "post": {
+            "operationId": "addToStockCatalog",
+            "summary": "Add stock pieces to a catalog, creating it if needed",
+            "requestBody": { "required": true, "content": stock_pieces },
+            "responses": { "200": catalog("Stock pieces now in the catalog"), "400": name_error, "404": not_found }
+        },
+        "delete": {
+            "operationId": "deleteStockCatalog",
+            "summary": "Delete a catalog",
+            "responses": { "204": { "description": "Catalog deleted" }, "400": name_error, "404": not_found }
+        }
+    })
+}
+
+/// Operations on a preset, which are only served if the server has a preset
+/// directory
+fn preset_path() -> Value {
+    let preset = json!({ "application/json": { "schema": schema_ref("Preset") } });
+    let ok = |description: &str| json!({ "description": description, "content": preset });
+    let name_error = error_response("Invalid preset name or request body", "InvalidBodyError");
+    let not_found = error_response(
+        "No preset with that name, or presets aren't enabled",
+        "Error",
+    );
+
+    json!({
+        "parameters": [{
+            "name": "name",
+            "in": "path",
+            "required": true,
+            "description": "Preset name, made of letters, digits, `-`, `_`, and `.`",
+            "schema": { "type": "string", "pattern": "^[A-Za-z0-9_-][A-Za-z0-9._-]*$" }
+        }],
+        "get": {
+            "operationId": "getPreset",
+            "summary": "Get a preset",
+            "responses": { "200": ok("The preset"), "400": name_error, "404": not_found }
+        },
+        "put": {
+            "operationId": "putPreset",
+            "summary": "Create or replace a preset",
+            "requestBody": { "required": true, "content": preset },
+            "responses": { "200": ok("The stored preset"), "400": name_error, "404": not_found }
+        },
+        "delete": {
+            "operationId": "deletePreset",
+            "summary": "Delete a preset",
+            "responses": { "204": { "description": "Preset deleted" }, "400": name_error, "404": not_found }
+        }
+    })
+}
+
+fn error_response(description: &str, schema: &str) -> Value {
+    json!({
+        "description": description,
+        "content": {
+            "application/json": { "schema": schema_ref(schema) },
+            "application/problem+json": { "schema": schema_ref("Problem") }
+        }
+    })
+}
+
+/// Headers on every response telling clients how busy the server is
+fn backpressure_headers() -> Value {
+    let header = |description: &str| json!({ "description": description, "schema": { "type": "integer", "minimum": 0 } });
+    json!({
+        "X-Queue-Depth": header("Optimizer jobs waiting for a worker"),
+        "X-Concurrency-Remaining": header("Requests that could start now before new ones are turned away with 429"),
+        "X-Suggested-Delay-Ms": header("How long to wait before the next request, 0 while there's room, and otherwise an estimate from the waiting jobs and how long jobs have been taking")
+    })
+}
+
+/// Schemas of the request, response, and error bodies. Inputs are described
+/// as they're read and everything else as it's written, since they differ in
+/// which fields are required. Input dimensions are bounded by `max_dimension`.
+pub(super) fn schemas(max_dimension: Option<usize>) -> Value {
+    let mut schemas = definitions(SchemaSettings::openapi3().for_deserialize(), |generator| {
+        generator.subschema_for::<OptimizerInput>();
+        generator.subschema_for::<Preset>();
+    });
+    // Types in both are written the way they're read, which only ever allows
+    // more, such as leaving out fields that have defaults
+    let outputs = definitions(SchemaSettings::openapi3().for_serialize(), |generator| {
+        generator.subschema_for::<Optimized>();
+        generator.subschema_for::<BodyError>();
+        generator.subschema_for::<ValidationError>();
+        generator.subschema_for::<ResolvedCutPiece>();
+    });
+    for (name, schema) in outputs {
+        schemas.entry(name).or_insert(schema);
+    }
+    if let Value::Object(envelopes) = envelopes() {
+        schemas.extend(envelopes);
+    }
+
+    if let Some(max_dimension) = max_dimension {
+        let max_dimension = json!(max_dimension);
+        for property in &["width", "length"] {
+            schemas["StockPiece"]["properties"][property]["maximum"] = max_dimension.clone();
+        }
+        schemas["Dimension"]["oneOf"][0]["maximum"] = max_dimension;
+    }
+    Value::Object(schemas)
+}
+
+fn definitions(
+    settings: SchemaSettings,
+    add: impl FnOnce(&mut SchemaGenerator),
+) -> Map<String, Value> {
+    let mut generator = settings.into_generator();
+    add(&mut generator);
+    generator.take_definitions(true)
+}
+
+/// Bodies the handlers build as JSON rather than from a type, wrapping the
+/// generated schemas
+fn envelopes() -> Value {
+    json!({
+        "Error": {
+            "type": "object",
+            "required": ["message"],
+            "properties": {
+                "message": { "type": "string" },
+                "data": { "description": "Details about the error, if any" }
+            }
+        },
+        "Problem": {
+            "type": "object",
+            "description": "RFC 7807 problem details, returned instead of the usual error body when the request's `Accept` includes `application/problem+json`",
+            "required": ["type", "title", "status"],
+            "properties": {
+                "type": {
+                    "type": "string",
+                    "format": "uri",
+                    "description": "The server's problem type prefix followed by the code of the first validation error, such as `urn:cut-optimizer-2d:problem:noFit`, or `about:blank` for errors without a code"
+                },
+                "title": { "type": "string" },
+                "status": { "type": "integer" },
+                "detail": { "type": "string" },
+                "errors": {
+                    "type": "array",
+                    "description": "Validation errors, each with the `type` of its code",
+                    "items": { "type": "object" }
+                },
+                "data": { "description": "Details about an error without a code, if any" }
+            }
+        },
+        "InvalidBodyError": {
+            "type": "object",
+            "required": ["message", "data"],
+            "properties": {
+                "message": { "type": "string" },
+                "data": schema_ref("BodyError")
+            }
+        },
+        "ValidationErrors": {
+            "type": "object",
+            "required": ["message", "data"],
+            "properties": {
+                "message": { "type": "string" },
+                "data": { "type": "array", "items": schema_ref("ValidationError") }
+            }
+        },
+        "NoFitError": {
+            "type": "object",
+            "required": ["message", "data"],
+            "properties": {
+                "message": { "type": "string" },
+                "data": schema_ref("ResolvedCutPiece")
+            }
+        },
+        "Readiness": {
+            "type": "object",
+            "required": ["status", "components"],
+            "properties": {
+                "status": { "type": "string", "enum": ["ready", "degraded"] },
+                "components": {
+                    "type": "object",
+                    "description": "Status of `optimizer` and of each enabled component: `stockCatalogs`, `presets`, and `translations`",
+                    "additionalProperties": {
+                        "type": "object",
+                        "required": ["status"],
+                        "properties": {
+                            "status": { "type": "string", "enum": ["ok", "degraded"] },
+                            "error": { "type": "string", "description": "Why a degraded component last failed" }
+                        }
+                    }
+                }
+            }
+        },
+        "RpcRequest": {
+            "type": "object",
+            "required": ["jsonrpc", "method"],
+            "properties": {
+                "jsonrpc": { "type": "string", "enum": ["2.0"] },
+                "method": { "type": "string", "enum": ["optimize"] },
+                "params": schema_ref("OptimizerInput"),
+                "id": {
+                    "oneOf": [{ "type": "string" }, { "type": "number" }],
+                    "nullable": true,
+                    "description": "Omit to send a notification"
+                }
+            }
+        },
+        "RpcResponse": {
+            "type": "object",
+            "required": ["jsonrpc", "id"],
+            "properties": {
+                "jsonrpc": { "type": "string", "enum": ["2.0"] },
+                "result": schema_ref("Optimized"),
+                "error": {
+                    "type": "object",
+                    "required": ["code", "message"],
+                    "properties": {
+                        "code": { "type": "integer" },
+                        "message": { "type": "string" },
+                        "data": {}
+                    }
+                },
+                "id": {
+                    "oneOf": [{ "type": "string" }, { "type": "number" }],
+                    "nullable": true
+                }
+            }
+        }
+    })
+}
+
+/// Schema of a `cut_optimizer_2d` type, which can't derive one itself, for
+/// fields of that type to use with `#[schemars(with = "...")]`
+macro_rules! library_schema {
+    ($(#[$doc:meta])* $name:ident, |$generator:ident| $schema:expr) => {
+        $(#[$doc])*
+        pub(super) struct $name;
+
+        impl JsonSchema for $name {
+            fn schema_name() -> Cow<'static, str> {
+                stringify!($name).into()
+            }
+
+            fn json_schema($generator: &mut SchemaGenerator) -> Schema {
+                $schema
+            }
+        }
+    };
+}
+
+library_schema!(PatternDirection, |_generator| json_schema!({
+    "type": "string",
+    "enum": ["none", "parallelToWidth", "parallelToLength"]
+}));
+
+library_schema!(
+    /// Layout of a solution, which is flattened into the server's solutions
+    Solution,
+    |generator| json_schema!({
+        "type": "object",
+        "required": ["fitness", "stockPieces"],
+        "properties": {
+            "fitness": { "type": "number" },
+            "stockPieces": { "type": "array", "items": generator.subschema_for::<ResultStockPiece>() }
+        }
+    })
+);
+
+library_schema!(ResultStockPiece, |generator| json_schema!({
+    "type": "object",
+    "required": ["width", "length", "patternDirection", "cutPieces", "wastePieces"],
+    "properties": {
+        "width": { "type": "integer", "minimum": 0, "description": "Width of the stock piece" },
+        "length": { "type": "integer", "minimum": 0, "description": "Length of the stock piece" },
+        "patternDirection": generator.subschema_for::<PatternDirection>(),
+        "cutPieces": { "type": "array", "items": generator.subschema_for::<ResultCutPiece>() },
+        "wastePieces": { "type": "array", "items": generator.subschema_for::<RectFields>() }
+    }
+}));
+
+library_schema!(ResultCutPiece, |generator| json_schema!({
+    "type": "object",
+    "required": ["x", "y", "width", "length", "patternDirection", "isRotated"],
+    "properties": {
+        "externalId": { "type": "integer", "minimum": 0, "nullable": true },
+        "x": { "type": "integer", "minimum": 0, "description": "Offset from the left edge of the stock piece" },
+        "y": { "type": "integer", "minimum": 0, "description": "Offset from the top edge of the stock piece" },
+        "width": { "type": "integer", "minimum": 0, "description": "Width as placed" },
+        "length": { "type": "integer", "minimum": 0, "description": "Length as placed" },
+        "patternDirection": generator.subschema_for::<PatternDirection>(),
+        "isRotated": { "type": "boolean" }
+    }
+}));
+
+library_schema!(
+    /// Cut piece as it was given to the optimizer, such as the one in a
+    /// `noFit` error
+    ResolvedCutPiece,
+    |generator| json_schema!({
+        "type": "object",
+        "required": ["externalId", "width", "length", "patternDirection", "canRotate"],
+        "properties": {
+            "externalId": { "type": "integer", "minimum": 0, "nullable": true },
+            "width": { "type": "integer", "minimum": 0 },
+            "length": { "type": "integer", "minimum": 0 },
+            "patternDirection": generator.subschema_for::<PatternDirection>(),
+            "canRotate": { "type": "boolean" }
+        }
+    })
+);
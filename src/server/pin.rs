@@ -0,0 +1,64 @@
+use cut_optimizer_2d::{CutPiece, ResultCutPiece};
+
+use super::quantity::Expansion;
+use super::validation::rotated;
+use super::{InputStockPiece, OptimizerInput, Pin};
+
+/// Where a pin places its cut piece, measured from the corner of the stock
+/// piece's usable area
+pub(crate) fn placement(
+    pin: &Pin,
+    cut_piece: &CutPiece,
+    stock_piece: &InputStockPiece,
+) -> ResultCutPiece {
+    let (width, length, pattern_direction) = if pin.is_rotated {
+        (
+            cut_piece.length,
+            cut_piece.width,
+            rotated(cut_piece.pattern_direction),
+        )
+    } else {
+        (
+            cut_piece.width,
+            cut_piece.length,
+            cut_piece.pattern_direction,
+        )
+    };
+    ResultCutPiece {
+        external_id: cut_piece.external_id,
+        x: pin.x.saturating_sub(stock_piece.trim_left),
+        y: pin.y.saturating_sub(stock_piece.trim_top),
+        width,
+        length,
+        pattern_direction,
+        is_rotated: pin.is_rotated,
+    }
+}
+
+/// Take the pinned copies out of the optimization, returning the index of the
+/// stock piece and the placement of each, which validation has checked
+pub(crate) fn placements(
+    input: &OptimizerInput,
+    cut_pieces: &[CutPiece],
+    expansion: &mut Expansion,
+) -> Vec<(usize, ResultCutPiece)> {
+    input
+        .pins
+        .iter()
+        .map(|pin| {
+            let placement = placement(
+                pin,
+                &cut_pieces[pin.cut_piece],
+                &input.stock_pieces[pin.stock_piece],
+            );
+            let external_id = Some(expansion.pin(pin.cut_piece));
+            (
+                pin.stock_piece,
+                ResultCutPiece {
+                    external_id,
+                    ..placement
+                },
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,136 @@
+use axum::body::Bytes;
+use axum::extract::{Extension, Path};
+use axum::Json;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::store::{self, Store};
+use super::validation::{ValidationError, MAX_SEEDS};
+use super::{error, invalid_input, InputStockPiece, OptimizeError, OptimizeMethod};
+use super::{OptimizerConfig, OptimizerInput};
+
+/// Named defaults for inputs that refer to them by `preset`. Anything the
+/// input sets itself takes precedence.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub(crate) struct Preset {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) method: Option<OptimizeMethod>,
+
+    /// Width of the blade (kerf)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cut_width: Option<usize>,
+
+    /// Seed policy, used only if the input sets none of `randomSeed`,
+    /// `seedCount`, and `seeds`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) random_seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 1, max = MAX_SEEDS))]
+    pub(crate) seed_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) seeds: Option<Vec<u64>>,
+
+    /// Default stock, used only if the input has neither `stockPieces` nor
+    /// `stockCatalog`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) stock_pieces: Vec<InputStockPiece>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) stock_catalog: Option<String>,
+}
+
+impl Preset {
+    /// Fill in what the input leaves unset
+    fn apply(self, input: &mut OptimizerInput) {
+        input.method = input.method.or(self.method);
+        input.cut_width = input.cut_width.or(self.cut_width);
+
+        // Seeds override the other two, so mixing them would be confusing
+        if input.random_seed.is_none() && input.seed_count.is_none() && input.seeds.is_none() {
+            input.random_seed = self.random_seed;
+            input.seed_count = self.seed_count;
+            input.seeds = self.seeds;
+        }
+
+        if input.stock_pieces.is_empty() && input.stock_catalog.is_none() {
+            input.stock_pieces = self.stock_pieces;
+            input.stock_catalog = self.stock_catalog;
+        }
+    }
+}
+
+fn presets(config: &OptimizerConfig) -> Result<&Store, OptimizeError> {
+    config
+        .presets
+        .as_deref()
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Presets aren't enabled"))
+}
+
+/// Fill in what the input leaves unset from its preset
+pub(crate) async fn resolve(
+    config: &OptimizerConfig,
+    input: &mut OptimizerInput,
+) -> Result<(), OptimizeError> {
+    let name = match &input.preset {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+
+    let unknown = |message: String| {
+        invalid_input(vec![ValidationError::new(
+            "unknownPreset",
+            "preset".to_string(),
+            message,
+        )])
+    };
+    let presets = config
+        .presets
+        .as_deref()
+        .ok_or_else(|| unknown("Presets aren't enabled".to_string()))?;
+    let preset: Preset = presets
+        .get(name)
+        .await?
+        .ok_or_else(|| unknown(format!("No preset named `{}`", name)))?;
+    preset.apply(input);
+
+    Ok(())
+}
+
+/// Get a preset
+pub(super) async fn get_preset(
+    Extension(config): Extension<OptimizerConfig>,
+    Path(name): Path<String>,
+) -> Result<Json<Preset>, OptimizeError> {
+    let presets = presets(&config)?;
+    presets
+        .get(&name)
+        .await?
+        .map(Json)
+        .ok_or_else(|| presets.not_found())
+}
+
+/// Create or replace a preset
+pub(super) async fn put_preset(
+    Extension(config): Extension<OptimizerConfig>,
+    Path(name): Path<String>,
+    body: Bytes,
+) -> Result<Json<Preset>, OptimizeError> {
+    let presets = presets(&config)?;
+    let preset: Preset = store::decode(&body)?;
+    presets.put(&name, &preset).await?;
+    Ok(Json(preset))
+}
+
+/// Delete a preset
+pub(super) async fn delete_preset(
+    Extension(config): Extension<OptimizerConfig>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, OptimizeError> {
+    let presets = presets(&config)?;
+    if presets.delete(&name).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(presets.not_found())
+    }
+}
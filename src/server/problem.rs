@@ -0,0 +1,158 @@
+use axum::body::{self, BoxBody, Full};
+use axum::response::Response;
+use futures::future::BoxFuture;
+use http::header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use http::{Request, StatusCode};
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+use super::format::{accepted, media_type};
+
+const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Rewrite JSON error responses as RFC 7807 problem details for requests that
+/// accept `application/problem+json`. Other requests keep the
+/// `{message, data}` shape.
+#[derive(Debug, Clone)]
+pub(crate) struct ProblemLayer {
+    type_base: Arc<str>,
+}
+
+impl ProblemLayer {
+    /// Layer whose problem types are `type_base` followed by the error code
+    pub(crate) fn new(type_base: &str) -> Self {
+        Self {
+            type_base: type_base.into(),
+        }
+    }
+}
+
+impl<S> Layer<S> for ProblemLayer {
+    type Service = Problems<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Problems {
+            inner,
+            type_base: self.type_base.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Problems<S> {
+    inner: S,
+    type_base: Arc<str>,
+}
+
+impl<S, B> Service<Request<B>> for Problems<S>
+where
+    S: Service<Request<B>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let wants_problem = accepted(request.headers())
+            .iter()
+            .any(|(media_type, _)| *media_type == PROBLEM_JSON);
+        let type_base = self.type_base.clone();
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = response.await?;
+            let is_json = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| media_type(value) == "application/json");
+            if !wants_problem || !is_json || response.status().is_success() {
+                return Ok(response);
+            }
+
+            let (mut parts, body) = response.into_parts();
+            let bytes = match hyper::body::to_bytes(body).await {
+                Ok(bytes) => bytes,
+                Err(_) => return Ok(Response::from_parts(parts, body::boxed(Full::default()))),
+            };
+            let problem = match serde_json::from_slice(&bytes) {
+                Ok(error) => problem(error, parts.status, &type_base),
+                Err(_) => return Ok(Response::from_parts(parts, full(bytes.to_vec()))),
+            };
+
+            let bytes = serde_json::to_vec(&problem).expect("Problem should serialize");
+            parts
+                .headers
+                .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+            parts.headers.remove(CONTENT_LENGTH);
+            Ok(Response::from_parts(parts, full(bytes)))
+        })
+    }
+}
+
+fn full(bytes: Vec<u8>) -> BoxBody {
+    body::boxed(Full::from(bytes))
+}
+
+/// Problem details for a `{message, data}` error body. Validation errors get
+/// the type of their code, and are listed under `errors` with their own types.
+/// Other errors have no type beyond their status, so they're `about:blank`.
+fn problem(error: Value, status: StatusCode, type_base: &str) -> Value {
+    let mut error = match error {
+        Value::Object(error) => error,
+        error => {
+            let mut object = Map::new();
+            object.insert("data".to_string(), error);
+            object
+        }
+    };
+    let message = error.remove("message");
+    let data = error.remove("data");
+    let type_of = |code: &str| format!("{}{}", type_base, code);
+
+    let coded = data.as_ref().and_then(Value::as_array).filter(|errors| {
+        !errors.is_empty()
+            && errors
+                .iter()
+                .all(|e| e.get("code").is_some_and(Value::is_string))
+    });
+    match coded {
+        Some(errors) => {
+            let errors: Vec<_> = errors
+                .iter()
+                .cloned()
+                .map(|mut e| {
+                    let code = e["code"].as_str().unwrap_or_default().to_string();
+                    e["type"] = type_of(&code).into();
+                    e
+                })
+                .collect();
+            json!({
+                "type": errors[0]["type"],
+                "title": message,
+                "status": status.as_u16(),
+                "detail": errors[0]["message"],
+                "errors": errors
+            })
+        }
+        None => {
+            let mut problem = json!({
+                "type": "about:blank",
+                "title": status.canonical_reason(),
+                "status": status.as_u16(),
+                "detail": message
+            });
+            if let Some(data) = data {
+                problem["data"] = data;
+            }
+            problem
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use cut_optimizer_2d::{CutPiece, Solution};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// External IDs of pinned copies start here, well past those of the
+/// optimized copies and fillers
+const PINNED_IDS: usize = usize::MAX / 2;
+
+/// Cut pieces repeated by their quantities. While optimizing, each copy's
+/// external ID is its position in the expanded list, so it can be traced back
+/// to the input cut piece it came from.
+pub(crate) struct Expansion {
+    pub(crate) cut_pieces: Vec<CutPiece>,
+
+    /// Index of the input cut piece and of the copy, for each expanded piece
+    sources: Vec<(usize, usize)>,
+
+    /// Input cut pieces, with their external IDs as given
+    inputs: Vec<CutPiece>,
+
+    quantities: Vec<usize>,
+
+    /// Input cut pieces left out of the optimization, in the order they were
+    /// left out
+    left_out: Vec<usize>,
+
+    /// Input cut pieces placed only in leftover room after optimizing
+    optional: Vec<usize>,
+
+    /// Index of the input cut piece and of the copy, for each pinned copy
+    pinned: Vec<(usize, usize)>,
+}
+
+/// Where the copies of one input cut piece were placed
+#[derive(Serialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CutPieceGroup {
+    /// Index of the input cut piece in `cutPieces`
+    pub(crate) cut_piece: usize,
+    pub(crate) external_id: Option<usize>,
+    #[schemars(range(min = 1))]
+    pub(crate) quantity: usize,
+
+    /// Placement of each copy, in order
+    pub(crate) placements: Vec<Placement>,
+
+    /// Whether the copies were only placed where there was room left over, so
+    /// fewer than `quantity` may have been
+    pub(crate) optional: bool,
+}
+
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Placement {
+    /// Index of the stock piece in `stockPieces`
+    pub(crate) sheet: usize,
+
+    /// Index of the cut piece in the stock piece's `cutPieces`
+    pub(crate) index: usize,
+}
+
+/// Input cut piece that couldn't be placed
+#[derive(Serialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UnplacedPiece {
+    /// Index of the input cut piece in `cutPieces`
+    pub(crate) cut_piece: usize,
+    pub(crate) external_id: Option<usize>,
+
+    /// Number of copies left out
+    #[schemars(range(min = 1))]
+    pub(crate) quantity: usize,
+}
+
+impl Expansion {
+    /// Repeat each cut piece by the quantity at the same index
+    pub(crate) fn new(cut_pieces: &[CutPiece], quantities: Vec<usize>) -> Self {
+        let mut expanded = Vec::new();
+        let mut sources = Vec::new();
+        for (i, (cut_piece, &quantity)) in cut_pieces.iter().zip(&quantities).enumerate() {
+            for copy in 0..quantity {
+                expanded.push(CutPiece {
+                    external_id: Some(expanded.len()),
+                    ..cut_piece.clone()
+                });
+                sources.push((i, copy));
+            }
+        }
+
+        Self {
+            cut_pieces: expanded,
+            sources,
+            inputs: cut_pieces.to_vec(),
+            quantities,
+            left_out: Vec::new(),
+            optional: Vec::new(),
+            pinned: Vec::new(),
+        }
+    }
+
+    /// Stop optimizing every copy of the input cut piece at `index`
+    pub(crate) fn leave_out(&mut self, index: usize) {
+        if self.left_out.contains(&index) {
+            return;
+        }
+        self.left_out.push(index);
+        self.remove(index);
+    }
+
+    /// Optimize without the input cut piece at `index`, leaving its copies
+    /// for [`fillers`](Self::fillers) instead
+    pub(crate) fn make_optional(&mut self, index: usize) {
+        if self.optional.contains(&index) {
+            return;
+        }
+        self.optional.push(index);
+        self.remove(index);
+    }
+
+    /// Optimize one copy fewer of the input cut piece at `index`, since it's
+    /// pinned in place, returning the external ID to place the pinned copy
+    /// with
+    pub(crate) fn pin(&mut self, index: usize) -> usize {
+        let pinned = self.pinned.iter().filter(|(i, _)| *i == index).count();
+        let copy = self.quantities[index] - 1 - pinned;
+        self.pinned.push((index, copy));
+        self.retain(|source| source != (index, copy));
+        PINNED_IDS + self.pinned.len() - 1
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.retain(|(i, _)| i != index);
+    }
+
+    fn retain(&mut self, keep: impl Fn((usize, usize)) -> bool) {
+        let (cut_pieces, sources) = self
+            .cut_pieces
+            .drain(..)
+            .zip(self.sources.drain(..))
+            .filter(|(_, source)| keep(*source))
+            .unzip();
+        self.cut_pieces = cut_pieces;
+        self.sources = sources;
+        for (id, cut_piece) in self.cut_pieces.iter_mut().enumerate() {
+            cut_piece.external_id = Some(id);
+        }
+    }
+
+    /// Input cut pieces left out of the optimization
+    pub(crate) fn unplaced(&self) -> Vec<UnplacedPiece> {
+        self.left_out
+            .iter()
+            .map(|&i| UnplacedPiece {
+                cut_piece: i,
+                external_id: self.inputs[i].external_id,
+                quantity: self.quantities[i]
+                    - self
+                        .pinned
+                        .iter()
+                        .filter(|(pinned, _)| *pinned == i)
+                        .count(),
+            })
+            .collect()
+    }
+
+    /// Index of the input cut piece and of the copy, for each filler
+    fn filler_sources(&self) -> Vec<(usize, usize)> {
+        let mut optional = self.optional.clone();
+        optional.sort_unstable();
+        optional
+            .into_iter()
+            .flat_map(|i| (0..self.quantities[i]).map(move |copy| (i, copy)))
+            .collect()
+    }
+
+    /// Copies of the optional cut pieces, to place in a solution's leftover
+    /// room. Their external IDs follow on from the optimized cut pieces'.
+    pub(crate) fn fillers(&self) -> Vec<CutPiece> {
+        self.filler_sources()
+            .into_iter()
+            .enumerate()
+            .map(|(k, (i, _))| CutPiece {
+                external_id: Some(self.sources.len() + k),
+                ..self.inputs[i].clone()
+            })
+            .collect()
+    }
+
+    /// Give the cut pieces in a solution back their input external IDs,
+    /// returning where the copies of each input cut piece were placed
+    pub(crate) fn restore(&self, solution: &mut Solution) -> Vec<CutPieceGroup> {
+        let fillers = self.filler_sources();
+        let source = |id: usize| {
+            self.sources
+                .get(id)
+                .or_else(|| fillers.get(id.checked_sub(self.sources.len())?))
+                .or_else(|| self.pinned.get(id.checked_sub(PINNED_IDS)?))
+        };
+
+        let mut placements: Vec<Vec<Option<Placement>>> = self
+            .quantities
+            .iter()
+            .map(|&quantity| vec![None; quantity])
+            .collect();
+
+        for (sheet, stock_piece) in solution.stock_pieces.iter_mut().enumerate() {
+            for (index, cut_piece) in stock_piece.cut_pieces.iter_mut().enumerate() {
+                if let Some(&(i, copy)) = cut_piece.external_id.and_then(source) {
+                    cut_piece.external_id = self.inputs[i].external_id;
+                    placements[i][copy] = Some(Placement { sheet, index });
+                }
+            }
+        }
+
+        placements
+            .into_iter()
+            .enumerate()
+            .map(|(i, placements)| CutPieceGroup {
+                cut_piece: i,
+                external_id: self.inputs[i].external_id,
+                quantity: self.quantities[i],
+                placements: placements.into_iter().flatten().collect(),
+                optional: self.optional.contains(&i),
+            })
+            .collect()
+    }
+
+    /// Input cut piece that an expanded cut piece is a copy of
+    pub(crate) fn source(&self, cut_piece: &CutPiece) -> CutPiece {
+        self.source_index(cut_piece)
+            .map(|i| self.inputs[i].clone())
+            .unwrap_or_else(|| cut_piece.clone())
+    }
+
+    /// Index of the input cut piece that an expanded cut piece is a copy of
+    pub(crate) fn source_index(&self, cut_piece: &CutPiece) -> Option<usize> {
+        cut_piece
+            .external_id
+            .and_then(|id| self.sources.get(id))
+            .map(|&(i, _)| i)
+    }
+}
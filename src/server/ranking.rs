@@ -0,0 +1,169 @@
+use cut_optimizer_2d::{ResultStockPiece, Solution, StockPiece};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+use super::metrics;
+
+/// What to minimize when picking between solutions. Ties are broken by total
+/// price, then by waste area, then by the optimizer's fitness.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[schemars(extend("default" = "minCost"))]
+pub(crate) enum Objective {
+    #[default]
+    #[serde(rename = "minCost")]
+    Cost,
+    #[serde(rename = "minWaste")]
+    Waste,
+    #[serde(rename = "minSheets")]
+    Sheets,
+    #[serde(rename = "minCutLength")]
+    CutLength,
+}
+
+/// How good a solution is, compared according to an `Objective`
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Score {
+    /// Total price of the stock pieces used
+    pub(crate) price: usize,
+
+    /// Area of the stock pieces not covered by cut pieces
+    pub(crate) waste_area: usize,
+
+    /// Number of stock pieces used
+    pub(crate) sheet_count: usize,
+
+    /// Total length of all cuts
+    pub(crate) cut_length: usize,
+    pub(crate) fitness: f64,
+    #[serde(skip)]
+    pub(crate) objective: Objective,
+}
+
+impl Score {
+    pub(crate) fn new(
+        solution: &Solution,
+        stock_pieces: &[StockPiece],
+        cut_width: usize,
+        objective: Objective,
+    ) -> Self {
+        let mut price = 0;
+        let mut waste_area = 0;
+        for used in &solution.stock_pieces {
+            price += sheet_price(used, stock_pieces);
+            waste_area += used.width * used.length - used_area(used);
+        }
+
+        Self {
+            price,
+            waste_area,
+            sheet_count: solution.stock_pieces.len(),
+            cut_length: metrics::cut_metrics(solution, cut_width, None).cut_length,
+            fitness: solution.fitness,
+            objective,
+        }
+    }
+
+    /// Values to minimize, most important first
+    fn key(&self) -> [usize; 3] {
+        match self.objective {
+            Objective::Cost => [self.price, self.waste_area, 0],
+            Objective::Waste => [self.waste_area, self.price, 0],
+            Objective::Sheets => [self.sheet_count, self.price, self.waste_area],
+            Objective::CutLength => [self.cut_length, self.price, self.waste_area],
+        }
+    }
+
+    pub(crate) fn is_better_than(&self, other: &Score) -> bool {
+        self.key() < other.key() || (self.key() == other.key() && self.fitness > other.fitness)
+    }
+}
+
+/// Price of a used stock piece. The library doesn't report prices per sheet, so
+/// they're looked up from the matching input stock piece, taking the cheapest
+/// if several match.
+pub(crate) fn sheet_price(used: &ResultStockPiece, stock_pieces: &[StockPiece]) -> usize {
+    stock_pieces
+        .iter()
+        .filter(|sp| {
+            sp.width == used.width
+                && sp.length == used.length
+                && sp.pattern_direction == used.pattern_direction
+        })
+        .map(|sp| sp.price)
+        .min()
+        .unwrap_or_default()
+}
+
+/// Area of a used stock piece covered by cut pieces
+pub(crate) fn used_area(used: &ResultStockPiece) -> usize {
+    used.cut_pieces.iter().map(|cp| cp.width * cp.length).sum()
+}
+
+/// Sort the successful results best first, keeping the order of equally good
+/// ones, or return the first error if every result failed
+pub(crate) fn ranked<T, E, I, F>(results: I, score: F) -> Result<Vec<T>, E>
+where
+    I: IntoIterator<Item = Result<T, E>>,
+    F: Fn(&T) -> Score,
+{
+    let mut ranked = Vec::new();
+    let mut first_error = None;
+    for result in results {
+        match result {
+            Ok(candidate) => ranked.push(candidate),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    match first_error {
+        Some(e) if ranked.is_empty() => return Err(e),
+        None if ranked.is_empty() => panic!("No results to rank"),
+        _ => {}
+    }
+    ranked.sort_by(|a, b| {
+        let (a, b) = (score(a), score(b));
+        if a.is_better_than(&b) {
+            Ordering::Less
+        } else if b.is_better_than(&a) {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    });
+    Ok(ranked)
+}
+
+/// Pick the best successful result, or the first error if every result failed
+pub(crate) fn best<T, E, I, F>(results: I, score: F) -> Result<T, E>
+where
+    I: IntoIterator<Item = Result<T, E>>,
+    F: Fn(&T) -> Score,
+{
+    let mut best: Option<(T, Score)> = None;
+    let mut first_error = None;
+
+    for result in results {
+        match result {
+            Ok(candidate) => {
+                let candidate_score = score(&candidate);
+                match best {
+                    Some((_, ref best_score)) if !candidate_score.is_better_than(best_score) => {}
+                    _ => best = Some((candidate, candidate_score)),
+                }
+            }
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    match (best, first_error) {
+        (Some((best, _)), _) => Ok(best),
+        (None, Some(e)) => Err(e),
+        (None, None) => panic!("No results to pick the best from"),
+    }
+}
@@ -0,0 +1,128 @@
+use axum::body::Bytes;
+use axum::extract::Extension;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use futures::future::join_all;
+use http::StatusCode;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::format::from_value;
+use super::{run_optimizer, OptimizerConfig, OptimizerInput};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Call {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Handle a JSON-RPC 2.0 request or batch of requests
+pub(super) async fn rpc(Extension(config): Extension<OptimizerConfig>, body: Bytes) -> Response {
+    let request: Value = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = rpc_error(PARSE_ERROR, "Parse error", Some(e.to_string().into()));
+            return Json(error_response(Value::Null, error)).into_response();
+        }
+    };
+
+    let response = match request {
+        Value::Array(calls) if calls.is_empty() => Some(error_response(
+            Value::Null,
+            rpc_error(INVALID_REQUEST, "Invalid Request", None),
+        )),
+        // Every call of a batch runs at once, so large batches would get
+        // around the limit on concurrent requests
+        Value::Array(calls) if calls.len() > config.max_batch_size => Some(error_response(
+            Value::Null,
+            rpc_error(
+                INVALID_REQUEST,
+                "Batch too large",
+                Some(json!({ "maxBatchSize": config.max_batch_size })),
+            ),
+        )),
+        Value::Array(calls) => {
+            let responses: Vec<Value> =
+                join_all(calls.into_iter().map(|request| call(&config, request)))
+                    .await
+                    .into_iter()
+                    .flatten()
+                    .collect();
+            if responses.is_empty() {
+                None
+            } else {
+                Some(Value::Array(responses))
+            }
+        }
+        request => call(&config, request).await,
+    };
+
+    match response {
+        Some(response) => Json(response).into_response(),
+        // Nothing to return when every call was a notification
+        None => StatusCode::NO_CONTENT.into_response(),
+    }
+}
+
+/// Run a single call, returning `None` for notifications
+async fn call(config: &OptimizerConfig, request: Value) -> Option<Value> {
+    let id = request.get("id").cloned();
+    let valid_id = matches!(
+        id,
+        None | Some(Value::Null) | Some(Value::Number(_)) | Some(Value::String(_))
+    );
+
+    let call = match serde_json::from_value::<Call>(request) {
+        Ok(call) if valid_id && call.jsonrpc == "2.0" => call,
+        _ => {
+            return Some(error_response(
+                Value::Null,
+                rpc_error(INVALID_REQUEST, "Invalid Request", None),
+            ))
+        }
+    };
+
+    let result = match call.method.as_str() {
+        "optimize" => optimize(config, call.params).await,
+        _ => Err(rpc_error(METHOD_NOT_FOUND, "Method not found", None)),
+    };
+
+    let id = id?;
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        Err(error) => error_response(id, error),
+    })
+}
+
+async fn optimize(config: &OptimizerConfig, params: Value) -> Result<Value, Value> {
+    let input: OptimizerInput = from_value(params)
+        .map_err(|e| rpc_error(INVALID_PARAMS, "Invalid params", Some(json!(e))))?;
+
+    let solution = run_optimizer(config, input)
+        .await
+        .map_err(|(_, Json(body))| {
+            let message = body["message"].as_str().unwrap_or_default();
+            rpc_error(SERVER_ERROR, message, body.get("data").cloned())
+        })?;
+
+    Ok(json!(solution))
+}
+
+fn rpc_error(code: i64, message: &str, data: Option<Value>) -> Value {
+    match data {
+        Some(data) => json!({ "code": code, "message": message, "data": data }),
+        None => json!({ "code": code, "message": message }),
+    }
+}
+
+fn error_response(id: Value, error: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "error": error, "id": id })
+}
@@ -0,0 +1,214 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How often waiting runs check whether eco mode has started or ended
+const ECO_RECHECK: Duration = Duration::from_secs(60);
+
+/// Shares a fixed number of CPU slots between optimizer runs in time slices.
+///
+/// The optimizer can't be paused from outside, so runs take turns
+/// cooperatively: after each epoch, a run that has used up its slice gives its
+/// slot to the run that has waited longest and queues up again behind it. Runs
+/// get slots in the order they asked for them, so a small job started behind
+/// long ones waits at most a slice per slot-holder instead of for whole runs.
+///
+/// In eco mode there are fewer slots, and runs over the limit give theirs up
+/// after their next epoch.
+#[derive(Debug)]
+pub(crate) struct Scheduler {
+    slots: usize,
+    slice: Duration,
+    eco: Option<Eco>,
+    queue: Mutex<Queue>,
+    turn: Condvar,
+    yields: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Queue {
+    /// Slots taken by runs
+    held: usize,
+    next_ticket: u64,
+
+    /// Tickets of the runs waiting for a slot, oldest first
+    waiting: VecDeque<u64>,
+}
+
+/// Low-power operation with fewer slots during some hours of the day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Eco {
+    pub(crate) hours: EcoHours,
+
+    /// Slots to use during those hours
+    pub(crate) slots: usize,
+}
+
+/// Hours of the day in UTC, from `start` up to `end`, wrapping past midnight
+/// if `end` is before `start`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct EcoHours {
+    start: u64,
+    end: u64,
+}
+
+/// CPU slot held by a run, which is given back when dropped
+pub(crate) struct Slot<'a> {
+    scheduler: &'a Scheduler,
+    granted: Cell<Instant>,
+}
+
+impl Scheduler {
+    /// Scheduler for `slots` runs at once that each get `slice` at a time, with
+    /// fewer slots during the hours of `eco`
+    pub(crate) fn new(slots: usize, slice: Duration, eco: Option<Eco>) -> Self {
+        Self {
+            slots: slots.max(1),
+            slice,
+            eco,
+            queue: Mutex::new(Queue {
+                held: 0,
+                next_ticket: 0,
+                waiting: VecDeque::new(),
+            }),
+            turn: Condvar::new(),
+            yields: AtomicU64::new(0),
+        }
+    }
+
+    /// Wait for a free slot, behind every run that asked before
+    pub(crate) fn acquire(&self) -> Slot<'_> {
+        let queue = self.lock();
+        self.wait_turn(queue);
+        Slot {
+            scheduler: self,
+            granted: Cell::new(Instant::now()),
+        }
+    }
+
+    /// Number of runs waiting for a slot
+    pub(crate) fn waiting(&self) -> usize {
+        self.lock().waiting.len()
+    }
+
+    /// Times a run gave its slot to a waiting one
+    pub(crate) fn yields(&self) -> u64 {
+        self.yields.load(Ordering::Relaxed)
+    }
+
+    /// Whether eco mode is reducing the slots right now
+    pub(crate) fn is_eco(&self) -> bool {
+        self.eco
+            .is_some_and(|eco| eco.slots < self.slots && eco.hours.contains(utc_hour()))
+    }
+
+    /// Slots runs may hold right now
+    fn slots(&self) -> usize {
+        match self.eco {
+            Some(eco) if self.is_eco() => eco.slots.max(1),
+            _ => self.slots,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().expect("Scheduler lock was poisoned")
+    }
+
+    /// Join the back of the queue and wait until it's our turn and a slot is
+    /// free, then take the slot
+    fn wait_turn(&self, mut queue: MutexGuard<'_, Queue>) {
+        let ticket = queue.next_ticket;
+        queue.next_ticket += 1;
+        queue.waiting.push_back(ticket);
+
+        while queue.held >= self.slots() || queue.waiting.front() != Some(&ticket) {
+            // Wake up now and then in case eco mode ended
+            queue = self
+                .turn
+                .wait_timeout(queue, ECO_RECHECK)
+                .expect("Scheduler lock was poisoned")
+                .0;
+        }
+        queue.waiting.pop_front();
+        queue.held += 1;
+
+        // The next run in line may be able to take another free slot
+        self.turn.notify_all();
+    }
+}
+
+impl Slot<'_> {
+    /// Give the slot to the run that has waited longest if this one has used
+    /// up its slice, or give it up if eco mode leaves fewer slots than are
+    /// held, returning whether it waited for its next turn
+    pub(crate) fn yield_if_due(&self) -> bool {
+        let scheduler = self.scheduler;
+        let due = self.granted.get().elapsed() >= scheduler.slice;
+        if !due && scheduler.eco.is_none() {
+            return false;
+        }
+
+        let mut queue = scheduler.lock();
+        let over = queue.held > scheduler.slots();
+        let yielded = over || (due && !queue.waiting.is_empty());
+        if yielded {
+            queue.held -= 1;
+            scheduler.yields.fetch_add(1, Ordering::Relaxed);
+            scheduler.turn.notify_all();
+            scheduler.wait_turn(queue);
+        }
+        if due || yielded {
+            self.granted.set(Instant::now());
+        }
+        yielded
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.scheduler.lock().held -= 1;
+        self.scheduler.turn.notify_all();
+    }
+}
+
+impl EcoHours {
+    fn contains(&self, hour: u64) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&hour)
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
+}
+
+impl FromStr for EcoHours {
+    type Err = String;
+
+    /// Parse hours such as `22-6`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid hours `{}`, expected a range such as `22-6`", s);
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let hour = |hour: &str| {
+            hour.trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|&hour| hour <= 24)
+                .ok_or_else(invalid)
+        };
+
+        Ok(Self {
+            start: hour(start)? % 24,
+            end: hour(end)?,
+        })
+    }
+}
+
+fn utc_hour() -> u64 {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    seconds / 3600 % 24
+}
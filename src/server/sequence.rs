@@ -0,0 +1,266 @@
+use cut_optimizer_2d::Solution;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::proto::RectFields;
+
+/// Saw cut of a guillotine solution that goes all the way across a panel,
+/// listed in the order it's made
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CutStep {
+    /// Index of the stock piece in `stockPieces`
+    pub(crate) sheet: usize,
+
+    pub(crate) direction: CutDirection,
+
+    /// Where the kerf starts, measured from the corner of the whole stock
+    /// piece: an `x` for rips and a `y` for crosscuts
+    pub(crate) position: usize,
+
+    /// Panel the cut goes all the way across
+    pub(crate) panel: RectFields,
+
+    /// Panels on either side of the kerf, nearest the corner first. A side
+    /// the kerf takes up completely, such as a narrow trim, is left out.
+    pub(crate) results: Vec<Panel>,
+}
+
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum CutDirection {
+    /// Cut along the length of the stock piece, at an `x`
+    Rip,
+    /// Cut across the width of the stock piece, at a `y`
+    Crosscut,
+}
+
+impl CutDirection {
+    fn other(self) -> Self {
+        match self {
+            Self::Rip => Self::Crosscut,
+            Self::Crosscut => Self::Rip,
+        }
+    }
+
+    /// Start and end of a rectangle across the cuts in this direction
+    fn span(self, rect: &RectFields) -> (usize, usize) {
+        match self {
+            Self::Rip => (rect.x, rect.x + rect.width),
+            Self::Crosscut => (rect.y, rect.y + rect.length),
+        }
+    }
+
+    /// Parts of a rectangle before `start` and from `end` on, across the cuts
+    /// in this direction
+    fn split(self, rect: &RectFields, start: usize, end: usize) -> (RectFields, RectFields) {
+        match self {
+            Self::Rip => (
+                RectFields {
+                    width: start - rect.x,
+                    ..*rect
+                },
+                RectFields {
+                    x: end,
+                    width: rect.x + rect.width - end,
+                    ..*rect
+                },
+            ),
+            Self::Crosscut => (
+                RectFields {
+                    length: start - rect.y,
+                    ..*rect
+                },
+                RectFields {
+                    y: end,
+                    length: rect.y + rect.length - end,
+                    ..*rect
+                },
+            ),
+        }
+    }
+}
+
+/// Panel a cut leaves behind
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Panel {
+    #[serde(flatten)]
+    pub(crate) rect: RectFields,
+
+    pub(crate) kind: PanelKind,
+
+    /// Index of the cut piece in the stock piece's `cutPieces`, if the panel
+    /// is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cut_piece: Option<usize>,
+}
+
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum PanelKind {
+    /// Holds more than one cut piece, or one with waste around it, so later
+    /// steps cut it further
+    Panel,
+    /// Exactly one cut piece, with nothing around it
+    CutPiece,
+    /// No cut pieces, so it's left over
+    Waste,
+}
+
+/// Cut pieces within a panel, with their index in the stock piece's
+/// `cutPieces`
+type Pieces = Vec<(usize, RectFields)>;
+
+/// Steps that free every cut piece of a solution, whose trim has been
+/// restored, with through cuts. Each panel has strips cut off it one after
+/// another, starting with rips for whole sheets, then each strip is cut up the
+/// other way.
+///
+/// Returns `None` if a sheet can't be cut up that way, which can happen when
+/// pins or defects placed pieces outside of the guillotine layout.
+pub(crate) fn sequence(solution: &Solution, cut_width: usize) -> Option<Vec<CutStep>> {
+    let mut steps = Vec::new();
+    for (sheet, stock_piece) in solution.stock_pieces.iter().enumerate() {
+        let pieces = stock_piece
+            .cut_pieces
+            .iter()
+            .map(|cp| RectFields {
+                x: cp.x,
+                y: cp.y,
+                width: cp.width,
+                length: cp.length,
+            })
+            .enumerate()
+            .collect();
+        let whole = RectFields {
+            x: 0,
+            y: 0,
+            width: stock_piece.width,
+            length: stock_piece.length,
+        };
+        let mut cutter = Cutter {
+            sheet,
+            cut_width,
+            steps: &mut steps,
+        };
+        cutter.cut(whole, pieces, CutDirection::Rip)?;
+    }
+    Some(steps)
+}
+
+struct Cutter<'a> {
+    sheet: usize,
+    cut_width: usize,
+    steps: &'a mut Vec<CutStep>,
+}
+
+impl Cutter<'_> {
+    fn cut(&mut self, panel: RectFields, pieces: Pieces, preferred: CutDirection) -> Option<()> {
+        if kind(&panel, &pieces) != PanelKind::Panel {
+            return Some(());
+        }
+        let direction = [preferred, preferred.other()]
+            .iter()
+            .copied()
+            .find(|&direction| next_cut(&panel, &pieces, direction, self.cut_width).is_some())?;
+
+        let mut strips = Vec::new();
+        let (mut rest, mut rest_pieces) = (panel, pieces);
+        while let Some((start, end)) = next_cut(&rest, &rest_pieces, direction, self.cut_width) {
+            let (near, far) = direction.split(&rest, start, end);
+            let (near_pieces, far_pieces): (Pieces, Pieces) = rest_pieces
+                .into_iter()
+                .partition(|(_, rect)| direction.span(rect).1 <= start);
+            let results = [(&near, &near_pieces), (&far, &far_pieces)]
+                .iter()
+                .filter(|(rect, _)| rect.width > 0 && rect.length > 0)
+                .map(|(rect, pieces)| result(rect, pieces))
+                .collect();
+            self.steps.push(CutStep {
+                sheet: self.sheet,
+                direction,
+                position: start,
+                panel: rest,
+                results,
+            });
+            strips.push((near, near_pieces));
+            rest = far;
+            rest_pieces = far_pieces;
+        }
+        strips.push((rest, rest_pieces));
+
+        for (strip, pieces) in strips {
+            self.cut(strip, pieces, direction.other())?;
+        }
+        Some(())
+    }
+}
+
+fn kind(panel: &RectFields, pieces: &[(usize, RectFields)]) -> PanelKind {
+    match pieces {
+        [] => PanelKind::Waste,
+        [(_, rect)] if rect == panel => PanelKind::CutPiece,
+        _ => PanelKind::Panel,
+    }
+}
+
+fn result(rect: &RectFields, pieces: &[(usize, RectFields)]) -> Panel {
+    let kind = kind(rect, pieces);
+    Panel {
+        rect: *rect,
+        kind,
+        cut_piece: (kind == PanelKind::CutPiece).then(|| pieces[0].0),
+    }
+}
+
+/// Kerf of the first cut in a direction that goes all the way across a panel
+/// without going through any of its cut pieces, as its start and end. Kerfs go
+/// next to a cut piece and are only narrower than the cut width where they'd
+/// otherwise run into another piece or off the panel.
+fn next_cut(
+    panel: &RectFields,
+    pieces: &[(usize, RectFields)],
+    direction: CutDirection,
+    cut_width: usize,
+) -> Option<(usize, usize)> {
+    let (panel_start, panel_end) = direction.span(panel);
+    let spans: Vec<(usize, usize)> = pieces
+        .iter()
+        .map(|(_, rect)| direction.span(rect))
+        .collect();
+
+    let mut kerfs = Vec::new();
+    for &(start, end) in &spans {
+        if end < panel_end {
+            let kerf_end = spans
+                .iter()
+                .map(|&(start, _)| start)
+                .filter(|&start| start >= end)
+                .fold((end + cut_width).min(panel_end), usize::min);
+            kerfs.push((end, kerf_end));
+        }
+        if start > panel_start {
+            let kerf_start = spans
+                .iter()
+                .map(|&(_, end)| end)
+                .filter(|&end| end <= start)
+                .fold(start.saturating_sub(cut_width).max(panel_start), usize::max);
+            kerfs.push((kerf_start, start));
+        }
+    }
+    kerfs.sort_unstable();
+
+    kerfs.into_iter().find(|&(kerf_start, kerf_end)| {
+        let near = spans.iter().filter(|&&(_, end)| end <= kerf_start).count();
+        let far = spans
+            .iter()
+            .filter(|&&(start, _)| start >= kerf_end)
+            .count();
+        // The cut has to miss every piece and leave the pieces in a smaller
+        // panel than before
+        near + far == spans.len()
+            && (near > 0 || kerf_end > panel_start)
+            && (far > 0 || kerf_start < panel_end)
+    })
+}
@@ -0,0 +1,155 @@
+use http::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::format::BodyError;
+use super::health::{Component, Health};
+use super::{error, error_with_data, OptimizeError};
+
+/// Named JSON documents, such as stock catalogs or presets, stored as one file
+/// each in a directory
+#[derive(Debug)]
+pub(crate) struct Store {
+    dir: PathBuf,
+
+    /// What the documents are, for error messages
+    kind: &'static str,
+
+    /// Where failures to reach the directory are recorded
+    component: Component,
+    health: Arc<Health>,
+}
+
+impl Store {
+    pub(crate) fn new(
+        dir: PathBuf,
+        kind: &'static str,
+        component: Component,
+        health: Arc<Health>,
+    ) -> Self {
+        Self {
+            dir,
+            kind,
+            component,
+            health,
+        }
+    }
+
+    pub(crate) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of a document's file, if its name is valid
+    fn path(&self, name: &str) -> Result<PathBuf, OptimizeError> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+            && !name.starts_with('.');
+        if !valid {
+            return Err(error(
+                StatusCode::BAD_REQUEST,
+                "Names may only contain letters, digits, `-`, `_`, and `.`",
+            ));
+        }
+        Ok(self.dir.join(format!("{}.json", name)))
+    }
+
+    /// Document with a name, or `None` if there's no such document
+    pub(crate) async fn get<T: DeserializeOwned>(
+        &self,
+        name: &str,
+    ) -> Result<Option<T>, OptimizeError> {
+        let bytes = match tokio::fs::read(self.path(name)?).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.health.succeed(self.component);
+                return Ok(None);
+            }
+            Err(e) => return Err(self.unreachable(e)),
+        };
+        self.health.succeed(self.component);
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| self.storage_error(e))
+    }
+
+    /// Create or replace a document
+    pub(crate) async fn put<T: Serialize + ?Sized>(
+        &self,
+        name: &str,
+        document: &T,
+    ) -> Result<(), OptimizeError> {
+        let path = self.path(name)?;
+        let json = serde_json::to_vec_pretty(document).map_err(|e| self.storage_error(e))?;
+
+        // Write a temporary file first so readers never see half a document.
+        // Each write has its own, so concurrent writes of a document can't
+        // mix theirs, and the last one renamed wins.
+        static WRITES: AtomicU64 = AtomicU64::new(0);
+        let temporary = path.with_extension(format!(
+            "json.{}-{}.tmp",
+            std::process::id(),
+            WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| self.unreachable(e))?;
+        tokio::fs::write(&temporary, json)
+            .await
+            .map_err(|e| self.unreachable(e))?;
+        tokio::fs::rename(&temporary, &path)
+            .await
+            .map_err(|e| self.unreachable(e))?;
+        self.health.succeed(self.component);
+        Ok(())
+    }
+
+    /// Delete a document, returning whether there was one
+    pub(crate) async fn delete(&self, name: &str) -> Result<bool, OptimizeError> {
+        let deleted = match tokio::fs::remove_file(self.path(name)?).await {
+            Ok(()) => true,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+            Err(e) => return Err(self.unreachable(e)),
+        };
+        self.health.succeed(self.component);
+        Ok(deleted)
+    }
+
+    pub(crate) fn not_found(&self) -> OptimizeError {
+        error(
+            StatusCode::NOT_FOUND,
+            &format!("No {} with that name", self.kind),
+        )
+    }
+
+    /// Error for failing to reach the directory, which degrades the store
+    fn unreachable(&self, e: io::Error) -> OptimizeError {
+        self.health.fail(self.component, &e);
+        self.storage_error(e)
+    }
+
+    fn storage_error(&self, e: impl ToString) -> OptimizeError {
+        error_with_data(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Couldn't access the {}", self.kind),
+            e.to_string(),
+        )
+    }
+}
+
+/// Decode a document from a JSON request body
+pub(crate) fn decode<T: DeserializeOwned>(body: &[u8]) -> Result<T, OptimizeError> {
+    let mut deserializer = serde_json::Deserializer::from_slice(body);
+    serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+        error_with_data(
+            StatusCode::BAD_REQUEST,
+            "Invalid request body",
+            BodyError::from(e),
+        )
+    })
+}
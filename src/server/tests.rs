@@ -164,3 +164,2909 @@ async fn non_fitting_price_should_return_unprocessable_entity() {
 
     assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
 }
+
+async fn post_rpc(body: String) -> (StatusCode, Option<Value>) {
+    let resp = test_app()
+        .oneshot(
+            Request::builder()
+                .header("Content-Type", "application/json")
+                .method("POST")
+                .uri("/rpc")
+                .body(body.into())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let status = resp.status();
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    (status, serde_json::from_slice(&body).ok())
+}
+
+#[tokio::test]
+async fn rpc_optimize_should_return_result() {
+    let (status, body) = post_rpc(format!(
+        r#"{{ "jsonrpc": "2.0", "method": "optimize", "params": {}, "id": 1 }}"#,
+        TEST_INPUT
+    ))
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    let body = body.unwrap();
+    assert_eq!(body["id"], 1);
+    assert!(body["result"]["stockPieces"].is_array());
+}
+
+#[tokio::test]
+async fn rpc_batch_should_return_response_per_call() {
+    let (status, body) = post_rpc(format!(
+        r#"[
+            {{ "jsonrpc": "2.0", "method": "optimize", "params": {}, "id": 1 }},
+            {{ "jsonrpc": "2.0", "method": "optimize", "params": {{}}, "id": 2 }},
+            {{ "jsonrpc": "2.0", "method": "jobStatus", "params": {{}}, "id": 3 }},
+            {{ "jsonrpc": "2.0", "method": "optimize", "params": {} }}
+        ]"#,
+        TEST_INPUT, TEST_INPUT
+    ))
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    let body = body.unwrap();
+    let responses = body.as_array().unwrap();
+    assert_eq!(responses.len(), 3);
+    assert!(responses[0]["result"].is_object());
+    assert_eq!(responses[1]["error"]["code"], -32602);
+    assert_eq!(responses[2]["error"]["code"], -32601);
+}
+
+#[tokio::test]
+async fn rpc_batch_larger_than_max_batch_size_should_be_rejected() {
+    let call = format!(
+        r#"{{ "jsonrpc": "2.0", "method": "optimize", "params": {}, "id": 1 }}"#,
+        TEST_INPUT
+    );
+    let (status, body) = post_rpc(format!("[{}]", vec![call; 11].join(","))).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let body = body.unwrap();
+    assert_eq!(body["error"]["code"], -32600);
+    assert_eq!(body["error"]["data"]["maxBatchSize"], 10);
+    assert_eq!(body["id"], Value::Null);
+}
+
+#[tokio::test]
+async fn rpc_notification_should_return_no_content() {
+    let (status, _) = post_rpc(format!(
+        r#"{{ "jsonrpc": "2.0", "method": "optimize", "params": {} }}"#,
+        TEST_INPUT
+    ))
+    .await;
+
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn rpc_invalid_json_should_return_parse_error() {
+    let (status, body) = post_rpc("{".to_string()).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let body = body.unwrap();
+    assert_eq!(body["error"]["code"], -32700);
+    assert_eq!(body["id"], Value::Null);
+}
+
+#[tokio::test]
+async fn optimize_should_accept_and_return_cbor() {
+    let input: Value = serde_json::from_str(TEST_INPUT).unwrap();
+    let mut body = Vec::new();
+    ciborium::ser::into_writer(&input, &mut body).unwrap();
+
+    let resp = test_app()
+        .oneshot(
+            Request::builder()
+                .header("Content-Type", "application/cbor")
+                .header("Accept", "application/cbor")
+                .method("POST")
+                .uri("/optimize")
+                .body(body.into())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()["Content-Type"], "application/cbor");
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    let solution: Value = ciborium::de::from_reader(&body[..]).unwrap();
+    assert!(solution["stockPieces"].is_array());
+}
+
+async fn response_content_type(accept: &str) -> http::HeaderValue {
+    let resp = test_app()
+        .oneshot(
+            Request::builder()
+                .header("Content-Type", "application/json")
+                .header("Accept", accept)
+                .method("POST")
+                .uri("/optimize")
+                .body(TEST_INPUT.into())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    resp.headers()["Content-Type"].clone()
+}
+
+#[tokio::test]
+async fn response_format_should_follow_accept_quality() {
+    assert_eq!(
+        response_content_type("application/cbor;q=0, application/json").await,
+        "application/json"
+    );
+    assert_eq!(
+        response_content_type("application/json;q=0.1, application/cbor").await,
+        "application/cbor"
+    );
+    assert_eq!(
+        response_content_type("application/cbor, application/x-protobuf").await,
+        "application/cbor"
+    );
+}
+
+#[tokio::test]
+async fn unsupported_content_type_should_return_unsupported_media_type() {
+    let resp = test_app()
+        .oneshot(
+            Request::builder()
+                .header("Content-Type", "text/plain")
+                .method("POST")
+                .uri("/optimize")
+                .body(TEST_INPUT.into())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn optimize_should_accept_and_return_protobuf() {
+    use crate::proto;
+    use prost::Message;
+
+    let stock_piece = proto::StockPiece {
+        width: 48,
+        length: 96,
+        pattern_direction: proto::PatternDirection::None as i32,
+        price: 0,
+        quantity: None,
+        ..Default::default()
+    };
+    let cut_piece = proto::CutPiece {
+        external_id: Some(1),
+        width: 10,
+        length: 30,
+        pattern_direction: proto::PatternDirection::None as i32,
+        can_rotate: true,
+        ..Default::default()
+    };
+    let request = proto::OptimizeRequest {
+        method: Some(proto::OptimizeMethod::Guillotine as i32),
+        random_seed: Some(1),
+        cut_width: Some(2),
+        stock_pieces: vec![stock_piece],
+        cut_pieces: vec![cut_piece],
+        ..Default::default()
+    };
+
+    let resp = test_app()
+        .oneshot(
+            Request::builder()
+                .header("Content-Type", "application/x-protobuf")
+                .header("Accept", "application/x-protobuf")
+                .method("POST")
+                .uri("/optimize")
+                .body(request.encode_to_vec().into())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()["Content-Type"], "application/x-protobuf");
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    let solution = proto::Solution::decode(&body[..]).unwrap();
+    assert_eq!(solution.stock_pieces.len(), 1);
+    assert_eq!(solution.stock_pieces[0].cut_pieces[0].external_id, Some(1));
+}
+
+#[cfg(target_pointer_width = "32")]
+#[tokio::test]
+async fn protobuf_values_past_usize_should_be_rejected() {
+    use crate::proto;
+    use prost::Message;
+
+    let stock_piece = proto::StockPiece {
+        width: 48,
+        length: 96,
+        defects: vec![proto::Rect {
+            width: u64::MAX,
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let request = proto::OptimizeRequest {
+        stock_pieces: vec![stock_piece],
+        ..Default::default()
+    };
+
+    let resp = test_app()
+        .oneshot(
+            Request::builder()
+                .header("Content-Type", "application/x-protobuf")
+                .method("POST")
+                .uri("/optimize")
+                .body(request.encode_to_vec().into())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    let error: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["data"]["path"], "stockPieces[0].defects[0].width");
+}
+
+async fn optimize_content_encoding(args: &[&str]) -> Option<String> {
+    let resp = app(&Opt::from_iter(args))
+        .oneshot(
+            Request::builder()
+                .header("Content-Type", "application/json")
+                .header("Accept-Encoding", "gzip, br")
+                .method("POST")
+                .uri("/optimize")
+                .body(TEST_INPUT.into())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    resp.headers()
+        .get("Content-Encoding")
+        .map(|encoding| encoding.to_str().unwrap().to_string())
+}
+
+#[tokio::test]
+async fn compression_should_use_configured_algorithms() {
+    let encoding = optimize_content_encoding(&[
+        "cut-optimizer-2d-server",
+        "--compression-algorithms",
+        "gzip",
+    ])
+    .await;
+
+    assert_eq!(encoding.as_deref(), Some("gzip"));
+}
+
+#[tokio::test]
+async fn compression_should_skip_small_responses() {
+    let encoding =
+        optimize_content_encoding(&["cut-optimizer-2d-server", "--compression-min-size", "65535"])
+            .await;
+
+    assert_eq!(encoding, None);
+}
+
+#[tokio::test]
+async fn compression_can_be_disabled() {
+    let encoding =
+        optimize_content_encoding(&["cut-optimizer-2d-server", "--no-compression"]).await;
+
+    assert_eq!(encoding, None);
+}
+
+async fn cors_preflight(args: &[&str], origin: &str) -> http::Response<axum::body::BoxBody> {
+    app(&Opt::from_iter(args))
+        .oneshot(
+            Request::builder()
+                .header("Origin", origin)
+                .header("Access-Control-Request-Method", "POST")
+                .header("Access-Control-Request-Headers", "content-type")
+                .method("OPTIONS")
+                .uri("/optimize")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn cors_preflight_should_allow_configured_origin() {
+    let resp = cors_preflight(
+        &[
+            "cut-optimizer-2d-server",
+            "--cors-origin",
+            "https://a.example",
+            "--cors-origin",
+            "https://b.example",
+        ],
+        "https://b.example",
+    )
+    .await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers()["Access-Control-Allow-Origin"],
+        "https://b.example"
+    );
+}
+
+#[tokio::test]
+async fn cors_preflight_should_not_allow_other_origins() {
+    let resp = cors_preflight(
+        &[
+            "cut-optimizer-2d-server",
+            "--cors-origin",
+            "https://a.example",
+        ],
+        "https://evil.example",
+    )
+    .await;
+
+    assert!(resp.headers().get("Access-Control-Allow-Origin").is_none());
+}
+
+#[tokio::test]
+async fn cors_should_be_disabled_by_default() {
+    let resp = cors_preflight(&["cut-optimizer-2d-server"], "https://a.example").await;
+
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert!(resp.headers().get("Access-Control-Allow-Origin").is_none());
+}
+
+async fn optimize_with_max_requests(max_requests: &str) -> http::Response<axum::body::BoxBody> {
+    app(&Opt::from_iter(&[
+        "cut-optimizer-2d-server",
+        "--max-requests",
+        max_requests,
+    ]))
+    .oneshot(
+        Request::builder()
+            .header("Content-Type", "application/json")
+            .method("POST")
+            .uri("/optimize")
+            .body(TEST_INPUT.into())
+            .unwrap(),
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn responses_should_tell_clients_how_busy_the_server_is() {
+    let resp = optimize_with_max_requests("100").await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()["X-Queue-Depth"], "0");
+    assert_eq!(resp.headers()["X-Concurrency-Remaining"], "100");
+    assert_eq!(resp.headers()["X-Suggested-Delay-Ms"], "0");
+
+    // Every request is shed when none may run at once
+    let resp = optimize_with_max_requests("0").await;
+
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers()["X-Concurrency-Remaining"], "0");
+    let delay: u64 = resp.headers()["X-Suggested-Delay-Ms"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(delay > 0);
+}
+
+fn collect_refs<'a>(value: &'a Value, refs: &mut Vec<&'a str>) {
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::String(reference)) = map.get("$ref") {
+                refs.push(reference);
+            }
+            map.values().for_each(|v| collect_refs(v, refs));
+        }
+        Value::Array(values) => values.iter().for_each(|v| collect_refs(v, refs)),
+        _ => {}
+    }
+}
+
+#[tokio::test]
+async fn openapi_should_describe_optimize_with_resolvable_refs() {
+    let resp = test_app()
+        .oneshot(
+            Request::builder()
+                .method("GET")
+                .uri("/openapi.json")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    let document: Value = serde_json::from_slice(&body).unwrap();
+    assert!(document["paths"]["/v1/optimize"]["post"].is_object());
+
+    let mut refs = Vec::new();
+    collect_refs(&document, &mut refs);
+    assert!(!refs.is_empty());
+    for reference in refs {
+        let name = reference.trim_start_matches("#/components/schemas/");
+        assert!(
+            document["components"]["schemas"][name].is_object(),
+            "unresolved {}",
+            reference
+        );
+    }
+}
+
+#[tokio::test]
+async fn schema_should_describe_error_bodies_with_resolvable_refs() {
+    let resp = test_app()
+        .oneshot(
+            Request::builder()
+                .method("GET")
+                .uri("/schema")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    let document: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(document["input"]["$ref"], "#/$defs/OptimizerInput");
+    assert_eq!(
+        document["errors"]["422"]["oneOf"].as_array().unwrap().len(),
+        2
+    );
+
+    let mut refs = Vec::new();
+    collect_refs(&document, &mut refs);
+    for reference in refs {
+        let name = reference.trim_start_matches("#/$defs/");
+        assert!(
+            document["$defs"][name].is_object(),
+            "unresolved {}",
+            reference
+        );
+    }
+}
+
+/// Check a body against a schema of the OpenAPI document, recording where it
+/// doesn't match. Properties that a schema with `properties` doesn't declare
+/// are mismatches too, so fields added without documenting them are caught.
+fn validate(schemas: &Value, schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.trim_start_matches("#/components/schemas/");
+        assert!(schemas[name].is_object(), "unresolved {}", reference);
+        return validate(schemas, &schemas[name], value, path, errors);
+    }
+    for option in schema["allOf"].as_array().into_iter().flatten() {
+        validate(schemas, option, value, path, errors);
+    }
+    for (keyword, exactly_one) in &[("oneOf", true), ("anyOf", false)] {
+        if let Some(options) = schema[keyword].as_array() {
+            let results: Vec<Vec<String>> = options
+                .iter()
+                .map(|option| {
+                    let mut option_errors = Vec::new();
+                    validate(schemas, option, value, path, &mut option_errors);
+                    option_errors
+                })
+                .collect();
+            match results.iter().filter(|result| result.is_empty()).count() {
+                0 => errors.extend(results.into_iter().min_by_key(Vec::len).unwrap()),
+                1 => {}
+                _ if *exactly_one => {
+                    errors.push(format!("{}: matches more than one of `oneOf`", path))
+                }
+                _ => {}
+            }
+            return;
+        }
+    }
+    if value.is_null() && schema["nullable"] == true {
+        return;
+    }
+    if let Some(options) = schema["enum"].as_array() {
+        if !options.contains(value) {
+            errors.push(format!("{}: {} isn't one of {:?}", path, value, options));
+        }
+    }
+    let type_matches = match schema["type"].as_str() {
+        Some("object") => value.is_object(),
+        Some("array") => value.is_array(),
+        Some("string") => value.is_string(),
+        Some("integer") => value.is_u64() || value.is_i64(),
+        Some("number") => value.is_number(),
+        Some("boolean") => value.is_boolean(),
+        _ => true,
+    };
+    if !type_matches {
+        errors.push(format!(
+            "{}: {} isn't of type {}",
+            path, value, schema["type"]
+        ));
+        return;
+    }
+    if let (Some(minimum), Some(n)) = (schema["minimum"].as_f64(), value.as_f64()) {
+        if n < minimum {
+            errors.push(format!("{}: {} is below {}", path, n, minimum));
+        }
+    }
+    if let (Some(maximum), Some(n)) = (schema["maximum"].as_f64(), value.as_f64()) {
+        if n > maximum {
+            errors.push(format!("{}: {} is above {}", path, n, maximum));
+        }
+    }
+
+    match value {
+        Value::Object(map) => {
+            for required in schema["required"].as_array().into_iter().flatten() {
+                if !map.contains_key(required.as_str().unwrap()) {
+                    errors.push(format!("{}: missing {}", path, required));
+                }
+            }
+            for (name, property) in map {
+                let property_path = format!("{}.{}", path, name);
+                match (
+                    schema["properties"].get(name),
+                    schema.get("additionalProperties"),
+                ) {
+                    (Some(property_schema), _) => {
+                        validate(schemas, property_schema, property, &property_path, errors)
+                    }
+                    (None, Some(additional)) if additional.is_object() => {
+                        validate(schemas, additional, property, &property_path, errors)
+                    }
+                    (None, None) if schema.get("properties").is_some() => {
+                        errors.push(format!("{}: not in the schema", property_path))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                let item_path = format!("{}[{}]", path, i);
+                validate(schemas, &schema["items"], item, &item_path, errors);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn schema_errors(name: &str, value: &Value) -> Vec<String> {
+    let document = openapi::openapi(None);
+    let schemas = &document["components"]["schemas"];
+    let mut errors = Vec::new();
+    validate(schemas, &schemas[name], value, name, &mut errors);
+    errors
+}
+
+#[tokio::test]
+async fn optimize_bodies_should_match_the_openapi_document() {
+    let everything = r#"
+        {
+            "method": "guillotine",
+            "randomSeed": 3,
+            "seedCount": 2,
+            "solutionCount": 2,
+            "cutWidth": 2,
+            "feedRate": 50,
+            "currency": "USD",
+            "pricePerArea": 0.01,
+            "minOffcutWidth": 5,
+            "minOffcutLength": 5,
+            "includeScores": true,
+            "includeComputeStats": true,
+            "allowPartial": true,
+            "objective": "minWaste",
+            "qualityWeights": { "utilization": 1 },
+            "variables": { "W": 20 },
+            "stockPieces": [
+                {
+                    "width": 48,
+                    "length": 96,
+                    "patternDirection": "none",
+                    "price": 30,
+                    "quantity": 2,
+                    "trimLeft": 1,
+                    "metadata": { "orderLine": 7 }
+                }
+            ],
+            "cutPieces": [
+                {
+                    "externalId": 1,
+                    "width": "W - 10",
+                    "length": 30,
+                    "patternDirection": "none",
+                    "canRotate": true,
+                    "quantity": 3,
+                    "edgeAllowance": { "left": 1, "right": 1 },
+                    "metadata": { "part": "shelf" }
+                },
+                { "width": 5, "length": 5, "patternDirection": "none", "canRotate": false, "optional": true },
+                { "width": 200, "length": 200, "patternDirection": "none", "canRotate": true }
+            ]
+        }
+    "#;
+    let materials = TEST_INPUT
+        .replace(r#""price": 0"#, r#""price": 0, "material": "oak""#)
+        .replace(
+            r#""canRotate": true"#,
+            r#""canRotate": true, "material": "oak""#,
+        );
+    let nested = TEST_INPUT
+        .replace(r#""method": "guillotine""#, r#""method": "nested""#)
+        .replace(r#""cutWidth": 2"#, r#""cutWidth": 0"#);
+
+    for input in &[TEST_INPUT, everything, &materials, &nested] {
+        let (status, solution) = optimize_json(input).await;
+        assert_eq!(status, StatusCode::OK, "{}", solution);
+
+        let input: Value = serde_json::from_str(input).unwrap();
+        let mut errors = schema_errors("OptimizerInput", &input);
+        errors.extend(schema_errors("Optimized", &solution));
+        assert!(errors.is_empty(), "{:#?}", errors);
+        if input["method"] == "nested" {
+            assert!(solution["warnings"].is_array());
+        }
+
+        // Fields missing from the document are caught
+        let mut undocumented = solution.clone();
+        match undocumented.get_mut("materials") {
+            Some(materials) => materials["oak"]["undocumented"] = json!(1),
+            None => undocumented["undocumented"] = json!(1),
+        }
+        assert_eq!(schema_errors("Optimized", &undocumented).len(), 1);
+    }
+}
+
+#[tokio::test]
+async fn error_bodies_should_match_the_openapi_document() {
+    let no_fit = r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 2,
+            "stockPieces": [
+                { "width": 48, "length": 96, "patternDirection": "none", "price": 0 }
+            ],
+            "cutPieces": [
+                { "width": 49, "length": 30, "patternDirection": "none", "canRotate": false }
+            ]
+        }
+    "#;
+    let (status, error) = optimize_json(no_fit).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(error["data"][0]["suggestion"].is_object());
+    let mut errors = schema_errors("ValidationErrors", &error);
+
+    let (status, error) =
+        optimize_json(&TEST_INPUT.replace(r#""width": 48"#, r#""width": -48"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    errors.extend(schema_errors("InvalidBodyError", &error));
+    assert!(errors.is_empty(), "{:#?}", errors);
+
+    let mut undocumented = error.clone();
+    undocumented["data"]["undocumented"] = json!(1);
+    assert_eq!(schema_errors("InvalidBodyError", &undocumented).len(), 1);
+}
+
+/// String literals that follow each occurrence of `prefix` in some source
+fn literals_after<'a>(source: &'a str, prefix: &str) -> Vec<&'a str> {
+    source
+        .split(prefix)
+        .skip(1)
+        .filter_map(|rest| {
+            let rest = rest.trim_start().strip_prefix('"')?;
+            rest.split('"').next()
+        })
+        .collect()
+}
+
+#[test]
+fn error_schemas_should_list_every_code_in_the_source() {
+    let sources = [
+        include_str!("../server.rs"),
+        include_str!("catalog.rs"),
+        include_str!("material.rs"),
+        include_str!("preset.rs"),
+        include_str!("validation.rs"),
+    ];
+    let document = openapi::openapi(None);
+    let schemas = &document["components"]["schemas"];
+
+    let codes: Vec<&str> = sources
+        .iter()
+        .flat_map(|source| literals_after(source, "ValidationError::new("))
+        .collect();
+    assert!(codes.contains(&"noFit"));
+    for code in codes {
+        assert!(
+            schemas["ValidationError"]["properties"]["code"]["enum"]
+                .as_array()
+                .unwrap()
+                .contains(&json!(code)),
+            "`{}` is missing from the ValidationError schema",
+            code
+        );
+    }
+
+    let codes: Vec<&str> = [include_str!("lint.rs"), include_str!("health.rs")]
+        .iter()
+        .flat_map(|source| literals_after(source, "code: "))
+        .collect();
+    assert!(codes.contains(&"degradedComponent"));
+    for code in codes {
+        assert!(
+            schemas["Warning"]["properties"]["code"]["enum"]
+                .as_array()
+                .unwrap()
+                .contains(&json!(code)),
+            "`{}` is missing from the Warning schema",
+            code
+        );
+    }
+}
+
+async fn get_docs(args: &[&str], path: &str) -> StatusCode {
+    app(&Opt::from_iter(args))
+        .oneshot(
+            Request::builder()
+                .method("GET")
+                .uri(path)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn docs_should_be_served_when_enabled() {
+    let args = ["cut-optimizer-2d-server", "--enable-docs"];
+    assert_eq!(get_docs(&args, "/docs").await, StatusCode::OK);
+    assert!(!include_str!("docs.html").contains("https://"));
+    assert_eq!(
+        get_docs(&args, "/docs/rapidoc-min.js").await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn docs_should_not_be_served_by_default() {
+    assert_eq!(
+        get_docs(&["cut-optimizer-2d-server"], "/docs").await,
+        StatusCode::NOT_FOUND
+    );
+}
+
+async fn get_ui(args: &[&str]) -> StatusCode {
+    app(&Opt::from_iter(args))
+        .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn ui_should_be_served_when_enabled() {
+    assert_eq!(
+        get_ui(&["cut-optimizer-2d-server", "--enable-ui"]).await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn ui_should_not_be_served_by_default() {
+    assert_eq!(
+        get_ui(&["cut-optimizer-2d-server"]).await,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn versioned_optimize_should_return_ok() {
+    let resp = test_app()
+        .oneshot(
+            Request::builder()
+                .header("Content-Type", "application/json")
+                .method("POST")
+                .uri("/v1/optimize")
+                .body(TEST_INPUT.into())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+async fn invalid_input_error(input: &str) -> Value {
+    let resp = test_app()
+        .oneshot(
+            Request::builder()
+                .header("Content-Type", "application/json")
+                .method("POST")
+                .uri("/optimize")
+                .body(input.to_string().into())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn invalid_input_should_report_missing_field() {
+    let error = invalid_input_error(&TEST_INPUT.replace(r#""cutPieces""#, r#""pieces""#)).await;
+
+    assert!(error["data"]["path"].is_null());
+    assert!(error["data"]["reason"]
+        .as_str()
+        .unwrap()
+        .contains("missing field `cutPieces`"));
+}
+
+#[tokio::test]
+async fn invalid_input_should_report_field_path() {
+    let error =
+        invalid_input_error(&TEST_INPUT.replacen(r#""width": 48"#, r#""width": -48"#, 1)).await;
+
+    assert_eq!(error["data"]["path"], "stockPieces[0].width");
+    assert!(error["data"]["reason"].as_str().unwrap().contains("-48"));
+}
+
+#[tokio::test]
+async fn invalid_input_should_report_unknown_method() {
+    let error = invalid_input_error(&TEST_INPUT.replace("guillotine", "diagonal")).await;
+
+    assert_eq!(error["data"]["path"], "method");
+    assert!(error["data"]["reason"]
+        .as_str()
+        .unwrap()
+        .contains("unknown variant `diagonal`"));
+}
+
+async fn validation_error_codes(input: &str) -> Vec<(String, String)> {
+    let resp = test_app()
+        .oneshot(
+            Request::builder()
+                .header("Content-Type", "application/json")
+                .method("POST")
+                .uri("/optimize")
+                .body(input.to_string().into())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    let error: Value = serde_json::from_slice(&body).unwrap();
+    error["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| {
+            (
+                e["code"].as_str().unwrap().to_string(),
+                e["path"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn validation_should_reject_empty_piece_lists() {
+    let codes = validation_error_codes(
+        r#"{ "method": "nested", "cutWidth": 2, "stockPieces": [], "cutPieces": [] }"#,
+    )
+    .await;
+
+    assert_eq!(
+        codes,
+        vec![
+            ("emptyStockPieces".to_string(), "stockPieces".to_string()),
+            ("emptyCutPieces".to_string(), "cutPieces".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn validation_should_report_every_problem() {
+    let input = TEST_INPUT
+        .replace(r#""cutWidth": 2"#, r#""cutWidth": 200"#)
+        .replace(r#""width": 10"#, r#""width": 0"#)
+        .replace(r#""externalId": 2"#, r#""externalId": 1"#);
+
+    let codes = validation_error_codes(&input).await;
+
+    assert_eq!(
+        codes,
+        vec![
+            (
+                "zeroDimension".to_string(),
+                "cutPieces[0].width".to_string()
+            ),
+            ("cutWidthTooLarge".to_string(), "cutWidth".to_string()),
+            (
+                "duplicateExternalId".to_string(),
+                "cutPieces[1].externalId".to_string()
+            ),
+        ]
+    );
+}
+
+async fn optimize_json(input: &str) -> (StatusCode, Value) {
+    optimize_json_with(test_app(), input).await
+}
+
+async fn optimize_json_with(app: Router<Body>, input: &str) -> (StatusCode, Value) {
+    let resp = app
+        .oneshot(
+            Request::builder()
+                .header("Content-Type", "application/json")
+                .method("POST")
+                .uri("/optimize")
+                .body(input.to_string().into())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let status = resp.status();
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn solution_should_report_method() {
+    let (status, solution) = optimize_json(TEST_INPUT).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(solution["method"], "guillotine");
+    assert_eq!(solution["partial"], false);
+}
+
+#[tokio::test]
+async fn best_method_should_report_winning_method() {
+    for method in ["best", "auto"] {
+        let input = TEST_INPUT.replace(
+            r#""method": "guillotine""#,
+            &format!(r#""method": "{}""#, method),
+        );
+        let (status, solution) = optimize_json(&input).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(
+            solution["method"] == "guillotine" || solution["method"] == "nested",
+            "unexpected method {}",
+            solution["method"]
+        );
+        assert_eq!(solution["stockPieces"].as_array().unwrap().len(), 1);
+    }
+}
+
+#[tokio::test]
+async fn seed_count_should_return_best_seed_and_scores() {
+    let input = TEST_INPUT.replace(
+        r#""randomSeed": 1,"#,
+        r#""randomSeed": 10, "seedCount": 4, "includeScores": true,"#,
+    );
+    let (status, solution) = optimize_json(&input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let seed = solution["randomSeed"].as_u64().unwrap();
+    assert!((10..14).contains(&seed), "unexpected seed {}", seed);
+
+    let seeds: Vec<_> = solution["scores"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|score| score["seed"].as_u64().unwrap())
+        .collect();
+    assert_eq!(seeds, vec![10, 11, 12, 13]);
+}
+
+#[tokio::test]
+async fn solution_should_report_stats_per_sheet_and_overall() {
+    let input = TEST_INPUT.replace(r#""price": 0"#, r#""price": 25"#);
+    let (status, solution) = optimize_json(&input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let stats = &solution["stats"];
+    let sheets = stats["sheets"].as_array().unwrap();
+    assert_eq!(stats["sheetCount"], sheets.len());
+    assert_eq!(
+        stats["usedArea"],
+        10 * 30 + 45 * 100,
+        "every cut piece is used exactly once"
+    );
+    assert_eq!(stats["price"], 25 * sheets.len());
+
+    let stock_area: u64 = solution["stockPieces"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|sp| sp["width"].as_u64().unwrap() * sp["length"].as_u64().unwrap())
+        .sum();
+    assert_eq!(
+        stats["usedArea"].as_u64().unwrap() + stats["wasteArea"].as_u64().unwrap(),
+        stock_area
+    );
+    let utilization = stats["utilizationPercent"].as_f64().unwrap();
+    assert!((utilization - 100.0 * 4800.0 / stock_area as f64).abs() < 1e-9);
+    assert!(stats["cutLength"].as_u64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn cost_should_add_area_cost_to_sheet_prices() {
+    let input = TEST_INPUT
+        .replace(r#""price": 0"#, r#""price": 25"#)
+        .replace(
+            r#""cutWidth": 2,"#,
+            r#""cutWidth": 2, "currency": "USD", "pricePerArea": 0.01,"#,
+        );
+    let (status, solution) = optimize_json(&input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let cost = &solution["cost"];
+    assert_eq!(cost["currency"], "USD");
+    let mut material_cost = 0.0;
+    for (sheet, stock_piece) in cost["sheets"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .zip(solution["stockPieces"].as_array().unwrap())
+    {
+        let area = stock_piece["width"].as_f64().unwrap() * stock_piece["length"].as_f64().unwrap();
+        assert_eq!(sheet["price"], 25);
+        assert!((sheet["areaCost"].as_f64().unwrap() - area * 0.01).abs() < 1e-9);
+        material_cost += sheet["total"].as_f64().unwrap();
+    }
+    assert!((cost["materialCost"].as_f64().unwrap() - material_cost).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn invalid_currency_should_be_rejected() {
+    let input = TEST_INPUT.replace(r#""cutWidth": 2,"#, r#""cutWidth": 2, "currency": "usd","#);
+    let (status, body) = optimize_json(&input).await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["data"][0]["code"], "invalidCurrency");
+}
+
+#[tokio::test]
+async fn offcuts_should_only_include_big_enough_non_overlapping_waste() {
+    let (_, solution) = optimize_json(TEST_INPUT).await;
+    assert!(solution.get("offcuts").is_none());
+
+    for method in ["guillotine", "nested"] {
+        let input = TEST_INPUT.replace(
+            r#""method": "guillotine","#,
+            &format!(
+                r#""method": "{}", "minOffcutWidth": 10, "minOffcutLength": 6,"#,
+                method
+            ),
+        );
+        let (status, solution) = optimize_json(&input).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let offcuts = solution["offcuts"].as_array().unwrap();
+        assert!(!offcuts.is_empty());
+        let rect = |offcut: &Value| {
+            ["sheet", "x", "y", "width", "length"].map(|field| offcut[field].as_u64().unwrap())
+        };
+        for (i, a) in offcuts.iter().enumerate() {
+            let [sheet, x, y, width, length] = rect(a);
+            assert!(width.min(length) >= 6 && width.max(length) >= 10);
+            for b in &offcuts[i + 1..] {
+                let [other_sheet, ox, oy, ow, ol] = rect(b);
+                let overlap = sheet == other_sheet
+                    && x < ox + ow
+                    && ox < x + width
+                    && y < oy + ol
+                    && oy < y + length;
+                assert!(!overlap, "{} overlaps {}", a, b);
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn prefer_remnants_should_use_remnants_before_new_sheets() {
+    let input = r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 2,
+            "preferRemnants": true,
+            "stockPieces": [
+                { "width": 48, "length": 96, "patternDirection": "none", "price": 10 },
+                {
+                    "width": 48,
+                    "length": 50,
+                    "patternDirection": "none",
+                    "price": 20,
+                    "quantity": 1,
+                    "isRemnant": true
+                }
+            ],
+            "cutPieces": [
+                { "width": 10, "length": 30, "patternDirection": "none", "canRotate": true },
+                { "width": 20, "length": 40, "patternDirection": "none", "canRotate": true }
+            ]
+        }
+    "#;
+    let (status, solution) = optimize_json(input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let stats = &solution["stats"];
+    assert_eq!(stats["sheetCount"], 1);
+    assert_eq!(stats["remnantCount"], 1);
+    assert_eq!(stats["price"], 20, "reported prices aren't changed");
+    assert_eq!(stats["sheets"][0]["isRemnant"], true);
+    assert!(stats.get("isRemnant").is_none());
+    assert_eq!(solution["stockPieces"][0]["length"], 50);
+
+    // The premium on new sheets saturates instead of overflowing
+    let priceless = input
+        .replace(r#""price": 20"#, &format!(r#""price": {}"#, u64::MAX))
+        .replace(r#""quantity": 1"#, r#""quantity": 3"#);
+    let (status, solution) = optimize_json(&priceless).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(solution["stats"]["remnantCount"], 1);
+}
+
+#[tokio::test]
+async fn must_use_stock_pieces_should_appear_in_the_solution() {
+    let input = |must_use_length: usize| {
+        format!(
+            r#"
+            {{
+                "method": "guillotine",
+                "cutWidth": 2,
+                "stockPieces": [
+                    {{ "width": 48, "length": 96, "patternDirection": "none", "price": 10 }},
+                    {{
+                        "width": 20,
+                        "length": {},
+                        "patternDirection": "none",
+                        "price": 30,
+                        "quantity": 1,
+                        "mustUse": true
+                    }}
+                ],
+                "cutPieces": [
+                    {{ "width": 10, "length": 30, "patternDirection": "none", "canRotate": true }},
+                    {{ "width": 20, "length": 40, "patternDirection": "none", "canRotate": true }}
+                ]
+            }}
+        "#,
+            must_use_length
+        )
+    };
+
+    let (status, solution) = optimize_json(&input(40)).await;
+    assert_eq!(status, StatusCode::OK);
+    let sheets = solution["stockPieces"].as_array().unwrap();
+    assert!(sheets.iter().any(|sheet| sheet["length"] == 40));
+    assert_eq!(solution["stats"]["sheetCount"], 2);
+
+    let (status, error) = optimize_json(&input(5)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["data"][0]["code"], "unsatisfiableMustUse");
+    assert_eq!(error["data"][0]["path"], "stockPieces[1].mustUse");
+}
+
+#[tokio::test]
+async fn optional_cut_pieces_should_only_fill_leftover_room() {
+    let input = |required: bool| {
+        format!(
+            r#"
+            {{
+                "method": "guillotine",
+                "cutWidth": 2,
+                "stockPieces": [
+                    {{ "width": 48, "length": 96, "patternDirection": "none", "price": 0 }}
+                ],
+                "cutPieces": [
+                    {{
+                        "width": 30,
+                        "length": 60,
+                        "patternDirection": "none",
+                        "canRotate": false,
+                        "optional": {}
+                    }},
+                    {{
+                        "width": 10,
+                        "length": 10,
+                        "patternDirection": "none",
+                        "canRotate": false,
+                        "quantity": 4,
+                        "optional": true
+                    }},
+                    {{
+                        "width": 48,
+                        "length": 96,
+                        "patternDirection": "none",
+                        "canRotate": false,
+                        "optional": true
+                    }}
+                ]
+            }}
+        "#,
+            !required
+        )
+    };
+
+    let (status, solution) = optimize_json(&input(true)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(solution["stats"]["sheetCount"], 1);
+    let groups = solution["cutPieceGroups"].as_array().unwrap();
+    assert_eq!(groups[0]["optional"], false);
+    assert_eq!(groups[0]["placements"].as_array().unwrap().len(), 1);
+    assert_eq!(groups[1]["optional"], true);
+    assert_eq!(groups[1]["placements"].as_array().unwrap().len(), 4);
+    assert!(groups[2]["placements"].as_array().unwrap().is_empty());
+
+    // Fillers go in the waste, a kerf away from everything else
+    let cut_pieces = solution["stockPieces"][0]["cutPieces"].as_array().unwrap();
+    assert_eq!(cut_pieces.len(), 5);
+    let rect = |cp: &Value| {
+        let get = |key: &str| cp[key].as_u64().unwrap();
+        (get("x"), get("y"), get("width"), get("length"))
+    };
+    for (i, a) in cut_pieces.iter().map(rect).enumerate() {
+        assert!(a.0 + a.2 <= 48 && a.1 + a.3 <= 96);
+        for b in cut_pieces.iter().map(rect).skip(i + 1) {
+            let apart = a.0 + a.2 + 2 <= b.0
+                || b.0 + b.2 + 2 <= a.0
+                || a.1 + a.3 + 2 <= b.1
+                || b.1 + b.3 + 2 <= a.1;
+            assert!(apart, "{:?} and {:?} overlap", a, b);
+        }
+    }
+
+    let (status, error) = optimize_json(&input(false)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["data"][0]["code"], "noRequiredCutPieces");
+}
+
+#[tokio::test]
+async fn quantities_past_the_largest_count_should_be_rejected() {
+    let input = format!(
+        r#"
+        {{
+            "method": "guillotine",
+            "cutWidth": 2,
+            "stockPieces": [
+                {{ "width": 48, "length": 96, "patternDirection": "none", "price": 0 }}
+            ],
+            "cutPieces": [
+                {{
+                    "width": 10,
+                    "length": 10,
+                    "patternDirection": "none",
+                    "canRotate": false,
+                    "quantity": {}
+                }},
+                {{
+                    "width": 10,
+                    "length": 10,
+                    "patternDirection": "none",
+                    "canRotate": false,
+                    "quantity": 2
+                }}
+            ]
+        }}
+    "#,
+        u64::MAX
+    );
+
+    assert_eq!(
+        validation_error_codes(&input).await,
+        vec![("tooManyCutPieces".to_string(), "cutPieces".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn cut_piece_quantity_should_be_expanded_and_grouped() {
+    let input = TEST_INPUT.replace(
+        r#""canRotate": true
+            },"#,
+        r#""canRotate": true,
+                "quantity": 3
+            },"#,
+    );
+    let (status, solution) = optimize_json(&input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let groups = solution["cutPieceGroups"].as_array().unwrap();
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0]["externalId"], 1);
+    assert_eq!(groups[0]["quantity"], 3);
+    assert_eq!(groups[1]["quantity"], 1);
+
+    let placed: Vec<_> = groups[0]["placements"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|placement| {
+            &solution["stockPieces"][placement["sheet"].as_u64().unwrap() as usize]["cutPieces"]
+                [placement["index"].as_u64().unwrap() as usize]
+        })
+        .collect();
+    assert_eq!(placed.len(), 3);
+    for cut_piece in placed {
+        assert_eq!(cut_piece["externalId"], 1);
+    }
+    assert_eq!(solution["stats"]["usedArea"], 3 * 10 * 30 + 45 * 100);
+}
+
+#[tokio::test]
+async fn cut_piece_groups_should_only_be_included_with_quantities() {
+    let (status, solution) = optimize_json(TEST_INPUT).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert!(solution.get("cutPieceGroups").is_none());
+    let external_ids: Vec<_> = solution["stockPieces"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .flat_map(|sp| sp["cutPieces"].as_array().unwrap())
+        .map(|cp| cp["externalId"].as_u64().unwrap())
+        .collect();
+    assert!(external_ids.contains(&1) && external_ids.contains(&2));
+}
+
+#[tokio::test]
+async fn zero_cut_piece_quantity_should_be_rejected() {
+    let input = TEST_INPUT.replace(
+        r#""canRotate": true
+            },"#,
+        r#""canRotate": true,
+                "quantity": 0
+            },"#,
+    );
+    let (status, body) = optimize_json(&input).await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["data"][0]["code"], "invalidQuantity");
+    assert_eq!(body["data"][0]["path"], "cutPieces[0].quantity");
+}
+
+#[tokio::test]
+async fn errors_should_be_problem_details_when_accepted() {
+    let request = |content_type: &str, body: String| {
+        Request::builder()
+            .header("Content-Type", content_type)
+            .header("Accept", "application/json, application/problem+json")
+            .method("POST")
+            .uri("/optimize")
+            .body(body.into())
+            .unwrap()
+    };
+    let problem = |resp: axum::response::Response| async move {
+        assert_eq!(resp.headers()["Content-Type"], "application/problem+json");
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        serde_json::from_slice::<Value>(&body).unwrap()
+    };
+
+    let input = TEST_INPUT.replace(
+        r#""canRotate": true
+            },"#,
+        r#""canRotate": true,
+                "quantity": 0
+            },"#,
+    );
+    let resp = test_app()
+        .oneshot(request("application/json", input))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = problem(resp).await;
+    assert_eq!(body["type"], "urn:cut-optimizer-2d:problem:invalidQuantity");
+    assert_eq!(body["status"], 422);
+    assert_eq!(body["detail"], "Quantity must be at least 1");
+    assert_eq!(body["errors"][0]["path"], "cutPieces[0].quantity");
+    assert_eq!(body["errors"][0]["type"], body["type"]);
+
+    let resp = test_app()
+        .oneshot(request("text/plain", TEST_INPUT.to_string()))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let body = problem(resp).await;
+    assert_eq!(body["type"], "about:blank");
+    assert_eq!(body["title"], "Unsupported Media Type");
+    assert!(body["detail"]
+        .as_str()
+        .unwrap()
+        .starts_with("Expected request"));
+}
+
+#[tokio::test]
+async fn edge_allowance_should_be_added_before_optimizing_and_reported() {
+    let input = TEST_INPUT.replace(
+        r#""canRotate": true
+            },"#,
+        r#""canRotate": true,
+                "edgeAllowance": { "top": 2, "bottom": 2, "left": 1, "right": 1 }
+            },"#,
+    );
+    let (status, solution) = optimize_json(&input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let allowances = solution["edgeAllowances"].as_array().unwrap();
+    assert_eq!(allowances.len(), 1);
+    let placed = &allowances[0];
+    let cut_piece = &solution["stockPieces"][placed["sheet"].as_u64().unwrap() as usize]
+        ["cutPieces"][placed["index"].as_u64().unwrap() as usize];
+    assert_eq!(cut_piece["externalId"], 1);
+
+    let field = |value: &Value, name: &str| value[name].as_u64().unwrap();
+    let finished = &placed["finished"];
+    assert_eq!(
+        field(cut_piece, "width"),
+        field(finished, "width") + field(placed, "left") + field(placed, "right")
+    );
+    assert_eq!(
+        field(cut_piece, "length"),
+        field(finished, "length") + field(placed, "top") + field(placed, "bottom")
+    );
+    assert_eq!(
+        field(finished, "x"),
+        field(cut_piece, "x") + field(placed, "left")
+    );
+    assert_eq!(
+        field(finished, "y"),
+        field(cut_piece, "y") + field(placed, "top")
+    );
+    if cut_piece["isRotated"] == true {
+        assert_eq!(
+            (field(finished, "width"), field(finished, "length")),
+            (30, 10)
+        );
+    } else {
+        assert_eq!(
+            (field(finished, "width"), field(finished, "length")),
+            (10, 30)
+        );
+        assert_eq!((field(placed, "left"), field(placed, "top")), (1, 2));
+    }
+}
+
+#[tokio::test]
+async fn metadata_should_be_echoed_with_the_placed_pieces() {
+    let input = TEST_INPUT
+        .replace(
+            r#""canRotate": true
+            },"#,
+            r#""canRotate": true,
+                "metadata": { "part": "shelf", "barcode": "A-17" }
+            },"#,
+        )
+        .replace(
+            r#""length": 120,
+                "patternDirection": "none","#,
+            r#""length": 120,
+                "metadata": { "orderLine": 7 },
+                "patternDirection": "none","#,
+        );
+    let (status, solution) = optimize_json(&input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let metadata = &solution["metadata"];
+    let sheets = metadata["sheets"].as_array().unwrap();
+    assert_eq!(sheets.len(), 1);
+    assert_eq!(sheets[0]["stockPiece"], 1);
+    assert_eq!(sheets[0]["metadata"], json!({ "orderLine": 7 }));
+    let sheet = &solution["stockPieces"][sheets[0]["sheet"].as_u64().unwrap() as usize];
+    assert_eq!(sheet["length"], 120);
+
+    let placed = metadata["cutPieces"].as_array().unwrap();
+    assert_eq!(placed.len(), 1);
+    assert_eq!(placed[0]["cutPiece"], 0);
+    assert_eq!(
+        placed[0]["metadata"],
+        json!({ "part": "shelf", "barcode": "A-17" })
+    );
+    let cut_piece = &solution["stockPieces"][placed[0]["sheet"].as_u64().unwrap() as usize]
+        ["cutPieces"][placed[0]["index"].as_u64().unwrap() as usize];
+    assert_eq!(cut_piece["externalId"], 1);
+
+    let not_object = input.replace(r#"{ "orderLine": 7 }"#, "7");
+    let (status, error) = optimize_json(&not_object).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["data"][0]["code"], "invalidMetadata");
+    assert_eq!(error["data"][0]["path"], "stockPieces[1].metadata");
+}
+
+#[tokio::test]
+async fn trimmed_stock_should_keep_placements_inside_the_trim() {
+    let input = r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 2,
+            "stockPieces": [
+                {
+                    "width": 48,
+                    "length": 96,
+                    "patternDirection": "none",
+                    "price": 0,
+                    "trimTop": 3,
+                    "trimBottom": 1,
+                    "trimLeft": 2,
+                    "trimRight": 1
+                }
+            ],
+            "cutPieces": [
+                { "width": 10, "length": 30, "patternDirection": "none", "canRotate": true },
+                { "width": 45, "length": 60, "patternDirection": "none", "canRotate": true }
+            ]
+        }
+    "#;
+    let (status, solution) = optimize_json(input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let stock_piece = &solution["stockPieces"][0];
+    assert_eq!(
+        (&stock_piece["width"], &stock_piece["length"]),
+        (&json!(48), &json!(96))
+    );
+    let field = |value: &Value, name: &str| value[name].as_u64().unwrap();
+    for rect in stock_piece["cutPieces"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .chain(stock_piece["wastePieces"].as_array().unwrap())
+    {
+        assert!(field(rect, "x") >= 2 && field(rect, "x") + field(rect, "width") <= 47);
+        assert!(field(rect, "y") >= 3 && field(rect, "y") + field(rect, "length") <= 95);
+    }
+}
+
+#[tokio::test]
+async fn guillotine_solutions_should_come_with_a_cutting_sequence() {
+    let input = r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 2,
+            "stockPieces": [
+                {
+                    "width": 48,
+                    "length": 96,
+                    "patternDirection": "none",
+                    "price": 0,
+                    "trimLeft": 1,
+                    "trimTop": 3
+                }
+            ],
+            "cutPieces": [
+                { "width": 10, "length": 30, "patternDirection": "none", "canRotate": true, "quantity": 3 },
+                { "width": 20, "length": 40, "patternDirection": "none", "canRotate": true, "quantity": 2 }
+            ]
+        }
+    "#;
+    let (status, solution) = optimize_json(input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let steps = solution["cutSequence"].as_array().unwrap();
+    assert_eq!(steps[0]["direction"], "rip");
+    assert_eq!(
+        steps[0]["panel"],
+        json!({ "x": 0, "y": 0, "width": 48, "length": 96 })
+    );
+
+    // Every cut piece comes off exactly once, where the solution placed it
+    let stock_piece = &solution["stockPieces"][0];
+    let mut freed = Vec::new();
+    for step in steps {
+        assert_eq!(step["sheet"], 0);
+        let panel = &step["panel"];
+        let (along, across) = match step["direction"].as_str().unwrap() {
+            "rip" => ("x", "width"),
+            _ => ("y", "length"),
+        };
+        let position = step["position"].as_u64().unwrap();
+        assert!(position >= panel[along].as_u64().unwrap());
+        assert!(position < panel[along].as_u64().unwrap() + panel[across].as_u64().unwrap());
+        for result in step["results"].as_array().unwrap() {
+            if result["kind"] == "cutPiece" {
+                let index = result["cutPiece"].as_u64().unwrap() as usize;
+                let cut_piece = &stock_piece["cutPieces"][index];
+                for field in &["x", "y", "width", "length"] {
+                    assert_eq!(result[field], cut_piece[field]);
+                }
+                freed.push(index);
+            }
+        }
+    }
+    freed.sort_unstable();
+    assert_eq!(freed, (0..5).collect::<Vec<_>>());
+
+    let nested = input.replace(r#""method": "guillotine""#, r#""method": "nested""#);
+    let (status, solution) = optimize_json(&nested).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(solution.get("cutSequence").is_none());
+}
+
+#[tokio::test]
+async fn trim_leaving_no_usable_area_should_be_rejected() {
+    let input = TEST_INPUT.replacen(
+        r#""price": 0"#,
+        r#""price": 0, "trimLeft": 24, "trimRight": 24"#,
+        1,
+    );
+    let (status, body) = optimize_json(&input).await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["data"][0]["code"], "trimTooLarge");
+    assert_eq!(body["data"][0]["path"], "stockPieces[0]");
+}
+
+#[tokio::test]
+async fn placements_should_avoid_stock_piece_defects() {
+    let input = r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 2,
+            "stockPieces": [
+                {
+                    "width": 48,
+                    "length": 96,
+                    "patternDirection": "none",
+                    "price": 0,
+                    "trimLeft": 2,
+                    "defects": [{ "x": 2, "y": 40, "width": 6, "length": 10 }]
+                }
+            ],
+            "cutPieces": [
+                {
+                    "width": 18,
+                    "length": 90,
+                    "patternDirection": "none",
+                    "canRotate": false,
+                    "quantity": 2
+                }
+            ]
+        }
+    "#;
+    let (status, solution) = optimize_json(input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(solution["stats"]["sheetCount"], 1);
+    let stock_piece = &solution["stockPieces"][0];
+    assert_eq!(
+        (&stock_piece["width"], &stock_piece["length"]),
+        (&json!(48), &json!(96))
+    );
+    let field = |value: &Value, name: &str| value[name].as_u64().unwrap();
+    for rect in stock_piece["cutPieces"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .chain(stock_piece["wastePieces"].as_array().unwrap())
+    {
+        let clear = field(rect, "x") >= 8
+            || field(rect, "y") >= 50
+            || field(rect, "y") + field(rect, "length") <= 40;
+        assert!(clear, "{} overlaps the defect", rect);
+    }
+
+    let outside = input.replace(r#""x": 2, "y": 40"#, r#""x": 44, "y": 40"#);
+    let (status, body) = optimize_json(&outside).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["data"][0]["code"], "invalidDefect");
+    assert_eq!(body["data"][0]["path"], "stockPieces[0].defects[0]");
+}
+
+#[tokio::test]
+async fn defects_past_the_largest_dimension_should_be_rejected() {
+    let input = format!(
+        r#"
+        {{
+            "method": "guillotine",
+            "cutWidth": 2,
+            "stockPieces": [
+                {{
+                    "width": 48,
+                    "length": 96,
+                    "patternDirection": "none",
+                    "price": 0,
+                    "defects": [{{ "x": {}, "y": 0, "width": 10, "length": 10 }}]
+                }}
+            ],
+            "cutPieces": [
+                {{ "width": 10, "length": 10, "patternDirection": "none", "canRotate": false }}
+            ],
+            "pins": [{{ "cutPiece": 0, "stockPiece": 0, "x": 0, "y": 0 }}]
+        }}
+    "#,
+        u64::MAX
+    );
+
+    assert_eq!(
+        validation_error_codes(&input).await,
+        vec![(
+            "invalidDefect".to_string(),
+            "stockPieces[0].defects[0]".to_string()
+        )]
+    );
+}
+
+#[tokio::test]
+async fn pinned_cut_pieces_should_stay_where_they_were_placed() {
+    let input = r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 2,
+            "stockPieces": [
+                { "width": 48, "length": 96, "patternDirection": "none", "price": 10, "quantity": 2 }
+            ],
+            "cutPieces": [
+                {
+                    "externalId": 1,
+                    "width": 20,
+                    "length": 40,
+                    "patternDirection": "none",
+                    "canRotate": false,
+                    "quantity": 2
+                },
+                { "externalId": 2, "width": 24, "length": 90, "patternDirection": "none", "canRotate": false }
+            ],
+            "pins": [{ "cutPiece": 0, "stockPiece": 0, "x": 0, "y": 0 }]
+        }
+    "#;
+    let (status, solution) = optimize_json(input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(solution["stats"]["sheetCount"], 2);
+    let pinned = json!({
+        "externalId": 1,
+        "x": 0,
+        "y": 0,
+        "width": 20,
+        "length": 40,
+        "patternDirection": "none",
+        "isRotated": false
+    });
+    let sheet = solution["stockPieces"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|sheet| sheet["cutPieces"].as_array().unwrap().contains(&pinned))
+        .expect("The pinned cut piece should be in the solution");
+    let field = |value: &Value, name: &str| value[name].as_u64().unwrap();
+    for rect in sheet["cutPieces"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|cut_piece| **cut_piece != pinned)
+        .chain(sheet["wastePieces"].as_array().unwrap())
+    {
+        let clear = field(rect, "x") >= 22 || field(rect, "y") >= 42;
+        assert!(clear, "{} isn't clear of the pinned cut piece", rect);
+    }
+    assert_eq!(
+        solution["cutPieceGroups"][0]["placements"]
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+
+    let overlapping = input.replace(
+        r#""x": 0, "y": 0 }]"#,
+        r#""x": 0, "y": 0 }, { "cutPiece": 0, "stockPiece": 0, "x": 10, "y": 20 }]"#,
+    );
+    let (status, body) = optimize_json(&overlapping).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["data"][0]["code"], "overlappingPin");
+    assert_eq!(body["data"][0]["path"], "pins[1]");
+}
+
+#[tokio::test]
+async fn materials_should_be_optimized_separately() {
+    let input = r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 2,
+            "stockPieces": [
+                { "width": 48, "length": 96, "patternDirection": "none", "price": 10, "material": "plywood" },
+                { "width": 60, "length": 60, "patternDirection": "none", "price": 10, "material": "mdf" }
+            ],
+            "cutPieces": [
+                { "externalId": 1, "width": 50, "length": 50, "patternDirection": "none", "canRotate": false, "material": "mdf" },
+                {
+                    "externalId": 2,
+                    "width": 20,
+                    "length": 40,
+                    "patternDirection": "none",
+                    "canRotate": false,
+                    "quantity": 2,
+                    "material": "plywood"
+                }
+            ]
+        }
+    "#;
+    let (status, solution) = optimize_json(input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let materials = solution["materials"].as_object().unwrap();
+    assert_eq!(materials.len(), 2);
+    assert_eq!(materials["mdf"]["stockPieces"][0]["width"], 60);
+    assert_eq!(
+        materials["mdf"]["stockPieces"][0]["cutPieces"][0]["externalId"],
+        1
+    );
+    assert_eq!(materials["plywood"]["stockPieces"][0]["width"], 48);
+    assert_eq!(materials["plywood"]["cutPieceGroups"][0]["cutPiece"], 1);
+
+    // The plywood cut piece is the first of its material, but its path counts
+    // every cut piece
+    let too_long = input.replace(r#""length": 40"#, r#""length": 100"#);
+    let (status, body) = optimize_json(&too_long).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["data"][0]["code"], "noFit");
+    assert_eq!(body["data"][0]["path"], "cutPieces[1]");
+
+    let missing = input.replace(r#", "material": "mdf" }"#, " }");
+    let (status, body) = optimize_json(&missing).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["data"][0]["code"], "missingMaterial");
+    assert_eq!(body["data"][0]["path"], "stockPieces[1].material");
+    assert_eq!(body["data"][1]["path"], "cutPieces[0].material");
+}
+
+#[tokio::test]
+async fn stock_piece_cut_width_should_override_the_input_cut_width() {
+    // 2 pieces of 24 only fit across a width of 48 without a kerf between them
+    let input = r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 2,
+            "stockPieces": [
+                { "width": 48, "length": 96, "patternDirection": "none", "price": 0, "cutWidth": 0 }
+            ],
+            "cutPieces": [
+                { "width": 24, "length": 96, "patternDirection": "none", "canRotate": false },
+                { "width": 24, "length": 96, "patternDirection": "none", "canRotate": false }
+            ]
+        }
+    "#;
+    let (status, solution) = optimize_json(input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(solution["stats"]["sheetCount"], 1);
+
+    let (status, solution) = optimize_json(&input.replace(r#", "cutWidth": 0"#, "")).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(solution["stats"]["sheetCount"], 2);
+}
+
+#[tokio::test]
+async fn objective_should_pick_the_best_seed_by_that_measure() {
+    for (objective, field) in [
+        ("minCost", "price"),
+        ("minWaste", "wasteArea"),
+        ("minSheets", "sheetCount"),
+        ("minCutLength", "cutLength"),
+    ] {
+        let input = TEST_INPUT.replace(
+            r#""randomSeed": 1,"#,
+            &format!(
+                r#""seedCount": 4, "includeScores": true, "objective": "{}","#,
+                objective
+            ),
+        );
+        let (status, solution) = optimize_json(&input).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let scores = solution["scores"].as_array().unwrap();
+        let best = scores
+            .iter()
+            .map(|score| score[field].as_u64().unwrap())
+            .min()
+            .unwrap();
+        let picked = scores
+            .iter()
+            .find(|score| score["seed"] == solution["randomSeed"])
+            .unwrap();
+        assert_eq!(picked[field], best, "{}", objective);
+    }
+}
+
+#[tokio::test]
+async fn solution_count_should_return_distinct_alternatives_best_first() {
+    let input = TEST_INPUT.replace(
+        r#""randomSeed": 1,"#,
+        r#""solutionCount": 4, "includeScores": true,"#,
+    );
+    let (status, solution) = optimize_json(&input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(solution["scores"].as_array().unwrap().len(), 4);
+    let alternatives = solution["alternatives"].as_array().unwrap();
+    assert!(alternatives.len() <= 3);
+
+    let mut layouts = vec![&solution["stockPieces"]];
+    let mut previous = solution["stats"]["price"].as_u64().unwrap();
+    for alternative in alternatives {
+        assert!(!layouts.contains(&&alternative["stockPieces"]));
+        layouts.push(&alternative["stockPieces"]);
+        let price = alternative["score"]["price"].as_u64().unwrap();
+        assert!(price >= previous);
+        previous = price;
+        assert!(alternative["metrics"]["cutCount"].as_u64().unwrap() > 0);
+    }
+
+    let (_, solution) = optimize_json(TEST_INPUT).await;
+    assert!(solution.get("alternatives").is_none());
+}
+
+#[test]
+fn cancelling_should_cancel_children_but_not_parents() {
+    let parent = Cancellation::default();
+    let child = parent.child();
+    let sibling = parent.child();
+
+    child.cancel();
+    assert!(child.is_cancelled());
+    assert!(!sibling.is_cancelled());
+    assert!(!parent.is_cancelled());
+
+    parent.cancel();
+    assert!(sibling.is_cancelled());
+}
+
+#[tokio::test]
+async fn early_stop_utilization_should_return_a_solution() {
+    let input = TEST_INPUT.replace(
+        r#""method": "guillotine","#,
+        r#""method": "best", "earlyStopUtilization": 0.01,"#,
+    );
+    let (status, solution) = optimize_json(&input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(solution["partial"], false);
+}
+
+#[tokio::test]
+async fn compute_stats_should_only_be_included_when_requested() {
+    let (_, solution) = optimize_json(TEST_INPUT).await;
+    assert!(solution.get("computeStats").is_none());
+
+    let input = TEST_INPUT.replace(
+        r#""method": "guillotine","#,
+        r#""method": "best", "seedCount": 3, "includeComputeStats": true,"#,
+    );
+    let (status, solution) = optimize_json(&input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let stats = &solution["computeStats"];
+    assert_eq!(stats["runs"], 6);
+    assert!(stats["epochs"].as_u64().unwrap() > 0);
+    assert!(stats["threads"].as_u64().unwrap() >= 1);
+    assert!(stats["wallMs"].as_f64().unwrap() > 0.0);
+    assert!(stats["peakMemoryBytes"].as_u64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn seeds_should_override_random_seed() {
+    let input = TEST_INPUT.replace(r#""randomSeed": 1,"#, r#""seeds": [7],"#);
+    let (status, solution) = optimize_json(&input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(solution["randomSeed"], 7);
+    assert!(solution.get("scores").is_none());
+}
+
+#[tokio::test]
+async fn validation_should_reject_bad_seed_counts() {
+    let input = TEST_INPUT.replace(r#""randomSeed": 1,"#, r#""seedCount": 0,"#);
+    assert_eq!(
+        validation_error_codes(&input).await,
+        vec![("noSeeds".to_string(), "seedCount".to_string())]
+    );
+
+    let input = TEST_INPUT.replace(r#""randomSeed": 1,"#, r#""seedCount": 1000,"#);
+    assert_eq!(
+        validation_error_codes(&input).await,
+        vec![("tooManySeeds".to_string(), "seedCount".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn exceeding_max_seconds_should_return_request_timeout() {
+    let input = TEST_INPUT.replace(r#""randomSeed": 1,"#, r#""maxSeconds": 0.000001,"#);
+    let (status, body) = optimize_json(&input).await;
+
+    assert_eq!(status, StatusCode::REQUEST_TIMEOUT);
+    assert_eq!(body["message"], "Optimizer didn't finish in time");
+}
+
+#[tokio::test]
+async fn generous_max_seconds_should_return_ok() {
+    let input = TEST_INPUT.replace(r#""randomSeed": 1,"#, r#""maxSeconds": 60,"#);
+    let (status, _) = optimize_json(&input).await;
+
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn validation_should_reject_non_positive_max_seconds() {
+    let input = TEST_INPUT.replace(r#""randomSeed": 1,"#, r#""maxSeconds": 0,"#);
+    assert_eq!(
+        validation_error_codes(&input).await,
+        vec![("invalidMaxSeconds".to_string(), "maxSeconds".to_string())]
+    );
+}
+
+#[test]
+fn dropping_cancel_guard_should_stop_optimizer() {
+    let cancellation = Cancellation::default();
+    drop(cancellation.cancel_on_drop());
+
+    assert!(cancellation.is_cancelled());
+    assert_eq!(cancellation.run(|progress| progress(0.5)), None);
+}
+
+#[tokio::test]
+async fn solution_should_include_cut_metrics() {
+    let input = r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 0,
+            "feedRate": 10,
+            "stockPieces": [
+                { "width": 20, "length": 10, "patternDirection": "none", "price": 0, "quantity": 1 }
+            ],
+            "cutPieces": [
+                { "width": 10, "length": 10, "patternDirection": "none", "canRotate": false },
+                { "width": 10, "length": 10, "patternDirection": "none", "canRotate": false }
+            ]
+        }
+    "#;
+    let (status, solution) = optimize_json(input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        solution["metrics"],
+        json!({ "cutCount": 1, "cutLength": 10, "sawingSeconds": 1.0 })
+    );
+}
+
+#[tokio::test]
+async fn best_effort_should_stop_before_request_timeout() {
+    let mut input: OptimizerInput = serde_json::from_str(TEST_INPUT).unwrap();
+    input.best_effort = Some(true);
+    let config = OptimizerConfig {
+        timeout: Duration::from_micros(1),
+        compute: Arc::new(ComputePool::new(
+            1,
+            None,
+            PanicPolicy::Continue,
+            None,
+            None,
+            None,
+        )),
+        max_dimension: None,
+        max_batch_size: 10,
+        catalogs: None,
+        presets: None,
+        translations: None,
+        health: Arc::default(),
+    };
+
+    let (status, Json(body)) = run_optimizer(&config, input).await.err().unwrap();
+
+    assert_eq!(status, StatusCode::REQUEST_TIMEOUT);
+    assert_eq!(body["message"], "Optimizer didn't finish in time");
+}
+
+#[tokio::test]
+async fn solution_should_include_quality_score() {
+    let input = r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 1,
+            "qualityWeights": { "utilization": 1, "offcutUsability": 0, "cutSimplicity": 0 },
+            "stockPieces": [
+                { "width": 40, "length": 10, "patternDirection": "none", "price": 0, "quantity": 1 }
+            ],
+            "cutPieces": [
+                { "width": 10, "length": 10, "patternDirection": "none", "canRotate": false },
+                { "width": 10, "length": 10, "patternDirection": "none", "canRotate": false }
+            ]
+        }
+    "#;
+    let (status, solution) = optimize_json(input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(solution["quality"]["utilization"], 0.5);
+    assert_eq!(solution["quality"]["offcutUsability"], 1.0);
+    assert_eq!(solution["quality"]["score"], 50.0);
+}
+
+#[tokio::test]
+async fn validation_should_reject_zero_quality_weights() {
+    let input = TEST_INPUT.replace(
+        r#""randomSeed": 1,"#,
+        r#""qualityWeights": { "utilization": 0, "offcutUsability": 0, "cutSimplicity": 0 },"#,
+    );
+    assert_eq!(
+        validation_error_codes(&input).await,
+        vec![(
+            "invalidQualityWeight".to_string(),
+            "qualityWeights".to_string()
+        )]
+    );
+}
+
+#[tokio::test]
+async fn exceeding_cpu_limit_should_stop_job() {
+    let input: OptimizerInput = serde_json::from_str(TEST_INPUT).unwrap();
+    let config = OptimizerConfig {
+        timeout: Duration::from_secs(60),
+        compute: Arc::new(ComputePool::new(
+            1,
+            Some(Duration::from_nanos(1)),
+            PanicPolicy::Continue,
+            None,
+            None,
+            None,
+        )),
+        max_dimension: None,
+        max_batch_size: 10,
+        catalogs: None,
+        presets: None,
+        translations: None,
+        health: Arc::default(),
+    };
+
+    let (status, Json(body)) = run_optimizer(&config, input).await.err().unwrap();
+
+    assert_eq!(status, StatusCode::REQUEST_TIMEOUT);
+    assert_eq!(
+        body["message"],
+        "Optimizer used more than the CPU time limit"
+    );
+    assert!(config
+        .compute
+        .metrics()
+        .contains("cut_optimizer_compute_jobs_killed_total 1\n"));
+}
+
+#[tokio::test]
+async fn panics_should_be_counted_and_restart_the_pool_if_asked() {
+    for (policy, restarts) in [(PanicPolicy::Continue, 0), (PanicPolicy::Restart, 1)] {
+        let compute = ComputePool::new(1, None, policy, None, None, None);
+        let (tx, rx) = oneshot::channel::<()>();
+        compute.spawn(move || {
+            let _tx = tx;
+            panic!("Optimizer bug");
+        });
+        assert!(rx.await.is_err());
+
+        // The panic is handled after the job's values are dropped, so wait for
+        // a later job on the same single worker
+        let (tx, rx) = oneshot::channel();
+        compute.spawn(move || tx.send(()).unwrap());
+        rx.await.unwrap();
+
+        let metrics = compute.metrics();
+        assert!(metrics.contains("cut_optimizer_compute_panics_total 1\n"));
+        assert!(metrics.contains(&format!(
+            "cut_optimizer_compute_pool_restarts_total {}\n",
+            restarts
+        )));
+    }
+}
+
+#[tokio::test]
+async fn metrics_should_report_compute_pool() {
+    let resp = test_app()
+        .oneshot(
+            Request::builder()
+                .uri("/metrics")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    let body = std::str::from_utf8(&body).unwrap();
+    assert!(body.contains("cut_optimizer_compute_active_workers 0\n"));
+    assert!(body.contains("cut_optimizer_compute_jobs_total 0\n"));
+}
+
+#[tokio::test]
+async fn cut_piece_expressions_should_use_variables() {
+    let input = r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 1,
+            "variables": { "W": 600, "t": 18 },
+            "stockPieces": [
+                { "width": 1000, "length": 1000, "patternDirection": "none", "price": 0 }
+            ],
+            "cutPieces": [
+                { "width": "W - 2*t", "length": "(W + t) / 2", "patternDirection": "none", "canRotate": false }
+            ]
+        }
+    "#;
+    let (status, solution) = optimize_json(input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let cut_piece = &solution["stockPieces"][0]["cutPieces"][0];
+    assert_eq!(cut_piece["width"], 564);
+    assert_eq!(cut_piece["length"], 309);
+}
+
+#[tokio::test]
+async fn invalid_cut_piece_expressions_should_be_reported() {
+    let input = TEST_INPUT
+        .replacen(r#""width": 10"#, r#""width": "W - 2""#, 1)
+        .replacen(r#""length": 100"#, r#""length": "(3 +""#, 1);
+
+    assert_eq!(
+        validation_error_codes(&input).await,
+        vec![
+            (
+                "invalidExpression".to_string(),
+                "cutPieces[0].width".to_string()
+            ),
+            (
+                "invalidExpression".to_string(),
+                "cutPieces[1].length".to_string()
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn dimensions_above_max_dimension_should_be_rejected() {
+    let app = app(&Opt::from_iter(&[
+        "cut-optimizer-2d-server",
+        "--max-dimension",
+        "96",
+    ]));
+    let (status, body) = optimize_json_with(app, TEST_INPUT).await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["data"][0]["code"], "dimensionTooLarge");
+    assert_eq!(body["data"][0]["path"], "stockPieces[1].length");
+    assert_eq!(body["data"][1]["path"], "cutPieces[1].length");
+
+    let schemas = &openapi::openapi(Some(96))["components"]["schemas"];
+    assert_eq!(schemas["StockPiece"]["properties"]["width"]["maximum"], 96);
+    assert_eq!(schemas["Dimension"]["oneOf"][0]["maximum"], 96);
+    assert!(
+        openapi::openapi(None)["components"]["schemas"]["StockPiece"]["properties"]["width"]
+            .get("maximum")
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn validation_should_report_every_non_fitting_cut_piece() {
+    let codes = validation_error_codes(
+        r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 2,
+            "stockPieces": [
+                { "width": 48, "length": 96, "patternDirection": "parallelToLength", "price": 0 }
+            ],
+            "cutPieces": [
+                { "width": 60, "length": 100, "patternDirection": "none", "canRotate": true },
+                { "width": 90, "length": 40, "patternDirection": "parallelToWidth", "canRotate": true },
+                { "width": 20, "length": 20, "patternDirection": "parallelToWidth", "canRotate": false },
+                { "width": 200, "length": 10, "patternDirection": "parallelToLength", "canRotate": true }
+            ]
+        }
+        "#,
+    )
+    .await;
+
+    assert_eq!(
+        codes,
+        vec![
+            ("noFit".to_string(), "cutPieces[0]".to_string()),
+            ("noFit".to_string(), "cutPieces[2]".to_string()),
+            ("noFit".to_string(), "cutPieces[3]".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn allow_partial_should_report_unplaced_pieces() {
+    let input = r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 2,
+            "allowMixedStockSizes": false,
+            "allowPartial": true,
+            "stockPieces": [
+                { "width": 10, "length": 100, "patternDirection": "none", "price": 0 },
+                { "width": 100, "length": 10, "patternDirection": "none", "price": 0 }
+            ],
+            "cutPieces": [
+                { "externalId": 1, "width": 5, "length": 90, "patternDirection": "none", "canRotate": false },
+                { "externalId": 2, "width": 90, "length": 5, "patternDirection": "none", "canRotate": false },
+                {
+                    "externalId": 3,
+                    "width": 500,
+                    "length": 500,
+                    "patternDirection": "none",
+                    "canRotate": true,
+                    "quantity": 2
+                }
+            ]
+        }
+    "#;
+    let (status, solution) = optimize_json(input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let unplaced = solution["unplacedPieces"].as_array().unwrap();
+    assert_eq!(unplaced.len(), 2);
+    assert_eq!(unplaced[0]["externalId"], 3);
+    assert_eq!(unplaced[0]["quantity"], 2);
+    assert_ne!(unplaced[1]["externalId"], 3);
+    assert_eq!(solution["stockPieces"].as_array().unwrap().len(), 1);
+
+    let (status, _) =
+        optimize_json(&input.replace(r#""allowPartial": true"#, r#""allowPartial": false"#)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[test]
+fn time_slices_should_go_to_the_longest_waiting_run() {
+    let scheduler = Arc::new(scheduler::Scheduler::new(1, Duration::ZERO, None));
+    let slot = scheduler.acquire();
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let waiting = {
+        let scheduler = scheduler.clone();
+        std::thread::spawn(move || {
+            let _slot = scheduler.acquire();
+            tx.send(()).unwrap();
+        })
+    };
+    while scheduler.waiting() == 0 {
+        std::thread::yield_now();
+    }
+
+    assert!(rx.try_recv().is_err(), "the only slot is taken");
+    assert!(slot.yield_if_due());
+    assert!(rx.try_recv().is_ok());
+    assert_eq!(scheduler.yields(), 1);
+    assert!(!slot.yield_if_due(), "nothing is waiting");
+
+    drop(slot);
+    waiting.join().unwrap();
+}
+
+#[tokio::test]
+async fn time_sliced_jobs_should_finish() {
+    let input = || -> OptimizerInput {
+        let mut input: OptimizerInput = serde_json::from_str(TEST_INPUT).unwrap();
+        input.seed_count = Some(4);
+        input
+    };
+    let config = OptimizerConfig {
+        timeout: Duration::from_secs(60),
+        compute: Arc::new(ComputePool::new(
+            1,
+            None,
+            PanicPolicy::Continue,
+            Some(Duration::ZERO),
+            None,
+            None,
+        )),
+        max_dimension: None,
+        max_batch_size: 10,
+        catalogs: None,
+        presets: None,
+        translations: None,
+        health: Arc::default(),
+    };
+
+    let (first, second) = tokio::join!(
+        run_optimizer(&config, input()),
+        run_optimizer(&config, input())
+    );
+
+    assert!(first.is_ok());
+    assert!(second.is_ok());
+    assert!(config
+        .compute
+        .metrics()
+        .contains("cut_optimizer_compute_waiting_runs 0\n"));
+}
+
+#[tokio::test]
+async fn no_fit_errors_should_suggest_a_stock_size() {
+    let input = r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 2,
+            "stockPieces": [
+                {
+                    "width": 48,
+                    "length": 96,
+                    "patternDirection": "parallelToLength",
+                    "price": 0,
+                    "trimLeft": 1,
+                    "trimRight": 1
+                },
+                { "width": 100, "length": 100, "patternDirection": "parallelToWidth", "price": 0 }
+            ],
+            "cutPieces": [
+                { "width": 60, "length": 90, "patternDirection": "parallelToLength", "canRotate": false },
+                { "width": 10, "length": 10, "patternDirection": "none", "canRotate": false }
+            ]
+        }
+    "#;
+    let (status, error) = optimize_json(input).await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        error["data"][0]["suggestion"],
+        serde_json::json!({
+            "width": 60,
+            "length": 90,
+            "patternDirection": "parallelToLength",
+            "closestStockPiece": { "index": 0, "width": 62, "length": 96 }
+        })
+    );
+    assert_eq!(
+        error["data"][1]["suggestion"],
+        serde_json::json!({ "width": 10, "length": 10, "patternDirection": "none" })
+    );
+}
+
+#[tokio::test]
+async fn no_fit_errors_should_include_fixes() {
+    let input = r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 2,
+            "stockPieces": [
+                { "width": 48, "length": 96, "patternDirection": "none", "price": 0 }
+            ],
+            "cutPieces": [
+                { "width": 49, "length": 30, "patternDirection": "none", "canRotate": false }
+            ]
+        }
+    "#;
+    let (status, error) = optimize_json(input).await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        error["data"][0]["fixes"],
+        serde_json::json!([
+            {
+                "message": "Enable rotation",
+                "changes": [{ "path": "cutPieces[0].canRotate", "value": true }]
+            },
+            {
+                "message": "Reduce the width by 1",
+                "changes": [{ "path": "cutPieces[0].width", "value": 48 }]
+            }
+        ])
+    );
+}
+
+#[tokio::test]
+async fn solutions_should_warn_about_likely_mistakes() {
+    let input = r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 0,
+            "stockPieces": [
+                { "width": 48, "length": 96, "patternDirection": "none", "price": 0 },
+                { "width": 20, "length": 5, "patternDirection": "none", "price": 0 }
+            ],
+            "cutPieces": [
+                { "width": 5, "length": 10, "patternDirection": "none", "canRotate": false }
+            ]
+        }
+    "#;
+    let (status, solution) = optimize_json(input).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let warnings = solution["warnings"].as_array().unwrap();
+    assert_eq!(warnings[0]["code"], "zeroCutWidth");
+    assert_eq!(warnings[1]["code"], "unusableStockPiece");
+    assert_eq!(warnings[1]["path"], "stockPieces[1]");
+    assert_eq!(
+        warnings[1]["fixes"][0]["changes"][0],
+        serde_json::json!({ "path": "cutPieces[0].canRotate", "value": true })
+    );
+
+    let (_, solution) = optimize_json(TEST_INPUT).await;
+    assert!(solution.get("warnings").is_none());
+}
+
+#[test]
+fn eco_hours_should_limit_runs_at_once() {
+    let eco = |hours: &str| scheduler::Eco {
+        hours: hours.parse().unwrap(),
+        slots: 1,
+    };
+    assert!(!scheduler::Scheduler::new(2, Duration::MAX, Some(eco("5-5"))).is_eco());
+    assert!("22".parse::<EcoHours>().is_err());
+    assert!("22-25".parse::<EcoHours>().is_err());
+
+    let scheduler = Arc::new(scheduler::Scheduler::new(
+        2,
+        Duration::MAX,
+        Some(eco("0-24")),
+    ));
+    assert!(scheduler.is_eco());
+    let slot = scheduler.acquire();
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let waiting = {
+        let scheduler = scheduler.clone();
+        std::thread::spawn(move || {
+            let _slot = scheduler.acquire();
+            tx.send(()).unwrap();
+        })
+    };
+    while scheduler.waiting() == 0 {
+        std::thread::yield_now();
+    }
+
+    assert!(rx.try_recv().is_err(), "eco mode leaves one slot");
+    drop(slot);
+    waiting.join().unwrap();
+    assert!(rx.try_recv().is_ok());
+}
+
+#[tokio::test]
+async fn stock_catalogs_should_be_stored_and_used_by_name() {
+    let dir = std::env::temp_dir().join(format!("cut-optimizer-catalogs-{}", std::process::id()));
+    let app = app(&Opt::from_iter(&[
+        "cut-optimizer-2d-server",
+        "--catalog-dir",
+        dir.to_str().unwrap(),
+    ]));
+    let call = |method: &str, uri: &str, body: &str| {
+        let request = Request::builder()
+            .header("Content-Type", "application/json")
+            .method(method)
+            .uri(uri)
+            .body(body.to_string().into())
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let resp = app.oneshot(request).await.unwrap();
+            let status = resp.status();
+            let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body).ok())
+        }
+    };
+    let stock_piece = |length: usize| {
+        format!(
+            r#"[{{ "width": 48, "length": {}, "patternDirection": "none", "price": 0 }}]"#,
+            length
+        )
+    };
+
+    let (status, _) = call("PUT", "/catalogs/plywood-18mm/stock", &stock_piece(96)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, catalog) = call("POST", "/catalogs/plywood-18mm/stock", &stock_piece(120)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(catalog.unwrap().as_array().unwrap().len(), 2);
+    let (_, catalog) = call("GET", "/catalogs/plywood-18mm/stock", "").await;
+    assert_eq!(catalog.unwrap()[1]["length"], 120);
+
+    let input = TEST_INPUT.replace(
+        r#""cutWidth": 2,"#,
+        r#""cutWidth": 2, "stockCatalog": "plywood-18mm","#,
+    );
+    let (status, solution) = optimize_json_with(app.clone(), &input).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(solution["stats"]["sheetCount"].as_u64().unwrap() > 0);
+
+    let (status, error) =
+        optimize_json_with(app.clone(), &input.replace("plywood-18mm", "mdf")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["data"][0]["code"], "unknownStockCatalog");
+
+    let (status, _) = call("GET", "/catalogs/a%20b/stock", "").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call("DELETE", "/catalogs/plywood-18mm/stock", "").await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = call("GET", "/catalogs/plywood-18mm/stock", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn failing_components_should_degrade_without_stopping_optimization() {
+    // Catalogs can't be stored under a regular file
+    let file = std::env::temp_dir().join(format!("cut-optimizer-degraded-{}", std::process::id()));
+    std::fs::write(&file, "").unwrap();
+    let dir = file.join("catalogs");
+    // Translations are in a directory the probe reaches, with a broken catalog
+    let translations = std::env::temp_dir().join(format!(
+        "cut-optimizer-degraded-translations-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&translations).unwrap();
+    std::fs::write(translations.join("fr.toml"), "noFit = [").unwrap();
+    let app = app(&Opt::from_iter(&[
+        "cut-optimizer-2d-server",
+        "--catalog-dir",
+        dir.to_str().unwrap(),
+        "--translation-dir",
+        translations.to_str().unwrap(),
+    ]));
+    let readyz = || {
+        let app = app.clone();
+        async move {
+            let resp = app
+                .oneshot(Request::get("/readyz").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+            let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+            serde_json::from_slice::<Value>(&body).unwrap()
+        }
+    };
+    let put_catalog = || {
+        app.clone().oneshot(
+            Request::builder()
+                .header("Content-Type", "application/json")
+                .method("PUT")
+                .uri("/catalogs/plywood-18mm/stock")
+                .body("[]".into())
+                .unwrap(),
+        )
+    };
+
+    let resp = put_catalog().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let (status, solution) = optimize_json_with(app.clone(), TEST_INPUT).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(solution["warnings"][0]["code"], "degradedComponent");
+    assert_eq!(solution["warnings"][0]["path"], "stockCatalog");
+
+    let french = TEST_INPUT.replace(r#""method""#, r#""language": "fr", "method""#);
+    let (status, _) = optimize_json_with(app.clone(), &french).await;
+    assert_eq!(status, StatusCode::OK);
+
+    for _ in 0..2 {
+        let health = readyz().await;
+        assert_eq!(health["status"], "degraded");
+        assert_eq!(health["components"]["optimizer"]["status"], "ok");
+        assert_eq!(health["components"]["stockCatalogs"]["status"], "degraded");
+        assert_eq!(health["components"]["translations"]["status"], "degraded");
+    }
+
+    // A reachable directory doesn't clear what failed in it, but using the
+    // components again does
+    std::fs::remove_file(&file).unwrap();
+    let health = readyz().await;
+    assert_eq!(health["components"]["stockCatalogs"]["status"], "degraded");
+    let resp = put_catalog().await.unwrap();
+    assert!(resp.status().is_success());
+    std::fs::write(translations.join("fr.toml"), "").unwrap();
+    optimize_json_with(app.clone(), &french).await;
+
+    let health = readyz().await;
+    assert_eq!(health["status"], "ready");
+    assert_eq!(health["components"]["stockCatalogs"]["status"], "ok");
+    assert_eq!(health["components"]["translations"]["status"], "ok");
+    let (_, solution) = optimize_json_with(app.clone(), TEST_INPUT).await;
+    assert!(solution.get("warnings").is_none());
+
+    std::fs::remove_dir_all(&file).unwrap();
+    std::fs::remove_dir_all(&translations).unwrap();
+}
+
+#[tokio::test]
+async fn messages_should_be_translated_from_catalogs() {
+    let dir =
+        std::env::temp_dir().join(format!("cut-optimizer-translations-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("fr.toml"),
+        r#"noFit = "La pièce {0}x{1} ne rentre dans aucun panneau ({path})""#,
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("de.json"),
+        r#"{ "unusableStockPiece": "Keine Platte passt in {0}x{1}" }"#,
+    )
+    .unwrap();
+    let app = app(&Opt::from_iter(&[
+        "cut-optimizer-2d-server",
+        "--translation-dir",
+        dir.to_str().unwrap(),
+    ]));
+
+    let too_long = TEST_INPUT
+        .replace(r#""length": 100"#, r#""length": 130"#)
+        .replace(r#""method""#, r#""language": "fr-CA", "method""#);
+    let (status, error) = optimize_json_with(app.clone(), &too_long).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        error["data"][0]["message"],
+        "La pièce 45x130 ne rentre dans aucun panneau (cutPieces[1])"
+    );
+
+    let unusable = r#"
+        {
+            "method": "guillotine",
+            "cutWidth": 2,
+            "stockPieces": [
+                { "width": 48, "length": 96, "patternDirection": "none", "price": 0 },
+                { "width": 20, "length": 5, "patternDirection": "none", "price": 0 }
+            ],
+            "cutPieces": [
+                { "width": 5, "length": 10, "patternDirection": "none", "canRotate": false }
+            ]
+        }
+    "#;
+    let resp = app
+        .oneshot(
+            Request::builder()
+                .header("Content-Type", "application/json")
+                .header("Accept-Language", "en;q=0.2, de-AT;q=0.8, fr;q=0.5")
+                .method("POST")
+                .uri("/optimize")
+                .body(unusable.into())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    let solution: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        solution["warnings"][0]["message"],
+        "Keine Platte passt in 20x5"
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn concurrent_preset_writes_should_leave_a_whole_document() {
+    let dir = std::env::temp_dir().join(format!(
+        "cut-optimizer-concurrent-presets-{}",
+        std::process::id()
+    ));
+    let app = app(&Opt::from_iter(&[
+        "cut-optimizer-2d-server",
+        "--preset-dir",
+        dir.to_str().unwrap(),
+    ]));
+    let puts = (0..16).map(|i| {
+        // Documents of different lengths, so a mix of two wouldn't parse
+        let preset = json!({ "method": "guillotine", "cutWidth": 10_usize.pow(i % 8) });
+        app.clone().oneshot(
+            Request::builder()
+                .header("Content-Type", "application/json")
+                .method("PUT")
+                .uri("/presets/panel-saw-default")
+                .body(preset.to_string().into())
+                .unwrap(),
+        )
+    });
+    for resp in join_all(puts).await {
+        assert_eq!(resp.unwrap().status(), StatusCode::OK);
+    }
+
+    let resp = app
+        .oneshot(
+            Request::get("/presets/panel-saw-default")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    let preset: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(preset["method"], "guillotine");
+    let files: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(files, vec!["panel-saw-default.json"]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn presets_should_fill_in_what_inputs_leave_unset() {
+    let dir = std::env::temp_dir().join(format!("cut-optimizer-presets-{}", std::process::id()));
+    let app = app(&Opt::from_iter(&[
+        "cut-optimizer-2d-server",
+        "--preset-dir",
+        dir.to_str().unwrap(),
+    ]));
+    let preset = r#"{ "method": "nested", "cutWidth": 3, "seedCount": 2 }"#;
+    let resp = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .header("Content-Type", "application/json")
+                .method("PUT")
+                .uri("/presets/panel-saw-default")
+                .body(preset.into())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let input = TEST_INPUT
+        .replace(
+            r#""method": "guillotine","#,
+            r#""preset": "panel-saw-default","#,
+        )
+        .replace(r#""cutWidth": 2,"#, "");
+    let (status, solution) = optimize_json_with(app.clone(), &input).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(solution["method"], "nested");
+
+    let overridden = input.replace(
+        r#""preset": "panel-saw-default","#,
+        r#""preset": "panel-saw-default", "method": "guillotine","#,
+    );
+    let (status, solution) = optimize_json_with(app.clone(), &overridden).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(solution["method"], "guillotine");
+
+    let (status, error) =
+        optimize_json_with(app.clone(), &input.replace("panel-saw-default", "cnc")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["data"][0]["code"], "unknownPreset");
+
+    let (status, error) = optimize_json_with(
+        app.clone(),
+        &input.replace(
+            r#""preset": "panel-saw-default","#,
+            r#""method": "nested","#,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["data"][0]["code"], "missingCutWidth");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
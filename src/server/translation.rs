@@ -0,0 +1,182 @@
+use axum::Json;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::warn;
+
+use super::health::{Component, Health};
+use super::lint::Warning;
+use super::OptimizeError;
+
+/// Translation catalogs for error and warning messages, read from files named
+/// after the language, such as `fr.toml` or `pt-br.json`. Each maps error and
+/// warning codes to messages. Catalogs are read when they're used, so they can
+/// be added or changed without restarting the server.
+///
+/// A message may refer to `{path}`, and to `{0}`, `{1}`, and so on for the
+/// numbers and `quoted` names in the English message, in order.
+#[derive(Debug)]
+pub(crate) struct Translations {
+    dir: PathBuf,
+
+    /// Language used when a request doesn't ask for one
+    default_language: Option<String>,
+
+    /// Where failures to read a catalog are recorded
+    health: Arc<Health>,
+}
+
+/// Messages of one language by error or warning code
+type Messages = HashMap<String, String>;
+
+impl Translations {
+    pub(crate) fn new(dir: PathBuf, default_language: Option<String>, health: Arc<Health>) -> Self {
+        Self {
+            dir,
+            default_language,
+            health,
+        }
+    }
+
+    pub(crate) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Catalog for a language, or for its primary language if there's no
+    /// catalog for the region, such as `fr` for `fr-CA`
+    async fn messages(&self, language: Option<&str>) -> Option<Messages> {
+        let language = language
+            .or(self.default_language.as_deref())?
+            .to_lowercase();
+        let valid = |tag: &str| {
+            !tag.is_empty() && tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        };
+        if !valid(&language) {
+            return None;
+        }
+
+        let mut tags = vec![language.as_str()];
+        if let Some((primary, _)) = language.split_once('-') {
+            tags.push(primary);
+        }
+        for tag in tags {
+            match self.read(tag).await {
+                Ok(Some(messages)) => {
+                    self.health.succeed(Component::Translations);
+                    return Some(messages);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("Couldn't read the `{}` translation catalog: {}", tag, e);
+                    self.health.fail(Component::Translations, e);
+                    return None;
+                }
+            }
+        }
+        self.health.succeed(Component::Translations);
+        None
+    }
+
+    async fn read(&self, tag: &str) -> io::Result<Option<Messages>> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        match tokio::fs::read_to_string(self.dir.join(format!("{}.toml", tag))).await {
+            Ok(text) => {
+                let document =
+                    toml_edit::Document::parse(text).map_err(|e| invalid(e.to_string()))?;
+                return document
+                    .iter()
+                    .map(|(code, item)| match item.as_str() {
+                        Some(message) => Ok((code.to_string(), message.to_string())),
+                        None => Err(invalid(format!("`{}` isn't a string", code))),
+                    })
+                    .collect::<io::Result<_>>()
+                    .map(Some);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        match tokio::fs::read(self.dir.join(format!("{}.json", tag))).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|e| invalid(e.to_string())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Translate the messages of warnings
+    pub(crate) async fn warnings(&self, language: Option<&str>, warnings: &mut [Warning]) {
+        if warnings.is_empty() {
+            return;
+        }
+        if let Some(messages) = self.messages(language).await {
+            for warning in warnings {
+                if let Some(message) =
+                    translate(&messages, warning.code, &warning.path, &warning.message)
+                {
+                    warning.message = message;
+                }
+            }
+        }
+    }
+
+    /// Translate the messages of the validation errors in an error response
+    pub(crate) async fn error(&self, language: Option<&str>, error: &mut OptimizeError) {
+        let (_, Json(body)) = error;
+        let errors = match body.get_mut("data").and_then(Value::as_array_mut) {
+            Some(errors) => errors,
+            None => return,
+        };
+        if let Some(messages) = self.messages(language).await {
+            for error in errors {
+                let field = |name| error.get(name).and_then(Value::as_str);
+                let (code, path, message) = match (field("code"), field("path"), field("message")) {
+                    (Some(code), Some(path), Some(message)) => (code, path, message),
+                    _ => continue,
+                };
+                if let Some(message) = translate(&messages, code, path, message) {
+                    error["message"] = Value::String(message);
+                }
+            }
+        }
+    }
+}
+
+/// Translated message for a code, filled in with the path and the arguments
+/// of the English message
+fn translate(messages: &Messages, code: &str, path: &str, english: &str) -> Option<String> {
+    let mut message = messages.get(code)?.replace("{path}", path);
+    for (i, argument) in arguments(english).iter().enumerate() {
+        message = message.replace(&format!("{{{}}}", i), argument);
+    }
+    Some(message)
+}
+
+/// Numbers and `quoted` names in a message, in order
+fn arguments(message: &str) -> Vec<&str> {
+    let mut arguments = Vec::new();
+    let mut rest = message;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_digit() || c == '`') {
+        rest = &rest[start..];
+        let end = if let Some(quoted) = rest.strip_prefix('`') {
+            match quoted.find('`') {
+                Some(end) => {
+                    arguments.push(&quoted[..end]);
+                    end + 2
+                }
+                None => break,
+            }
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            arguments.push(rest[..end].trim_end_matches('.'));
+            end
+        };
+        rest = &rest[end..];
+    }
+    arguments
+}
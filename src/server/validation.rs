@@ -0,0 +1,1003 @@
+use cut_optimizer_2d::{CutPiece, PatternDirection, ResultCutPiece, StockPiece};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashSet;
+
+use super::defect::{self, MAX_DEFECTS};
+use super::lint::{Change, Fix};
+use super::{openapi, pin, InputStockPiece, OptimizerInput};
+
+/// Most random seeds a single request may try
+pub(crate) const MAX_SEEDS: usize = 256;
+
+/// Most cut pieces a single request may ask for, counting quantities
+pub(crate) const MAX_CUT_PIECES: usize = 100_000;
+
+/// Largest time budget a request may ask for
+pub(crate) const MAX_SECONDS: f64 = 24.0 * 60.0 * 60.0;
+
+/// Every code a `ValidationError` can have, for the error schemas
+pub(crate) const CODES: &[&str] = &[
+    "emptyStockPieces",
+    "emptyCutPieces",
+    "noRequiredCutPieces",
+    "zeroDimension",
+    "dimensionTooLarge",
+    "cutWidthTooLarge",
+    "duplicateExternalId",
+    "trimTooLarge",
+    "ambiguousTrim",
+    "invalidDefect",
+    "tooManyDefects",
+    "ambiguousDefects",
+    "defectsNeedMixedSizes",
+    "invalidPin",
+    "tooManyPins",
+    "overlappingPin",
+    "conflictingPins",
+    "missingMaterial",
+    "noStockForMaterial",
+    "invalidMetadata",
+    "invalidQuantity",
+    "tooManyCutPieces",
+    "noSeeds",
+    "tooManySeeds",
+    "invalidSolutionCount",
+    "invalidMaxSeconds",
+    "invalidFeedRate",
+    "invalidQualityWeight",
+    "invalidEarlyStopUtilization",
+    "invalidPricePerArea",
+    "invalidCurrency",
+    "invalidExpression",
+    "unknownStockCatalog",
+    "unknownPreset",
+    "missingMethod",
+    "missingCutWidth",
+    "unsatisfiableMustUse",
+    "noFit",
+];
+
+/// Problem that makes an input impossible or meaningless to optimize
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ValidationError {
+    /// Machine-readable error code
+    #[schemars(extend("enum" = CODES))]
+    pub(crate) code: &'static str,
+
+    /// Path of the offending field, such as `cutPieces[2].width`
+    pub(crate) path: String,
+
+    pub(crate) message: String,
+
+    /// Stock size that would fit the cut piece, for `noFit`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) suggestion: Option<StockSuggestion>,
+
+    /// Changes to the input that would resolve the error
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) fixes: Vec<Fix>,
+}
+
+impl ValidationError {
+    pub(crate) fn new(code: &'static str, path: String, message: String) -> Self {
+        debug_assert!(CODES.contains(&code), "`{}` is missing from CODES", code);
+        Self {
+            code,
+            path,
+            message,
+            suggestion: None,
+            fixes: Vec::new(),
+        }
+    }
+}
+
+/// Smallest stock piece that would fit a cut piece that doesn't fit any, given
+/// with `noFit` errors
+#[derive(Debug, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StockSuggestion {
+    /// Smallest usable width, not counting trim. A piece alone on a stock piece
+    /// needs no kerf.
+    pub(crate) width: usize,
+
+    /// Smallest usable length, not counting trim
+    pub(crate) length: usize,
+    #[schemars(with = "openapi::PatternDirection")]
+    pub(crate) pattern_direction: PatternDirection,
+
+    /// Stock piece that would need to grow the least to fit the cut piece, if
+    /// any has a pattern direction it could fit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) closest_stock_piece: Option<ClosestStockPiece>,
+}
+
+#[derive(Debug, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ClosestStockPiece {
+    /// Index of the stock piece in `stockPieces`
+    pub(crate) index: usize,
+
+    /// Width the stock piece would need to be, including its trim
+    pub(crate) width: usize,
+
+    /// Length the stock piece would need to be, including its trim
+    pub(crate) length: usize,
+}
+
+/// Check an input and its resolved cut pieces before running the optimizer,
+/// returning every problem found. Dimensions larger than `max_dimension` are
+/// rejected.
+pub(crate) fn validate(
+    input: &OptimizerInput,
+    cut_pieces: &[CutPiece],
+    max_dimension: Option<usize>,
+) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+
+    if input.method.is_none() {
+        errors.push(ValidationError::new(
+            "missingMethod",
+            "method".to_string(),
+            "A method is required unless the preset sets one".to_string(),
+        ));
+    }
+
+    if input.cut_width.is_none() {
+        errors.push(ValidationError::new(
+            "missingCutWidth",
+            "cutWidth".to_string(),
+            "A cut width is required unless the preset sets one".to_string(),
+        ));
+    }
+
+    if input.stock_pieces.is_empty() {
+        errors.push(ValidationError::new(
+            "emptyStockPieces",
+            "stockPieces".to_string(),
+            "At least one stock piece is required".to_string(),
+        ));
+    }
+
+    if cut_pieces.is_empty() {
+        errors.push(ValidationError::new(
+            "emptyCutPieces",
+            "cutPieces".to_string(),
+            "At least one cut piece is required".to_string(),
+        ));
+    } else if input.cut_pieces.iter().all(|cut_piece| cut_piece.optional) {
+        errors.push(ValidationError::new(
+            "noRequiredCutPieces",
+            "cutPieces".to_string(),
+            "At least one cut piece has to be required, since optional ones only fill leftover room"
+                .to_string(),
+        ));
+    }
+
+    for (i, stock_piece) in input.stock_pieces.iter().enumerate() {
+        check_dimensions(
+            &mut errors,
+            &format!("stockPieces[{}]", i),
+            stock_piece.width,
+            stock_piece.length,
+            max_dimension,
+        );
+    }
+
+    for (i, stock_piece) in input.stock_pieces.iter().enumerate() {
+        if !stock_piece.is_trimmed() {
+            continue;
+        }
+        let usable = stock_piece.usable();
+        if usable.width == 0 || usable.length == 0 {
+            errors.push(ValidationError::new(
+                "trimTooLarge",
+                format!("stockPieces[{}]", i),
+                "Trim margins leave no usable area".to_string(),
+            ));
+        }
+
+        // Solutions only give the size of each stock piece used, so the trim
+        // of a stock piece has to follow from its usable size
+        let ambiguous = input.stock_pieces.iter().any(|other| {
+            let other_usable = other.usable();
+            other_usable.width == usable.width
+                && other_usable.length == usable.length
+                && other_usable.pattern_direction == usable.pattern_direction
+                && trim(other) != trim(stock_piece)
+        });
+        if ambiguous {
+            errors.push(ValidationError::new(
+                "ambiguousTrim",
+                format!("stockPieces[{}]", i),
+                "Another stock piece with a different size or trim has the same usable area"
+                    .to_string(),
+            ));
+        }
+    }
+
+    let stock_metadata = input
+        .stock_pieces
+        .iter()
+        .enumerate()
+        .map(|(i, sp)| (format!("stockPieces[{}].metadata", i), &sp.metadata));
+    let cut_metadata = input
+        .cut_pieces
+        .iter()
+        .enumerate()
+        .map(|(i, cp)| (format!("cutPieces[{}].metadata", i), &cp.metadata));
+    for (path, metadata) in stock_metadata.chain(cut_metadata) {
+        if metadata.as_ref().is_some_and(|value| !value.is_object()) {
+            errors.push(ValidationError::new(
+                "invalidMetadata",
+                path,
+                "Metadata must be a JSON object".to_string(),
+            ));
+        }
+    }
+
+    let pinned = check_pins(&mut errors, input, cut_pieces);
+    check_defects(&mut errors, input, &pinned);
+
+    for (i, cut_piece) in cut_pieces.iter().enumerate() {
+        check_dimensions(
+            &mut errors,
+            &format!("cutPieces[{}]", i),
+            cut_piece.width,
+            cut_piece.length,
+            max_dimension,
+        );
+    }
+
+    if let Some(solution_count) = input.solution_count {
+        if !(1..=MAX_SEEDS).contains(&solution_count) {
+            errors.push(ValidationError::new(
+                "invalidSolutionCount",
+                "solutionCount".to_string(),
+                format!("Solution count must be from 1 to {}", MAX_SEEDS),
+            ));
+        }
+    }
+
+    let mut cut_piece_count: usize = 0;
+    for (i, cut_piece) in input.cut_pieces.iter().enumerate() {
+        let quantity = cut_piece.quantity.unwrap_or(1);
+        if quantity == 0 {
+            errors.push(ValidationError::new(
+                "invalidQuantity",
+                format!("cutPieces[{}].quantity", i),
+                "Quantity must be at least 1".to_string(),
+            ));
+        }
+        cut_piece_count = cut_piece_count.saturating_add(quantity);
+    }
+    if cut_piece_count > MAX_CUT_PIECES {
+        errors.push(ValidationError::new(
+            "tooManyCutPieces",
+            "cutPieces".to_string(),
+            format!(
+                "At most {} cut pieces can be cut per request, counting quantities",
+                MAX_CUT_PIECES
+            ),
+        ));
+    }
+
+    // Optional cut pieces are placed after the stock pieces are chosen, so
+    // they can't be what a stock piece that must be used is used for
+    let (required_cut_pieces, required_count) = input
+        .cut_pieces
+        .iter()
+        .zip(cut_pieces)
+        .filter(|(input, _)| !input.optional)
+        .fold(
+            (Vec::new(), 0usize),
+            |(mut required, count), (input, cut_piece)| {
+                required.push(cut_piece.clone());
+                (required, count.saturating_add(input.quantity.unwrap_or(1)))
+            },
+        );
+    check_must_use(&mut errors, input, &required_cut_pieces, required_count);
+
+    if !input.stock_pieces.is_empty()
+        && input
+            .stock_pieces
+            .iter()
+            .all(|sp| sp.cut_width.unwrap_or(input.cut_width()) > sp.width.max(sp.length))
+    {
+        errors.push(ValidationError::new(
+            "cutWidthTooLarge",
+            "cutWidth".to_string(),
+            format!(
+                "Cut width {} is larger than every stock piece",
+                input.cut_width()
+            ),
+        ));
+    }
+
+    let (seeds_path, seed_count) = match (&input.seeds, input.seed_count) {
+        (Some(seeds), _) => ("seeds", seeds.len()),
+        (None, seed_count) => ("seedCount", seed_count.unwrap_or(1)),
+    };
+    if seed_count == 0 {
+        errors.push(ValidationError::new(
+            "noSeeds",
+            seeds_path.to_string(),
+            "At least one seed is required".to_string(),
+        ));
+    } else if seed_count > MAX_SEEDS {
+        errors.push(ValidationError::new(
+            "tooManySeeds",
+            seeds_path.to_string(),
+            format!("At most {} seeds can be tried per request", MAX_SEEDS),
+        ));
+    }
+
+    if let Some(max_seconds) = input.max_seconds {
+        if !(max_seconds > 0.0 && max_seconds <= MAX_SECONDS) {
+            errors.push(ValidationError::new(
+                "invalidMaxSeconds",
+                "maxSeconds".to_string(),
+                format!(
+                    "maxSeconds must be greater than 0 and at most {}",
+                    MAX_SECONDS
+                ),
+            ));
+        }
+    }
+
+    if let Some(feed_rate) = input.feed_rate {
+        if !(feed_rate > 0.0 && feed_rate.is_finite()) {
+            errors.push(ValidationError::new(
+                "invalidFeedRate",
+                "feedRate".to_string(),
+                "feedRate must be greater than 0".to_string(),
+            ));
+        }
+    }
+
+    if let Some(weights) = &input.quality_weights {
+        let fields = [
+            ("utilization", weights.utilization()),
+            ("offcutUsability", weights.offcut_usability()),
+            ("cutSimplicity", weights.cut_simplicity()),
+        ];
+        for (field, weight) in fields {
+            if !(weight >= 0.0 && weight.is_finite()) {
+                errors.push(ValidationError::new(
+                    "invalidQualityWeight",
+                    format!("qualityWeights.{}", field),
+                    format!("{} weight must be at least 0", field),
+                ));
+            }
+        }
+        if fields.iter().map(|(_, weight)| weight).sum::<f64>() <= 0.0 {
+            errors.push(ValidationError::new(
+                "invalidQualityWeight",
+                "qualityWeights".to_string(),
+                "At least one quality weight must be greater than 0".to_string(),
+            ));
+        }
+    }
+
+    if let Some(threshold) = input.early_stop_utilization {
+        if !(threshold > 0.0 && threshold <= 1.0) {
+            errors.push(ValidationError::new(
+                "invalidEarlyStopUtilization",
+                "earlyStopUtilization".to_string(),
+                "earlyStopUtilization must be greater than 0 and at most 1".to_string(),
+            ));
+        }
+    }
+
+    if let Some(price_per_area) = input.price_per_area {
+        if !(price_per_area >= 0.0 && price_per_area.is_finite()) {
+            errors.push(ValidationError::new(
+                "invalidPricePerArea",
+                "pricePerArea".to_string(),
+                "pricePerArea must be at least 0".to_string(),
+            ));
+        }
+    }
+
+    if let Some(currency) = &input.currency {
+        if !(currency.len() == 3 && currency.chars().all(|c| c.is_ascii_uppercase())) {
+            errors.push(ValidationError::new(
+                "invalidCurrency",
+                "currency".to_string(),
+                format!("`{}` isn't a three letter ISO 4217 currency code", currency),
+            ));
+        }
+    }
+
+    // The optimizer stops at the first cut piece that doesn't fit, so check
+    // them all up front. With partial placement they're reported as unplaced
+    // instead.
+    if !input.allow_partial.unwrap_or(false) {
+        for i in non_fitting(input, cut_pieces) {
+            errors.push(ValidationError {
+                suggestion: Some(suggest(&cut_pieces[i], &input.stock_pieces)),
+                fixes: no_fit_fixes(i, &cut_pieces[i], &input.stock_pieces),
+                ..ValidationError::new(
+                    "noFit",
+                    format!("cutPieces[{}]", i),
+                    format!(
+                        "Cut piece {}x{} doesn't fit in any stock piece",
+                        cut_pieces[i].width, cut_pieces[i].length
+                    ),
+                )
+            });
+        }
+    }
+
+    let mut external_ids = HashSet::new();
+    for (i, cut_piece) in cut_pieces.iter().enumerate() {
+        if let Some(external_id) = cut_piece.external_id {
+            if !external_ids.insert(external_id) {
+                errors.push(ValidationError::new(
+                    "duplicateExternalId",
+                    format!("cutPieces[{}].externalId", i),
+                    format!(
+                        "External ID {} is used by more than one cut piece",
+                        external_id
+                    ),
+                ));
+            }
+        }
+    }
+
+    errors
+}
+
+/// Check that pinned cut pieces lie within the usable area of their stock
+/// pieces, clear of its defects and each other, returning the index of the
+/// stock piece and the placement of each valid one
+fn check_pins(
+    errors: &mut Vec<ValidationError>,
+    input: &OptimizerInput,
+    cut_pieces: &[CutPiece],
+) -> Vec<(usize, ResultCutPiece)> {
+    let mut pinned: Vec<(usize, ResultCutPiece)> = Vec::new();
+    let mut counts = vec![0; cut_pieces.len()];
+    for (k, pin) in input.pins.iter().enumerate() {
+        let path = format!("pins[{}]", k);
+        let invalid = |field: &str, message: String| {
+            ValidationError::new("invalidPin", format!("{}{}", path, field), message)
+        };
+
+        let (cut_piece, stock_piece) = match (
+            cut_pieces.get(pin.cut_piece),
+            input.stock_pieces.get(pin.stock_piece),
+        ) {
+            (Some(cut_piece), Some(stock_piece)) => (cut_piece, stock_piece),
+            (None, _) => {
+                errors.push(invalid(
+                    ".cutPiece",
+                    format!("There's no cut piece {}", pin.cut_piece),
+                ));
+                continue;
+            }
+            (_, None) => {
+                errors.push(invalid(
+                    ".stockPiece",
+                    format!("There's no stock piece {}", pin.stock_piece),
+                ));
+                continue;
+            }
+        };
+
+        let quantity = input.cut_pieces[pin.cut_piece].quantity.unwrap_or(1);
+        counts[pin.cut_piece] += 1;
+        if input.cut_pieces[pin.cut_piece].optional {
+            errors.push(invalid(
+                ".cutPiece",
+                "Optional cut pieces only fill leftover room, so they can't be pinned".to_string(),
+            ));
+            continue;
+        } else if counts[pin.cut_piece] > quantity {
+            errors.push(invalid(
+                ".cutPiece",
+                format!(
+                    "Cut piece {} has more pins than its quantity of {}",
+                    pin.cut_piece, quantity
+                ),
+            ));
+            continue;
+        } else if stock_piece.quantity == Some(0) {
+            errors.push(invalid(
+                ".stockPiece",
+                "A stock piece with a quantity of 0 can't have pins".to_string(),
+            ));
+            continue;
+        } else if pin.is_rotated && !cut_piece.can_rotate {
+            errors.push(invalid(
+                ".isRotated",
+                format!("Cut piece {} can't be rotated", pin.cut_piece),
+            ));
+            continue;
+        } else if bad_defects(stock_piece) {
+            // `check_defects` reports those
+            continue;
+        }
+
+        let placement = pin::placement(pin, cut_piece, stock_piece);
+        if placement.pattern_direction != stock_piece.pattern_direction {
+            errors.push(invalid(
+                "",
+                "The cut piece's pattern has to run the same way as the stock piece's".to_string(),
+            ));
+            continue;
+        }
+        let usable = stock_piece.usable();
+        let within = pin.x >= stock_piece.trim_left
+            && pin.y >= stock_piece.trim_top
+            && placement.x.saturating_add(placement.width) <= usable.width
+            && placement.y.saturating_add(placement.length) <= usable.length;
+        if !within {
+            errors.push(invalid(
+                "",
+                "Pinned cut pieces have to lie within the usable area of the stock piece"
+                    .to_string(),
+            ));
+            continue;
+        }
+
+        let on_sheet: Vec<_> = pinned
+            .iter()
+            .filter(|(i, _)| *i == pin.stock_piece)
+            .map(|(_, other)| defect::placed(other))
+            .collect();
+        if stock_piece.defects.len() + on_sheet.len() >= MAX_DEFECTS {
+            errors.push(ValidationError::new(
+                "tooManyPins",
+                path,
+                format!(
+                    "A stock piece can have at most {} defects and pins together",
+                    MAX_DEFECTS
+                ),
+            ));
+            continue;
+        }
+        let overlaps = defect::usable_defects(stock_piece)
+            .iter()
+            .chain(&on_sheet)
+            .any(|other| defect::intersection(&defect::placed(&placement), other).is_some());
+        if overlaps {
+            errors.push(ValidationError::new(
+                "overlappingPin",
+                path,
+                "The pinned cut piece overlaps a defect or another pinned cut piece".to_string(),
+            ));
+            continue;
+        }
+        pinned.push((pin.stock_piece, placement));
+    }
+
+    // Each solution is optimized with a single cut width, and has to include
+    // every sheet with pins
+    let cut_widths: HashSet<_> = input
+        .pins
+        .iter()
+        .filter_map(|pin| input.stock_pieces.get(pin.stock_piece))
+        .map(|sp| sp.cut_width.unwrap_or(input.cut_width()))
+        .collect();
+    if cut_widths.len() > 1 {
+        errors.push(ValidationError::new(
+            "conflictingPins",
+            "pins".to_string(),
+            "Stock pieces with pins have different cut widths, but a solution uses only one"
+                .to_string(),
+        ));
+    }
+
+    pinned
+}
+
+/// Check that defects lie within their stock pieces, and that the regions
+/// around them and around pinned cut pieces can be told apart from other
+/// stock pieces in solutions
+fn check_defects(
+    errors: &mut Vec<ValidationError>,
+    input: &OptimizerInput,
+    pinned: &[(usize, ResultCutPiece)],
+) {
+    let pins = |i: usize| -> Vec<ResultCutPiece> {
+        pinned
+            .iter()
+            .filter(|(j, _)| *j == i)
+            .map(|(_, cut_piece)| cut_piece.clone())
+            .collect()
+    };
+    let defective: Vec<_> = input
+        .stock_pieces
+        .iter()
+        .enumerate()
+        .filter(|(i, sp)| !sp.defects.is_empty() || !pins(*i).is_empty())
+        .collect();
+    if defective.is_empty() {
+        return;
+    }
+
+    if input.allow_mixed_stock_sizes == Some(false) {
+        errors.push(ValidationError::new(
+            "defectsNeedMixedSizes",
+            "allowMixedStockSizes".to_string(),
+            "Stock pieces with defects or pins are cut into regions of different sizes, so allowMixedStockSizes can't be false"
+                .to_string(),
+        ));
+    }
+
+    let key = |sp: &StockPiece| (sp.width, sp.length, sp.pattern_direction);
+    let mut region_sizes: Vec<(usize, HashSet<_>)> = Vec::new();
+    for (i, stock_piece) in &defective {
+        let path = format!("stockPieces[{}].defects", i);
+        if stock_piece.defects.len() > MAX_DEFECTS {
+            errors.push(ValidationError::new(
+                "tooManyDefects",
+                path,
+                format!("A stock piece can have at most {} defects", MAX_DEFECTS),
+            ));
+            continue;
+        }
+
+        let mut valid = true;
+        for (j, defect) in stock_piece.defects.iter().enumerate() {
+            if !defect::within(defect, stock_piece) {
+                valid = false;
+                errors.push(ValidationError::new(
+                    "invalidDefect",
+                    format!("{}[{}]", path, j),
+                    "Defects need a width and length, and have to lie within the stock piece"
+                        .to_string(),
+                ));
+            }
+        }
+        if !valid {
+            continue;
+        }
+
+        let cut_width = stock_piece.cut_width.unwrap_or(input.cut_width());
+        let mut regions = Vec::new();
+        if !stock_piece.defects.is_empty() {
+            regions = defect::regions(stock_piece, &[], cut_width);
+            if regions.is_empty() {
+                errors.push(ValidationError::new(
+                    "invalidDefect",
+                    path,
+                    "Defects leave no usable area".to_string(),
+                ));
+                continue;
+            }
+        }
+        // A sheet may be filled by its pins, leaving no regions
+        let pins = pins(*i);
+        if !pins.is_empty() {
+            regions.extend(defect::regions(stock_piece, &pins, cut_width));
+        }
+
+        let usable = stock_piece.usable();
+        let sizes: HashSet<_> = regions
+            .iter()
+            .map(|region| (region.width, region.length, usable.pattern_direction))
+            .collect();
+        region_sizes.push((*i, sizes));
+    }
+
+    // Solutions only give the size of each stock piece used, so regions have
+    // to be told apart from other stock pieces by their size. Regions of
+    // different stock pieces may be the same size, since either has room for
+    // what's placed on them.
+    for (i, sizes) in &region_sizes {
+        let stock_piece = &input.stock_pieces[*i];
+        let usable = key(&stock_piece.usable());
+        let ambiguous = input.stock_pieces.iter().any(|other| {
+            let other_usable = key(&other.usable());
+            let same_piece = other_usable == usable && other.defects == stock_piece.defects;
+            !same_piece && (other_usable == usable || sizes.contains(&other_usable))
+        });
+        if ambiguous {
+            errors.push(ValidationError::new(
+                "ambiguousDefects",
+                if stock_piece.defects.is_empty() {
+                    format!("stockPieces[{}]", i)
+                } else {
+                    format!("stockPieces[{}].defects", i)
+                },
+                "A region around the defects or pins has the same size as another stock piece"
+                    .to_string(),
+            ));
+        }
+    }
+}
+
+/// Check that the stock pieces that must be used could all appear in one
+/// solution. Every sheet holds at least one cut piece, and a solution uses a
+/// single cut width.
+fn check_must_use(
+    errors: &mut Vec<ValidationError>,
+    input: &OptimizerInput,
+    cut_pieces: &[CutPiece],
+    cut_piece_count: usize,
+) {
+    let must_use: Vec<_> = input
+        .stock_pieces
+        .iter()
+        .enumerate()
+        .filter(|(_, sp)| sp.must_use)
+        .collect();
+    if must_use.is_empty() {
+        return;
+    }
+
+    for (i, stock_piece) in &must_use {
+        let usable = stock_piece.usable();
+        if stock_piece.quantity == Some(0) {
+            errors.push(ValidationError::new(
+                "unsatisfiableMustUse",
+                format!("stockPieces[{}].quantity", i),
+                "A stock piece that must be used needs a quantity of at least 1".to_string(),
+            ));
+        } else if !cut_pieces.iter().any(|cp| fits(cp, &usable)) {
+            errors.push(ValidationError::new(
+                "unsatisfiableMustUse",
+                format!("stockPieces[{}].mustUse", i),
+                format!(
+                    "No cut piece fits in stock piece {}x{}",
+                    stock_piece.width, stock_piece.length
+                ),
+            ));
+        }
+    }
+
+    let required: usize = must_use
+        .iter()
+        .map(|(_, sp)| sp.quantity.unwrap_or(1))
+        .fold(0, usize::saturating_add);
+    if required > cut_piece_count {
+        errors.push(ValidationError::new(
+            "unsatisfiableMustUse",
+            "stockPieces".to_string(),
+            format!(
+                "{} stock pieces must be used, but there are only {} cut pieces to put on them",
+                required, cut_piece_count
+            ),
+        ));
+    }
+
+    let cut_widths: HashSet<_> = must_use
+        .iter()
+        .map(|(_, sp)| sp.cut_width.unwrap_or(input.cut_width()))
+        .collect();
+    if cut_widths.len() > 1 {
+        errors.push(ValidationError::new(
+            "unsatisfiableMustUse",
+            "stockPieces".to_string(),
+            "Stock pieces that must be used have different cut widths, but a solution uses only one"
+                .to_string(),
+        ));
+    }
+
+    let sizes: HashSet<_> = must_use
+        .iter()
+        .map(|(_, sp)| (sp.width, sp.length, sp.pattern_direction))
+        .collect();
+    if !input.allow_mixed_stock_sizes.unwrap_or(true) && sizes.len() > 1 {
+        errors.push(ValidationError::new(
+            "unsatisfiableMustUse",
+            "stockPieces".to_string(),
+            "Stock pieces that must be used have different sizes, but allowMixedStockSizes is false"
+                .to_string(),
+        ));
+    }
+}
+
+/// Whether `check_defects` rejects a stock piece for its defects, which then
+/// have no regions to work out
+fn bad_defects(stock_piece: &InputStockPiece) -> bool {
+    stock_piece.defects.len() > MAX_DEFECTS
+        || !stock_piece
+            .defects
+            .iter()
+            .all(|d| defect::within(d, stock_piece))
+}
+
+/// Indexes of the required cut pieces that don't fit in any stock piece.
+/// Optional ones that don't fit are just never placed, and stock pieces with
+/// bad defects are left to `check_defects`.
+pub(crate) fn non_fitting(input: &OptimizerInput, cut_pieces: &[CutPiece]) -> Vec<usize> {
+    let stock_pieces: Vec<_> = input
+        .stock_pieces
+        .iter()
+        .filter(|sp| !bad_defects(sp))
+        .collect();
+    if stock_pieces.is_empty() {
+        return Vec::new();
+    }
+
+    let usable: Vec<_> = stock_pieces
+        .into_iter()
+        .flat_map(|sp| defect::usable_regions(sp, sp.cut_width.unwrap_or(input.cut_width())))
+        .collect();
+    cut_pieces
+        .iter()
+        .enumerate()
+        .filter(|(i, cut_piece)| {
+            !input.cut_pieces[*i].optional && !usable.iter().any(|sp| fits(cut_piece, sp))
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Whether a cut piece fits in an empty stock piece, upright or rotated, with
+/// its pattern running the same way as the stock piece's. A piece that fills
+/// the stock piece needs no kerf.
+pub(super) fn fits(cut_piece: &CutPiece, stock_piece: &StockPiece) -> bool {
+    let upright = cut_piece.pattern_direction == stock_piece.pattern_direction
+        && cut_piece.width <= stock_piece.width
+        && cut_piece.length <= stock_piece.length;
+    let rotated = cut_piece.can_rotate
+        && rotated(cut_piece.pattern_direction) == stock_piece.pattern_direction
+        && cut_piece.length <= stock_piece.width
+        && cut_piece.width <= stock_piece.length;
+    upright || rotated
+}
+
+/// Changes to a cut piece that doesn't fit any stock piece that would make it
+/// fit: letting it rotate, or making it as little smaller as possible
+fn no_fit_fixes(index: usize, cut_piece: &CutPiece, stock_pieces: &[InputStockPiece]) -> Vec<Fix> {
+    let usable: Vec<_> = stock_pieces.iter().map(InputStockPiece::usable).collect();
+    let mut fixes = Vec::new();
+
+    let rotatable = CutPiece {
+        can_rotate: true,
+        ..cut_piece.clone()
+    };
+    if !cut_piece.can_rotate && usable.iter().any(|sp| fits(&rotatable, sp)) {
+        fixes.push(Fix::new(
+            "Enable rotation".to_string(),
+            vec![Change::new(format!("cutPieces[{}].canRotate", index), true)],
+        ));
+    }
+
+    // Largest size that fits some stock piece the way the cut piece may be
+    // placed, as the width and length of the cut piece
+    let mut best: Option<(usize, usize)> = None;
+    for stock_piece in &usable {
+        let upright = (cut_piece.pattern_direction == stock_piece.pattern_direction).then(|| {
+            (
+                cut_piece.width.min(stock_piece.width),
+                cut_piece.length.min(stock_piece.length),
+            )
+        });
+        let rotated = (cut_piece.can_rotate
+            && rotated(cut_piece.pattern_direction) == stock_piece.pattern_direction)
+            .then(|| {
+                (
+                    cut_piece.width.min(stock_piece.length),
+                    cut_piece.length.min(stock_piece.width),
+                )
+            });
+        for (width, length) in upright.into_iter().chain(rotated) {
+            let area = width as u128 * length as u128;
+            if area > 0 && best.is_none_or(|(w, l)| area > w as u128 * l as u128) {
+                best = Some((width, length));
+            }
+        }
+    }
+
+    if let Some((width, length)) = best {
+        let mut reductions = Vec::new();
+        let mut changes = Vec::new();
+        if width < cut_piece.width {
+            reductions.push(format!("the width by {}", cut_piece.width - width));
+            changes.push(Change::new(format!("cutPieces[{}].width", index), width));
+        }
+        if length < cut_piece.length {
+            reductions.push(format!("the length by {}", cut_piece.length - length));
+            changes.push(Change::new(format!("cutPieces[{}].length", index), length));
+        }
+        fixes.push(Fix::new(
+            format!("Reduce {}", reductions.join(" and ")),
+            changes,
+        ));
+    }
+
+    fixes
+}
+
+/// Stock size that would fit a cut piece, preferring the smallest change to an
+/// existing stock piece
+fn suggest(cut_piece: &CutPiece, stock_pieces: &[InputStockPiece]) -> StockSuggestion {
+    let mut closest: Option<(u128, StockSuggestion)> = None;
+    for (index, stock_piece) in stock_pieces.iter().enumerate() {
+        let upright = (cut_piece.pattern_direction == stock_piece.pattern_direction)
+            .then_some((cut_piece.width, cut_piece.length));
+        let rotated = (cut_piece.can_rotate
+            && rotated(cut_piece.pattern_direction) == stock_piece.pattern_direction)
+            .then_some((cut_piece.length, cut_piece.width));
+
+        for (width, length) in upright.into_iter().chain(rotated) {
+            let grown = ClosestStockPiece {
+                index,
+                width: stock_piece.width.max(
+                    width
+                        .saturating_add(stock_piece.trim_left)
+                        .saturating_add(stock_piece.trim_right),
+                ),
+                length: stock_piece.length.max(
+                    length
+                        .saturating_add(stock_piece.trim_top)
+                        .saturating_add(stock_piece.trim_bottom),
+                ),
+            };
+            let growth = (grown.width as u128 * grown.length as u128)
+                - (stock_piece.width as u128 * stock_piece.length as u128);
+            if closest.as_ref().is_none_or(|(best, _)| growth < *best) {
+                closest = Some((
+                    growth,
+                    StockSuggestion {
+                        width,
+                        length,
+                        pattern_direction: stock_piece.pattern_direction,
+                        closest_stock_piece: Some(grown),
+                    },
+                ));
+            }
+        }
+    }
+
+    closest
+        .map(|(_, suggestion)| suggestion)
+        .unwrap_or(StockSuggestion {
+            width: cut_piece.width,
+            length: cut_piece.length,
+            pattern_direction: cut_piece.pattern_direction,
+            closest_stock_piece: None,
+        })
+}
+
+/// Pattern direction of a cut piece after rotating it
+pub(super) fn rotated(pattern_direction: PatternDirection) -> PatternDirection {
+    match pattern_direction {
+        PatternDirection::None => PatternDirection::None,
+        PatternDirection::ParallelToWidth => PatternDirection::ParallelToLength,
+        PatternDirection::ParallelToLength => PatternDirection::ParallelToWidth,
+    }
+}
+
+/// Size and trim margins of a stock piece
+fn trim(stock_piece: &InputStockPiece) -> [usize; 6] {
+    [
+        stock_piece.width,
+        stock_piece.length,
+        stock_piece.trim_top,
+        stock_piece.trim_bottom,
+        stock_piece.trim_left,
+        stock_piece.trim_right,
+    ]
+}
+
+fn check_dimensions(
+    errors: &mut Vec<ValidationError>,
+    path: &str,
+    width: usize,
+    length: usize,
+    max_dimension: Option<usize>,
+) {
+    for (field, value) in [("width", width), ("length", length)] {
+        if value == 0 {
+            errors.push(ValidationError::new(
+                "zeroDimension",
+                format!("{}.{}", path, field),
+                format!("{} must be greater than zero", field),
+            ));
+        }
+        if let Some(max_dimension) = max_dimension.filter(|&max| value > max) {
+            errors.push(ValidationError::new(
+                "dimensionTooLarge",
+                format!("{}.{}", path, field),
+                format!("{} must be at most {}", field, max_dimension),
+            ));
+        }
+    }
+}
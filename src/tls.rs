@@ -0,0 +1,138 @@
+use axum::Router;
+use hyper::server::conn::Http;
+use hyper::Body;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::{
+    self, Certificate, PrivateKey, ServerConfig, SupportedCipherSuite, SupportedProtocolVersion,
+};
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, error};
+
+use crate::Opt;
+
+#[cfg(test)]
+mod tests;
+
+/// Build the TLS configuration from the certificate, key, and policy options
+pub(crate) fn server_config(opt: &Opt, cert: &str, key: &str) -> Result<ServerConfig, String> {
+    let cipher_suites = cipher_suites(&opt.tls_cipher_suites)?;
+    let protocol_versions = protocol_versions(&opt.tls_min_version)?;
+
+    let mut config = ServerConfig::builder()
+        .with_cipher_suites(&cipher_suites)
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(protocol_versions)
+        .map_err(|e| format!("Invalid TLS policy: {}", e))?
+        .with_no_client_auth()
+        .with_single_cert(load_certs(cert)?, load_key(key)?)
+        .map_err(|e| format!("Invalid TLS certificate or key: {}", e))?;
+
+    config.alpn_protocols = opt
+        .tls_alpn
+        .iter()
+        .map(|protocol| protocol.as_bytes().to_vec())
+        .collect();
+
+    Ok(config)
+}
+
+/// Cipher suites allowed by name (e.g. `TLS13_AES_256_GCM_SHA384`), or all
+/// supported suites if no names are given
+fn cipher_suites(names: &[String]) -> Result<Vec<SupportedCipherSuite>, String> {
+    if names.is_empty() {
+        return Ok(rustls::ALL_CIPHER_SUITES.to_vec());
+    }
+
+    names
+        .iter()
+        .map(|name| {
+            rustls::ALL_CIPHER_SUITES
+                .iter()
+                .find(|suite| format!("{:?}", suite.suite()).eq_ignore_ascii_case(name))
+                .copied()
+                .ok_or_else(|| format!("Unsupported TLS cipher suite: {}", name))
+        })
+        .collect()
+}
+
+static TLS13_ONLY: &[&SupportedProtocolVersion] = &[&rustls::version::TLS13];
+
+/// Protocol versions at or above the minimum version
+fn protocol_versions(
+    min_version: &str,
+) -> Result<&'static [&'static SupportedProtocolVersion], String> {
+    match min_version {
+        "1.2" => Ok(rustls::ALL_VERSIONS),
+        "1.3" => Ok(TLS13_ONLY),
+        _ => Err(format!("Unsupported minimum TLS version: {}", min_version)),
+    }
+}
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>, String> {
+    let file = File::open(path).map_err(|e| format!("Couldn't open {}: {}", path, e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .map_err(|e| format!("Couldn't read certificates from {}: {}", path, e))?;
+
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", path));
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn load_key(path: &str) -> Result<PrivateKey, String> {
+    let file = File::open(path).map_err(|e| format!("Couldn't open {}: {}", path, e))?;
+    let items = rustls_pemfile::read_all(&mut BufReader::new(file))
+        .map_err(|e| format!("Couldn't read private key from {}: {}", path, e))?;
+
+    items
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| format!("No private key found in {}", path))
+}
+
+/// Run optimizer server over TLS. Connections that haven't finished the
+/// handshake within `handshake_timeout` are dropped, since the HTTP timeout
+/// only starts once there's a request.
+pub(crate) async fn serve(
+    listener: std::net::TcpListener,
+    config: Arc<ServerConfig>,
+    handshake_timeout: Duration,
+    app: Router<Body>,
+) {
+    let acceptor = TlsAcceptor::from(config);
+    let listener = TcpListener::from_std(listener).unwrap();
+
+    loop {
+        let (stream, peer_addr) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("Error accepting connection: {}", e);
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(handshake_timeout, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => {
+                    if let Err(e) = Http::new().serve_connection(stream, app).await {
+                        debug!("Error serving connection from {}: {}", peer_addr, e);
+                    }
+                }
+                Ok(Err(e)) => debug!("TLS handshake with {} failed: {}", peer_addr, e),
+                Err(_) => debug!("TLS handshake with {} timed out", peer_addr),
+            }
+        });
+    }
+}
@@ -0,0 +1,54 @@
+use super::*;
+use tokio::io::AsyncReadExt;
+
+#[test]
+fn cipher_suites_should_default_to_all_supported() {
+    assert_eq!(
+        cipher_suites(&[]).unwrap().len(),
+        rustls::ALL_CIPHER_SUITES.len()
+    );
+}
+
+#[test]
+fn cipher_suites_should_match_names_case_insensitively() {
+    let suites = cipher_suites(&["tls13_aes_256_gcm_sha384".to_string()]).unwrap();
+
+    assert_eq!(suites, vec![rustls::cipher_suite::TLS13_AES_256_GCM_SHA384]);
+}
+
+#[test]
+fn unknown_cipher_suite_should_be_rejected() {
+    assert!(cipher_suites(&["TLS_RSA_WITH_RC4_128_MD5".to_string()]).is_err());
+}
+
+#[test]
+fn min_version_should_exclude_older_protocols() {
+    assert_eq!(protocol_versions("1.3").unwrap().len(), 1);
+    assert_eq!(protocol_versions("1.2").unwrap().len(), 2);
+    assert!(protocol_versions("1.1").is_err());
+}
+
+#[tokio::test]
+async fn stalled_handshakes_should_be_dropped() {
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(rustls::server::ResolvesServerCertUsingSni::new()));
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve(
+        listener,
+        Arc::new(config),
+        Duration::from_millis(50),
+        Router::new(),
+    ));
+
+    // Connect without ever sending a ClientHello
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let mut buf = [0; 1];
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf))
+        .await
+        .expect("connection should be dropped");
+    assert!(matches!(read, Ok(0) | Err(_)));
+}
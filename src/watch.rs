@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{error, info};
+
+use crate::cli;
+use crate::server::OptimizerConfig;
+use crate::Opt;
+
+#[cfg(test)]
+mod tests;
+
+/// Suffix of the files solutions are written to
+const RESULT_SUFFIX: &str = ".result.json";
+
+/// Optimize `*.json` inputs dropped in a directory, writing each solution, or
+/// the error body the HTTP API would respond with, next to the input as
+/// `*.result.json`.
+///
+/// The directory is polled, since change notifications often don't work on
+/// network shares.
+pub(crate) async fn serve(dir: &Path, opt: &Opt) {
+    info!("Watching {} for optimizer inputs", dir.display());
+
+    let mut watcher = Watcher::new(dir, OptimizerConfig::from(opt));
+    let mut interval = tokio::time::interval(Duration::from_secs(opt.watch_interval.max(1)));
+    loop {
+        interval.tick().await;
+        if let Err(e) = watcher.poll().await {
+            error!("Couldn't read {}: {}", dir.display(), e);
+        }
+    }
+}
+
+struct Watcher {
+    dir: PathBuf,
+    config: OptimizerConfig,
+
+    /// Size and modification time of inputs waiting to be optimized, as of the
+    /// last poll
+    pending: HashMap<PathBuf, (u64, SystemTime)>,
+}
+
+impl Watcher {
+    fn new(dir: &Path, config: OptimizerConfig) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            config,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Optimize inputs without an up to date result. An input is only read
+    /// once its size and modification time haven't changed since the last
+    /// poll, so inputs still being copied aren't read.
+    async fn poll(&mut self) -> io::Result<()> {
+        let mut pending = HashMap::new();
+
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let result_path = match result_path(&path) {
+                Some(result_path) => result_path,
+                None => continue,
+            };
+
+            let metadata = entry.metadata().await?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let modified = metadata.modified()?;
+            let is_up_to_date = tokio::fs::metadata(&result_path)
+                .await
+                .and_then(|result| result.modified())
+                .is_ok_and(|result_modified| result_modified >= modified);
+            if is_up_to_date {
+                continue;
+            }
+
+            let state = (metadata.len(), modified);
+            if self.pending.get(&path) == Some(&state) {
+                self.optimize(&path, &result_path).await;
+            } else {
+                pending.insert(path, state);
+            }
+        }
+
+        self.pending = pending;
+        Ok(())
+    }
+
+    async fn optimize(&self, path: &Path, result_path: &Path) {
+        info!("Optimizing {}", path.display());
+
+        let written = async {
+            let input = tokio::fs::read(path).await?;
+            let json = cli::optimize_json(&self.config, &input).await?;
+
+            // Write to a temporary file first so the result never appears
+            // partially written
+            let temp_path = result_path.with_extension("json.tmp");
+            tokio::fs::write(&temp_path, json).await?;
+            tokio::fs::rename(&temp_path, result_path).await
+        };
+
+        if let Err(e) = written.await {
+            error!("Couldn't write {}: {}", result_path.display(), e);
+        }
+    }
+}
+
+/// Path of the result for an input, or `None` if `path` isn't an input
+fn result_path(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    if name.ends_with(RESULT_SUFFIX) {
+        return None;
+    }
+    let stem = name.strip_suffix(".json")?;
+    Some(path.with_file_name(format!("{}{}", stem, RESULT_SUFFIX)))
+}
@@ -0,0 +1,49 @@
+use super::*;
+use serde_json::Value;
+use structopt::StructOpt;
+
+#[test]
+fn result_path_should_only_match_inputs() {
+    assert_eq!(
+        result_path(Path::new("jobs/cabinet.json")),
+        Some(PathBuf::from("jobs/cabinet.result.json"))
+    );
+    assert_eq!(result_path(Path::new("jobs/cabinet.result.json")), None);
+    assert_eq!(result_path(Path::new("jobs/cabinet.result.json.tmp")), None);
+    assert_eq!(result_path(Path::new("jobs/notes.txt")), None);
+}
+
+#[tokio::test]
+async fn poll_should_optimize_inputs_once_they_stop_changing() {
+    let dir = std::env::temp_dir().join(format!("cut-optimizer-watch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("cabinet.json"),
+        r#"{
+            "method": "guillotine",
+            "cutWidth": 1,
+            "stockPieces": [{ "width": 48, "length": 96, "patternDirection": "none", "price": 0 }],
+            "cutPieces": [
+                { "externalId": 1, "width": 10, "length": 30, "patternDirection": "none", "canRotate": true }
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    let config = OptimizerConfig::from(&Opt::from_iter(&["cut-optimizer-2d-server"]));
+    let mut watcher = Watcher::new(&dir, config);
+    let result = dir.join("cabinet.result.json");
+
+    watcher.poll().await.unwrap();
+    assert!(!result.exists());
+
+    watcher.poll().await.unwrap();
+    let solution: Value = serde_json::from_slice(&std::fs::read(&result).unwrap()).unwrap();
+    assert_eq!(solution["stockPieces"][0]["cutPieces"][0]["externalId"], 1);
+
+    // The result is up to date, so the input isn't pending anymore
+    watcher.poll().await.unwrap();
+    assert!(watcher.pending.is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}